@@ -24,9 +24,7 @@ impl AssetLoader {
 
         for menu in game.menus.values() {
             if !menu.properties.BackgroundImage.is_empty() {
-                let path = assets
-                    .join("sprites")
-                    .join(&menu.properties.BackgroundImage.replace("\\", "/"));
+                let path = game.sprite_path(assets, &menu.properties.BackgroundImage);
 
                 if path.exists() {
                     textures.insert(
@@ -36,11 +34,9 @@ impl AssetLoader {
                 }
             }
 
-            for element in &menu.elements {
+            for element in menu.elements.iter() {
                 if !element.sprite.is_empty() {
-                    let path = assets
-                        .join("sprites")
-                        .join(&element.sprite.replace("\\", "/"));
+                    let path = game.sprite_path(assets, &element.sprite);
 
                     if path.exists() {
                         textures.insert(element.sprite.clone(), Texture::new(ctx, path.to_str().unwrap())?);
@@ -56,6 +52,14 @@ impl AssetLoader {
             }
         }
 
+        // The generated slot picker isn't in game.json, make sure its font is around
+        if !fonts.contains_key(&SLOT_PICKER_FONT_SIZE) {
+            fonts.insert(
+                SLOT_PICKER_FONT_SIZE,
+                Font::vector(ctx, "./src/Arial.ttf", SLOT_PICKER_FONT_SIZE as f32)?,
+            );
+        }
+
         logger.log("AssetLoader", "Loaded Menus");
         Ok(AssetLoader {
             animations: HashMap::new(),
@@ -76,9 +80,7 @@ impl AssetLoader {
         for office in game.offices.values() {
             for state in office.states.values() {
                 if !state.is_empty() {
-                    let path = assets
-                        .join("sprites")
-                        .join(&state.replace("\\", "/"));
+                    let path = game.sprite_path(assets, &state);
 
                     if path.exists() {
                         textures.insert(state.clone(), Texture::new(ctx, path.to_str().unwrap())?);
@@ -87,9 +89,7 @@ impl AssetLoader {
             }
 
             if !&office.uibuttons.camera.image.is_empty() {
-                let path = assets
-                    .join("sprites")
-                    .join(&office.uibuttons.camera.image.replace("\\", "/"));
+                let path = game.sprite_path(assets, &office.uibuttons.camera.image);
 
                 if path.exists() && !textures.contains_key(&office.uibuttons.camera.image) {
                     textures.insert(office.uibuttons.camera.image.clone(), Texture::new(ctx, path.to_str().unwrap())?);
@@ -97,9 +97,7 @@ impl AssetLoader {
             }
 
             if !&office.uibuttons.mask.image.is_empty() {
-                let path = assets
-                    .join("sprites")
-                    .join(&office.uibuttons.mask.image.replace("\\", "/"));
+                let path = game.sprite_path(assets, &office.uibuttons.mask.image);
 
                 if path.exists() && !textures.contains_key(&office.uibuttons.mask.image) {
                     textures.insert(office.uibuttons.mask.image.clone(), Texture::new(ctx, path.to_str().unwrap())?);
@@ -108,9 +106,7 @@ impl AssetLoader {
 
             for obj in &office.objects {
                 if !obj.sprite.is_empty() {
-                    let path = assets
-                        .join("sprites")
-                        .join(&obj.sprite.replace("\\", "/"));
+                    let path = game.sprite_path(assets, &obj.sprite);
 
                     if path.exists() {
                         textures.insert(obj.sprite.clone(), Texture::new(ctx, path.to_str().unwrap())?);
@@ -118,9 +114,7 @@ impl AssetLoader {
                 }
 
                 if !obj.on_sprite.is_empty() {
-                    let path = assets
-                        .join("sprites")
-                        .join(&obj.on_sprite.replace("\\", "/"));
+                    let path = game.sprite_path(assets, &obj.on_sprite);
 
                     if path.exists() {
                         textures.insert(obj.on_sprite.clone(), Texture::new(ctx, path.to_str().unwrap())?);
@@ -154,7 +148,7 @@ impl AssetLoader {
             let mut durations = Vec::with_capacity(anim_json.len());
 
             for json in anim_json {
-                let sprite_path = assets.join("sprites").join(&json.sprite.replace("\\", "/"));
+                let sprite_path = game.sprite_path(assets, &json.sprite);
                 let tex = Texture::new(ctx, &sprite_path).unwrap();
                 durations.push(Duration::from_secs_f64(json.duration as f64 * 0.04));
                 textures.push(tex);
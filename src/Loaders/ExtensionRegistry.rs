@@ -0,0 +1,185 @@
+use crate::GameLoader::OfficeObject;
+
+// Sets up an office object's runtime state when its office loads
+type OfficeObjectInit = Rc<dyn Fn(&mut OfficeData, &OfficeObject)>;
+// Draws one office object, the position already has the scroll applied
+type OfficeObjectDraw = Rc<dyn Fn(&mut Context, &mut EngineData, &mut CacheData, &OfficeObject, Vec2<f32>) -> tetra::Result>;
+type MenuElementDraw = Rc<dyn Fn(&mut Context, &mut EngineData, &mut CacheData, &Element, Vec2<f32>) -> tetra::Result>;
+
+// Built in extensions by the name game.json uses for them. "core" is always loaded, whether it's listed or not.
+const BUILTIN_EXTENSIONS: &[(&str, fn(&mut ExtensionRegistry))] = &[
+    ("core", ExtensionRegistry::register_core),
+];
+
+// The event clicking an object fires. Sprite ones are sized by their sprite, others by their text,
+// office objects have no text so theirs always go by the sprite.
+#[derive(Clone)]
+pub struct ClickArea {
+    pub event: String,
+    pub sprite: bool,
+}
+
+#[derive(Clone)]
+pub struct OfficeObjectType {
+    pub init: OfficeObjectInit,
+    pub click: Option<ClickArea>,
+    pub draw: OfficeObjectDraw,
+}
+
+#[derive(Clone)]
+pub struct MenuElementType {
+    pub click: Option<ClickArea>,
+    pub draw: MenuElementDraw,
+}
+
+// Every office object type, menu element type and extra code block the game can use, filled in by
+// the extensions game.json lists in loaded_extensions
+pub struct ExtensionRegistry {
+    office_objects: HashMap<String, OfficeObjectType>,
+    menu_elements: HashMap<String, MenuElementType>,
+    code_blocks: Vec<(String, CodeBlock)>,
+    // Ones that aren't built in, a plugin with the name has to provide them
+    needs_plugins: Vec<String>,
+    pub loaded: Vec<String>,
+}
+
+impl ExtensionRegistry {
+    pub fn new() -> ExtensionRegistry {
+        ExtensionRegistry {
+            office_objects: HashMap::new(),
+            menu_elements: HashMap::new(),
+            code_blocks: Vec::new(),
+            needs_plugins: Vec::new(),
+            loaded: Vec::new(),
+        }
+    }
+
+    // Built in ones register straight away, the rest are checked against plugins once they're loaded
+    pub fn load(extensions: &[String], logger: &Logger) -> ExtensionRegistry {
+        let mut registry = ExtensionRegistry::new();
+        registry.load_builtin("core");
+        for name in extensions {
+            if registry.loaded.contains(name) {
+                continue;
+            }
+            if !registry.load_builtin(name) {
+                registry.needs_plugins.push(name.clone());
+            }
+        }
+        logger.log("Extensions", format!("Built in extensions: {}", registry.loaded.join(", ")).as_str());
+        registry
+    }
+
+    fn load_builtin(&mut self, name: &str) -> bool {
+        match BUILTIN_EXTENSIONS.iter().find(|(builtin, _)| builtin.eq_ignore_ascii_case(name)) {
+            Some((builtin, register)) => {
+                register(self);
+                self.loaded.push(builtin.to_string());
+                true
+            }
+            None => false,
+        }
+    }
+
+    // False if the game needs an extension nothing provides
+    pub fn check_plugins(&mut self, plugins: &[String], logger: &Logger) -> bool {
+        let mut found_all = true;
+        for name in std::mem::take(&mut self.needs_plugins) {
+            if plugins.iter().any(|plugin| plugin.eq_ignore_ascii_case(&name)) {
+                self.loaded.push(name);
+            } else {
+                logger.log_error("Extensions", format!(
+                    "game.json needs the extension '{}', which isn't built in and isn't an installed plugin. Built in ones are: {}.",
+                    name, BUILTIN_EXTENSIONS.iter().map(|(builtin, _)| *builtin).collect::<Vec<_>>().join(", ")
+                ).as_str());
+                found_all = false;
+            }
+        }
+        found_all
+    }
+
+    // Headless runs don't load plugins, so there's nothing to check plugin extensions against
+    pub fn skip_plugins(&mut self, logger: &Logger) {
+        for name in std::mem::take(&mut self.needs_plugins) {
+            logger.log_warn("Extensions", format!("Extension '{}' comes from a plugin, plugins don't load headless.", name).as_str());
+        }
+    }
+
+    pub fn register_office_object(&mut self, name: &str, object_type: OfficeObjectType) {
+        self.office_objects.insert(name.to_string(), object_type);
+    }
+
+    pub fn register_menu_element(&mut self, name: &str, element_type: MenuElementType) {
+        self.menu_elements.insert(name.to_string(), element_type);
+    }
+
+    // Handed to the event manager when the scripts start
+    pub fn register_code_block<F>(&mut self, name: &str, func: F)
+    where
+        F: Fn(&mut EngineData, &mut EventManager, &[Value]) -> bool + 'static,
+    {
+        self.code_blocks.push((name.to_string(), Rc::new(func)));
+    }
+
+    pub fn office_object(&self, name: &str) -> Option<OfficeObjectType> {
+        self.office_objects.get(name).cloned()
+    }
+
+    pub fn menu_element(&self, name: &str) -> Option<MenuElementType> {
+        self.menu_elements.get(name).cloned()
+    }
+
+    pub fn install_code_blocks(&self, event_manager: &mut EventManager) {
+        for (name, block) in &self.code_blocks {
+            let block = block.clone();
+            event_manager.register_code_block(name, move |engine, events, args| block(engine, events, args));
+        }
+    }
+
+    // The object and element types the engine has always had
+    fn register_core(&mut self) {
+        let clickable_sprite = Some(ClickArea { event: "on_sprite_clicked".to_string(), sprite: true });
+        self.register_office_object("sprite", OfficeObjectType {
+            init: Rc::new(OfficeManager::init_sprite),
+            click: clickable_sprite.clone(),
+            draw: Rc::new(OfficeRenderer::draw_sprite),
+        });
+        self.register_office_object("door_button", OfficeObjectType {
+            init: Rc::new(OfficeManager::init_object),
+            click: clickable_sprite.clone(),
+            draw: Rc::new(OfficeRenderer::draw_door_button),
+        });
+        self.register_office_object("light_button", OfficeObjectType {
+            init: Rc::new(OfficeManager::init_light_button),
+            click: clickable_sprite,
+            draw: Rc::new(OfficeRenderer::draw_light_button),
+        });
+        self.register_office_object("animation", OfficeObjectType {
+            init: Rc::new(OfficeManager::init_animation),
+            click: None,
+            draw: Rc::new(OfficeRenderer::draw_animation),
+        });
+        self.register_office_object("door", OfficeObjectType {
+            init: Rc::new(OfficeManager::init_door),
+            click: None,
+            draw: Rc::new(OfficeRenderer::draw_door),
+        });
+
+        self.register_menu_element("Button", MenuElementType {
+            click: Some(ClickArea { event: "button_clicked".to_string(), sprite: false }),
+            draw: Rc::new(MenuRenderer::draw_button),
+        });
+        self.register_menu_element("StaticText", MenuElementType {
+            click: None,
+            draw: Rc::new(MenuRenderer::draw_static_text),
+        });
+        self.register_menu_element("Image", MenuElementType {
+            click: Some(ClickArea { event: "image_clicked".to_string(), sprite: true }),
+            draw: Rc::new(MenuRenderer::draw_image),
+        });
+        self.register_menu_element("Animation", MenuElementType {
+            click: None,
+            draw: Rc::new(MenuRenderer::draw_animation),
+        });
+    }
+}
@@ -16,6 +16,7 @@ pub mod GameLoader {
     use std::str::FromStr;
     use serde_json::Map;
     use std::fmt::Display;
+    use std::rc::Rc;
 
     #[derive(Debug, Serialize, Deserialize, Clone)]
     pub struct Game {
@@ -24,12 +25,39 @@ pub mod GameLoader {
         pub(crate) game_info: GameInfo,
         pub(crate) menus: HashMap<String, Menu>,
         pub(crate) offices: HashMap<String, Office>,
+        #[serde(default)]
+        pub(crate) minigames: HashMap<String, Minigame>,
         pub(crate) sounds: Sounds,
         pub(crate) loaded_extensions: Vec<String>,
+        // Named input actions mapped to key names, e.g. "toggle_camera": ["s", "space"]
+        #[serde(default)]
+        pub(crate) actions: HashMap<String, Vec<String>>,
         #[serde(skip_deserializing)]
         pub(crate) office_scripts: HashMap<String, Vec<Code>>,
+        // scripts/global, loaded once at startup and never unloaded
+        #[serde(skip_deserializing)]
+        pub(crate) global_scripts: HashMap<String, Vec<Code>>,
+        // scripts/nights/<night>, only loaded while that night is being played
+        #[serde(skip_deserializing)]
+        pub(crate) night_scripts: HashMap<i32, HashMap<String, Vec<Code>>>,
+        #[serde(skip_deserializing)]
+        pub(crate) lua_scripts: LuaScripts,
         #[serde(skip_deserializing)]
         pub(crate) animations: HashMap<String, Vec<AnimationJson>>,
+        // Sprite name to the mod file replacing it
+        #[serde(skip)]
+        pub(crate) sprite_overrides: HashMap<String, PathBuf>,
+    }
+
+    impl Game {
+        // Where a sprite named in game.json actually is, a mod's copy if one replaced it
+        pub fn sprite_path(&self, assets: &PathBuf, sprite: &str) -> PathBuf {
+            let sprite = sprite.replace("\\", "/");
+            match self.sprite_overrides.get(&sprite) {
+                Some(path) => path.clone(),
+                None => assets.join("sprites").join(sprite),
+            }
+        }
     }
 
     #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -77,16 +105,20 @@ pub mod GameLoader {
 
     #[derive(Debug, Serialize, Deserialize, Clone)]
     pub struct GameInfo {
-        id: String,
-        title: String,
+        pub(crate) id: String,
+        pub(crate) title: String,
         style: i32,
+        // The menu shown over the office when the player pauses, "Pause" if there's a menu with that name
+        #[serde(default)]
+        pub(crate) pause_menu: String,
     }
 
     #[derive(Debug, Serialize, Deserialize, Clone)]
     pub struct Menu {
         pub(crate) code: Vec<Code>,
+        // Shared so drawing can hold on to them while the element types get the whole engine
         #[serde(deserialize_with = "ignore_if_map")]
-        pub(crate) elements: Vec<Element>,
+        pub(crate) elements: Rc<Vec<Element>>,
         pub(crate) properties: Properties,
     }
 
@@ -102,7 +134,7 @@ pub mod GameLoader {
     }
 
 
-    fn ignore_if_map<'de, D>(deserializer: D) -> Result<Vec<Element>, D::Error>
+    fn ignore_if_map<'de, D>(deserializer: D) -> Result<Rc<Vec<Element>>, D::Error>
         where
             D: serde::Deserializer<'de>,
     {
@@ -111,7 +143,7 @@ pub mod GameLoader {
         match &value {
             Value::Array(_array) => {
                 let elements: Result<Vec<Element>, _> = serde_json::from_value(value.clone());
-                elements.map_err(|e| D::Error::custom(format!("Failed to deserialize elements: {}", e)))
+                elements.map(Rc::new).map_err(|e| D::Error::custom(format!("Failed to deserialize elements: {}", e)))
             }
             Value::Object(_hashmap) => {
                 let parse: HashMap<String, Value> = serde_json::from_value(value.clone()).unwrap();
@@ -128,7 +160,7 @@ pub mod GameLoader {
                         Err(err) => println!("INVALID ELEMENT: {}", err),
                     }
                 }
-                Ok(elements_vec)
+                Ok(Rc::new(elements_vec))
             }
             _ => Err(D::Error::custom("Unexpected JSON type for Vec<Element>")),
         }
@@ -142,6 +174,9 @@ pub mod GameLoader {
         pub block: String,
         #[serde(default)]
         pub subcode: Vec<Code>,
+        // Only read on top level events, higher runs first
+        #[serde(default)]
+        pub priority: i32,
     }
 
     #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -201,14 +236,115 @@ pub mod GameLoader {
         ButtonArrowFont: String,
     }
 
+    // A top down 8-bit style minigame. The map is rows of characters, each one a tile from tiles, and
+    // everything is placed in unscaled pixels. The player walks with the move_up/down/left/right actions.
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct Minigame {
+        #[serde(default)]
+        pub code: Vec<Code>,
+        #[serde(default = "default_tile_size")]
+        pub tile_size: i32,
+        // Pixel art is drawn this many times bigger
+        #[serde(default = "default_minigame_scale")]
+        pub scale: f32,
+        #[serde(default)]
+        pub tiles: HashMap<String, MinigameTile>,
+        #[serde(default)]
+        pub map: Vec<String>,
+        #[serde(default)]
+        pub sprites: Vec<MinigameSprite>,
+        pub player: MinigamePlayer,
+        #[serde(default)]
+        pub triggers: Vec<MinigameTrigger>,
+        // "r,g,b" like the menu arrow colors
+        #[serde(default)]
+        pub background_color: String,
+        #[serde(default)]
+        pub music: String,
+    }
+
+    fn default_tile_size() -> i32 {
+        16
+    }
+
+    fn default_minigame_scale() -> f32 {
+        3.0
+    }
+
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct MinigameTile {
+        #[serde(default)]
+        pub sprite: String,
+        #[serde(default)]
+        pub solid: bool,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct MinigameSprite {
+        pub id: String,
+        pub sprite: String,
+        pub x: f32,
+        pub y: f32,
+        #[serde(default)]
+        pub solid: bool,
+        // The collision box when it's solid, a tile if left out
+        #[serde(default)]
+        pub width: Option<f32>,
+        #[serde(default)]
+        pub height: Option<f32>,
+        #[serde(default)]
+        pub hidden: bool,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct MinigamePlayer {
+        pub sprite: String,
+        pub x: f32,
+        pub y: f32,
+        // Pixels per tick
+        #[serde(default = "default_player_speed")]
+        pub speed: f32,
+        // The collision box, the sprite's drawn at the box's top left
+        #[serde(default = "default_player_size")]
+        pub width: f32,
+        #[serde(default = "default_player_size")]
+        pub height: f32,
+    }
+
+    fn default_player_speed() -> f32 {
+        1.0
+    }
+
+    fn default_player_size() -> f32 {
+        12.0
+    }
+
+    // Walking into one fires on_minigame_trigger <id>, once per time the player enters it
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct MinigameTrigger {
+        pub id: String,
+        pub x: f32,
+        pub y: f32,
+        pub width: f32,
+        pub height: f32,
+        // Only fires the first time
+        #[serde(default)]
+        pub once: bool,
+    }
+
     #[derive(Debug, Serialize, Deserialize, Clone)]
     pub struct Office {
         pub animations: Animations,
         pub flashlight: bool,
         pub mask: bool,
-        pub(crate) objects: Vec<OfficeObject>,
+        // Shared for the same reason as menu elements
+        pub(crate) objects: Rc<Vec<OfficeObject>>,
         pub panorama: bool,
         pub power: Power,
+        // The engine runs the clock (on_hour, on_night_end) and power drain for this office.
+        // Off unless game.json asks for it, games whose scripts keep their own time leave it off.
+        #[serde(default)]
+        pub night_clock: bool,
         pub(crate) states: HashMap<String, String>,
         pub toxic: bool,
         pub uibuttons: Uibuttons,
@@ -218,7 +354,7 @@ pub mod GameLoader {
     pub struct Animations {
         camera: String,
         mask: String,
-        powerout: String,
+        pub(crate) powerout: String,
     }
 
     #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -247,10 +383,10 @@ pub mod GameLoader {
 
     #[derive(Debug, Serialize, Deserialize, Clone)]
     pub struct Power {
-        animatronic: String,
-        enabled: bool,
-        starting_level: i32,
-        ucn: bool,
+        pub(crate) animatronic: String,
+        pub(crate) enabled: bool,
+        pub(crate) starting_level: i32,
+        pub(crate) ucn: bool,
     }
 
     #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -296,52 +432,175 @@ pub mod GameLoader {
         pub sprite: String,
     }
 
-    pub fn Load(input_json_path: &str) -> Game {
-        let mut file = std::fs::File::open(input_json_path).expect("Failed to open the JSON file");
-        let mut content = String::new();
-        file.read_to_string(&mut content)
-            .expect("Failed to read the JSON file");
+    // .lua files sit in the same folders as the .fescripts and load alongside them, menus and
+    // minigames get their own under scripts/menus/<menu> and scripts/minigames/<minigame>.
+    // Kept as source, they're compiled when their scope starts.
+    #[derive(Debug, Default, Serialize, Deserialize, Clone)]
+    pub struct LuaScripts {
+        pub(crate) office: HashMap<String, String>,
+        pub(crate) global: HashMap<String, String>,
+        pub(crate) nights: HashMap<i32, HashMap<String, String>>,
+        pub(crate) menus: HashMap<String, HashMap<String, String>>,
+        pub(crate) minigames: HashMap<String, HashMap<String, String>>,
+    }
 
-        let mut settings = serde_json::from_str::<Game>(&content)
-            .expect("Failed to deserialize JSON content");
+    // Every .lua directly inside the folder
+    pub fn load_lua_scripts(dir: &PathBuf) -> HashMap<String, String> {
+        let mut scripts = HashMap::new();
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return scripts,
+        };
+
+        for script in entries.flatten() {
+            let name = script.file_name().to_string_lossy().to_string();
+            if name.ends_with(".lua") {
+                match fs::read_to_string(script.path()) {
+                    Ok(source) => { scripts.insert(name, source); }
+                    Err(e) => println!("Failed to read Lua script {:?}: {}", script.path(), e),
+                }
+            }
+        }
+        scripts
+    }
 
+    // Every .fescript directly inside the folder, a missing folder just means no scripts
+    pub fn load_scripts(dir: &PathBuf) -> Result<HashMap<String, Vec<Code>>, String> {
         let mut scripts: HashMap<String, Vec<Code>> = HashMap::new();
-        let mut animations: HashMap<String, Vec<AnimationJson>> = HashMap::new();
-
-        for script in fs::read_dir(input_json_path.replace("game.json","scripts")).unwrap() {
-            let script = script.unwrap();
-            if script.file_name().to_str().unwrap().ends_with(".fescript")
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return Ok(scripts),
+        };
+
+        for script in entries.flatten() {
+            let name = script.file_name().to_string_lossy().to_string();
+            if name.ends_with(".fescript")
             {
-                let mut json = String::new();
-                fs::File::open(script.path())
-                    .expect("Failed to open the JSON file")
-                    .read_to_string(&mut json)
-                    .expect("Failed to read the JSON file");
-
-                scripts.insert(
-                    script.file_name().to_str().unwrap().to_string(),
-                    serde_json::from_str::<Vec<Code>>(&json).expect("Failed to deserialize JSON content"));
+                let json = fs::read_to_string(script.path())
+                    .map_err(|e| format!("Failed to read {:?}: {}", script.path(), e))?;
+                let code = serde_json::from_str::<Vec<Code>>(&json)
+                    .map_err(|e| format!("{:?} isn't a valid script: {}", script.path(), e))?;
+                scripts.insert(name, code);
             }
         }
-        settings.office_scripts = scripts;
+        Ok(scripts)
+    }
 
-        for script in fs::read_dir(input_json_path.replace("game.json","animations")).unwrap() {
-            let animation = script.unwrap();
-            if animation.file_name().to_str().unwrap().ends_with(".json")
-            {
-                let mut json = String::new();
-                fs::File::open(animation.path())
-                    .expect("Failed to open the JSON file")
-                    .read_to_string(&mut json)
-                    .expect("Failed to read the JSON file");
-
-                animations.insert(
-                    animation.file_name().to_str().unwrap().to_string(),
-                    serde_json::from_str(&json).expect("Failed to deserialize JSON content"));
+    pub fn Load(input_json_path: &str) -> Result<Game, String> {
+        LoadPatched(input_json_path, |_| {})
+    }
+
+    // Patch gets to change the raw game.json before it's read
+    pub fn LoadPatched<F>(input_json_path: &str, patch: F) -> Result<Game, String>
+    where
+        F: FnOnce(&mut Value),
+    {
+        let mut json = read_json(&PathBuf::from(input_json_path))?;
+        patch(&mut json);
+        let mut settings = from_json(json)?;
+        load_content(&mut settings, &PathBuf::from(input_json_path.replace("game.json", "")))?;
+        Ok(settings)
+    }
+
+    pub fn read_json(path: &PathBuf) -> Result<Value, String> {
+        let mut content = String::new();
+        fs::File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut content))
+            .map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+        serde_json::from_str::<Value>(&content).map_err(|e| format!("{:?} isn't valid JSON: {}", path, e))
+    }
+
+    // game.json without any of the scripts or animations, those come from load_content
+    pub fn from_json(json: Value) -> Result<Game, String> {
+        serde_json::from_value::<Game>(json).map_err(|e| format!("Not a valid game.json: {}", e))
+    }
+
+    // Scripts and animations of a game or mod folder. Read whole before any of it is used, so a
+    // broken file leaves none of the folder half applied.
+    #[derive(Default)]
+    pub struct Content {
+        office_scripts: HashMap<String, Vec<Code>>,
+        global_scripts: HashMap<String, Vec<Code>>,
+        night_scripts: HashMap<i32, HashMap<String, Vec<Code>>>,
+        lua_scripts: LuaScripts,
+        animations: HashMap<String, Vec<AnimationJson>>,
+    }
+
+    pub fn read_content(root: &PathBuf) -> Result<Content, String> {
+        let mut content = Content::default();
+        let scripts_dir = root.join("scripts");
+        content.office_scripts = load_scripts(&scripts_dir)?;
+        content.global_scripts = load_scripts(&scripts_dir.join("global"))?;
+        content.lua_scripts.office = load_lua_scripts(&scripts_dir);
+        content.lua_scripts.global = load_lua_scripts(&scripts_dir.join("global"));
+        if let Ok(menus) = fs::read_dir(scripts_dir.join("menus")) {
+            for menu in menus.flatten() {
+                let name = menu.file_name().to_string_lossy().to_string();
+                content.lua_scripts.menus.insert(name, load_lua_scripts(&menu.path()));
+            }
+        }
+
+        if let Ok(minigames) = fs::read_dir(scripts_dir.join("minigames")) {
+            for minigame in minigames.flatten() {
+                let name = minigame.file_name().to_string_lossy().to_string();
+                content.lua_scripts.minigames.insert(name, load_lua_scripts(&minigame.path()));
+            }
+        }
+
+        if let Ok(nights) = fs::read_dir(scripts_dir.join("nights")) {
+            for night in nights.flatten() {
+                match night.file_name().to_str().and_then(|name| name.parse::<i32>().ok()) {
+                    Some(number) => {
+                        content.night_scripts.insert(number, load_scripts(&night.path())?);
+                        content.lua_scripts.nights.insert(number, load_lua_scripts(&night.path()));
+                    }
+                    None => println!("Skipping night script folder {:?}, its name isn't a night number", night.file_name()),
+                }
+            }
+        }
+
+        if let Ok(animations) = fs::read_dir(root.join("animations")) {
+            for animation in animations.flatten() {
+                let name = animation.file_name().to_string_lossy().to_string();
+                if name.ends_with(".json")
+                {
+                    let json = fs::read_to_string(animation.path())
+                        .map_err(|e| format!("Failed to read {:?}: {}", animation.path(), e))?;
+                    let frames = serde_json::from_str(&json)
+                        .map_err(|e| format!("{:?} isn't a valid animation: {}", animation.path(), e))?;
+                    content.animations.insert(name, frames);
+                }
             }
         }
-        settings.animations = animations;
+        Ok(content)
+    }
+
+    // Files with the same name as ones already loaded replace them
+    pub fn apply_content(settings: &mut Game, content: Content) {
+        settings.office_scripts.extend(content.office_scripts);
+        settings.global_scripts.extend(content.global_scripts);
+        for (night, scripts) in content.night_scripts {
+            settings.night_scripts.entry(night).or_default().extend(scripts);
+        }
+        let lua = content.lua_scripts;
+        settings.lua_scripts.office.extend(lua.office);
+        settings.lua_scripts.global.extend(lua.global);
+        for (night, scripts) in lua.nights {
+            settings.lua_scripts.nights.entry(night).or_default().extend(scripts);
+        }
+        for (menu, scripts) in lua.menus {
+            settings.lua_scripts.menus.entry(menu).or_default().extend(scripts);
+        }
+        for (minigame, scripts) in lua.minigames {
+            settings.lua_scripts.minigames.entry(minigame).or_default().extend(scripts);
+        }
+        settings.animations.extend(content.animations);
+    }
 
-        settings
+    // Scripts and animations under root, replacing ones with the same file name. Called for the game
+    // folder and then every mod's.
+    pub fn load_content(settings: &mut Game, root: &PathBuf) -> Result<(), String> {
+        apply_content(settings, read_content(root)?);
+        Ok(())
     }
 }
\ No newline at end of file
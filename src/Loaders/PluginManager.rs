@@ -1,55 +1,546 @@
+use std::ffi::{c_char, c_void, CStr};
+use std::rc::Weak;
 use libloading::{Library, Symbol};
+use tetra::graphics::mesh::{Mesh, ShapeStyle};
 
-pub trait Plugin {
-    fn initialize(&mut self);
-    fn update(&mut self, ctx: &mut Context);
-    fn shutdown(&mut self);
+// Bumped whenever PluginVTable or PluginHost change shape. The plugin is told the engine's version and
+// hands back a table built for it, or null if it can't work with that version.
+pub const PLUGIN_API_VERSION: u32 = 3;
+// Every plugin exports: extern "C" fn fnaf_plugin_entry(host_api_version: u32) -> *const PluginVTable
+const PLUGIN_ENTRY_SYMBOL: &[u8] = b"fnaf_plugin_entry\0";
+// draw() gets called twice a frame, under and over the scene
+pub const PLUGIN_LAYER_BELOW: u32 = 0;
+pub const PLUGIN_LAYER_ABOVE: u32 = 1;
+// What kind of object draw_object is drawing, and register_object_type registers
+pub const PLUGIN_OBJECT_OFFICE: u32 = 0;
+pub const PLUGIN_OBJECT_MENU: u32 = 1;
+
+// Only C types cross the library boundary, so plugins can be built with any compiler version
+// (or language). The plugin keeps its own state behind the handle create() returns.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PluginVTable {
+    pub api_version: u32,
+    // Nul terminated, owned by the plugin and valid for as long as it's loaded
+    pub name: *const c_char,
+    // The host table stays valid until destroy, plugins keep the pointer
+    pub create: Option<unsafe extern "C" fn(host: *const PluginHost) -> *mut c_void>,
+    pub initialize: Option<unsafe extern "C" fn(handle: *mut c_void)>,
+    pub update: Option<unsafe extern "C" fn(handle: *mut c_void, delta_seconds: f64)>,
+    pub shutdown: Option<unsafe extern "C" fn(handle: *mut c_void)>,
+    pub destroy: Option<unsafe extern "C" fn(handle: *mut c_void)>,
+    // For events the plugin subscribed to
+    pub on_event: Option<unsafe extern "C" fn(handle: *mut c_void, event: *const c_char, args: *const *const c_char, arg_count: usize)>,
+    // For blocks the plugin registered, the args are already evaluated
+    pub run_block: Option<unsafe extern "C" fn(handle: *mut c_void, block: *const c_char, args: *const *const c_char, arg_count: usize) -> bool>,
+    pub draw: Option<unsafe extern "C" fn(handle: *mut c_void, scene: i32, layer: u32)>,
+    // For office objects and menu elements of a type the plugin registered, the host draw functions work in here
+    pub draw_object: Option<unsafe extern "C" fn(handle: *mut c_void, kind: u32, object_type: *const c_char, id: *const c_char, x: f32, y: f32)>,
+}
+
+// What the engine offers plugins. Strings are nul terminated UTF-8 and only borrowed for the call.
+// These only do anything while the engine is calling into the plugin (initialize, update, on_event,
+// run_block, draw), the draw functions only inside draw and draw_object. Only the draw functions and
+// log work in draw_object. Colors are 0xRRGGBBAA.
+#[repr(C)]
+pub struct PluginHost {
+    pub api_version: u32,
+    pub context: *mut c_void,
+    pub log: unsafe extern "C" fn(context: *mut c_void, message: *const c_char),
+    pub subscribe: unsafe extern "C" fn(context: *mut c_void, event: *const c_char),
+    pub trigger_event: unsafe extern "C" fn(context: *mut c_void, event: *const c_char, args: *const *const c_char, arg_count: usize),
+    pub register_block: unsafe extern "C" fn(context: *mut c_void, block: *const c_char),
+    // A new office object or menu element type for game.json. Clicking one fires click_event, none if it's
+    // null or empty. Clickable ones are sized by their sprite if click_sprite is set, otherwise their text.
+    pub register_object_type: unsafe extern "C" fn(context: *mut c_void, kind: u32, object_type: *const c_char, click_event: *const c_char, click_sprite: bool),
+    pub run_block: unsafe extern "C" fn(context: *mut c_void, block: *const c_char, args: *const *const c_char, arg_count: usize) -> bool,
+    // The getters copy into the buffer, cut to fit and nul terminated, and return the full length
+    // so a bigger buffer can be tried. -1 means there's no such value.
+    pub evaluate: unsafe extern "C" fn(context: *mut c_void, expression: *const c_char, buffer: *mut c_char, buffer_len: usize) -> i64,
+    pub get_var: unsafe extern "C" fn(context: *mut c_void, name: *const c_char, buffer: *mut c_char, buffer_len: usize) -> i64,
+    pub set_var: unsafe extern "C" fn(context: *mut c_void, name: *const c_char, value: *const c_char),
+    pub get_data: unsafe extern "C" fn(context: *mut c_void, name: *const c_char, buffer: *mut c_char, buffer_len: usize) -> i64,
+    pub set_data: unsafe extern "C" fn(context: *mut c_void, name: *const c_char, value: *const c_char),
+    pub draw_rect: unsafe extern "C" fn(context: *mut c_void, x: f32, y: f32, width: f32, height: f32, color: u32),
+    pub draw_text: unsafe extern "C" fn(context: *mut c_void, text: *const c_char, x: f32, y: f32, font_size: i32, color: u32),
+    pub draw_sprite: unsafe extern "C" fn(context: *mut c_void, sprite: *const c_char, x: f32, y: f32),
+}
+
+type PluginEntry = unsafe extern "C" fn(host_api_version: u32) -> *const PluginVTable;
+
+// Drawn by the engine once the plugin's draw() returns
+enum PluginDraw {
+    Rect { x: f32, y: f32, width: f32, height: f32, color: u32 },
+    Text { text: String, x: f32, y: f32, font_size: i32, color: u32 },
+    Sprite { sprite: String, x: f32, y: f32 },
+}
+
+// What the host functions can reach during a call into the plugin, all null outside of one
+#[derive(Clone, Copy)]
+struct HostCall {
+    engine: *mut EngineData,
+    events: *mut EventManager,
+    draws: *mut Vec<PluginDraw>,
+}
+
+impl HostCall {
+    fn none() -> HostCall {
+        HostCall { engine: std::ptr::null_mut(), events: std::ptr::null_mut(), draws: std::ptr::null_mut() }
+    }
+}
+
+// One per plugin, PluginHost::context points here. Event hooks and blocks the plugin registered hold
+// on to it, so it can outlive the library and `alive` keeps them from calling into unloaded code.
+struct PluginContext {
+    name: String,
+    vtable: PluginVTable,
+    handle: Cell<*mut c_void>,
+    alive: Cell<bool>,
+    call: Cell<HostCall>,
+    this: Weak<PluginContext>,
+    logger: Logger,
+}
+
+impl PluginContext {
+    // Lends the engine to the host functions for the length of one call into the plugin
+    fn enter<R, F>(&self, engine: &mut EngineData, events: &mut EventManager, draws: *mut Vec<PluginDraw>, call: F) -> Option<R>
+    where
+        F: FnOnce(*mut c_void) -> R,
+    {
+        if !self.alive.get() {
+            return None;
+        }
+        let previous = self.call.replace(HostCall { engine, events, draws });
+        let result = call(self.handle.get());
+        self.call.set(previous);
+        Some(result)
+    }
+
+    fn on_event(&self, engine: &mut EngineData, events: &mut EventManager, event: &str, args: &[String]) {
+        if let Some(on_event) = self.vtable.on_event {
+            let event = to_c_string(event);
+            let (_owned, pointers) = to_c_args(args);
+            self.enter(engine, events, std::ptr::null_mut(), |handle| unsafe {
+                on_event(handle, event.as_ptr(), pointers.as_ptr(), pointers.len())
+            });
+        }
+    }
+
+    // Office objects and menu elements are drawn without the event manager around, only drawing works
+    fn draw_object(&self, ctx: &mut Context, engine: &mut EngineData, cache: &mut CacheData, kind: u32, object_type: &str, id: &str, position: Vec2<f32>) -> tetra::Result {
+        let draw_object = match self.vtable.draw_object {
+            Some(draw_object) => draw_object,
+            None => return Ok(()),
+        };
+        if !self.alive.get() {
+            return Ok(());
+        }
+        let (object_type, id) = (to_c_string(object_type), to_c_string(id));
+        let mut draws = Vec::new();
+        let previous = self.call.replace(HostCall { engine, events: std::ptr::null_mut(), draws: &mut draws });
+        unsafe { draw_object(self.handle.get(), kind, object_type.as_ptr(), id.as_ptr(), position.x, position.y) };
+        self.call.set(previous);
+        render_plugin_draws(ctx, cache, draws)
+    }
+
+    fn run_block(&self, engine: &mut EngineData, events: &mut EventManager, block: &str, args: &[String]) -> bool {
+        let run_block = match self.vtable.run_block {
+            Some(run_block) => run_block,
+            None => return false,
+        };
+        let block = to_c_string(block);
+        let (_owned, pointers) = to_c_args(args);
+        self.enter(engine, events, std::ptr::null_mut(), |handle| unsafe {
+            run_block(handle, block.as_ptr(), pointers.as_ptr(), pointers.len())
+        }).unwrap_or(false)
+    }
+}
+
+// Interior nuls can't cross as C strings, they get dropped
+fn to_c_string(text: &str) -> CString {
+    CString::new(text.replace('\0', "")).unwrap_or_default()
 }
 
-pub trait PluginFactory {
-    fn create_plugin(&self) -> Box<dyn Plugin>;
+// The pointers borrow from the CStrings, keep both around until the call is done
+fn to_c_args(args: &[String]) -> (Vec<CString>, Vec<*const c_char>) {
+    let owned: Vec<CString> = args.iter().map(|arg| to_c_string(arg)).collect();
+    let pointers = owned.iter().map(|arg| arg.as_ptr()).collect();
+    (owned, pointers)
 }
+
+unsafe fn from_c_string(text: *const c_char) -> String {
+    if text.is_null() {
+        return String::new();
+    }
+    CStr::from_ptr(text).to_string_lossy().to_string()
+}
+
+unsafe fn from_c_args(args: *const *const c_char, arg_count: usize) -> Vec<String> {
+    if args.is_null() {
+        return Vec::new();
+    }
+    std::slice::from_raw_parts(args, arg_count).iter().map(|arg| from_c_string(*arg)).collect()
+}
+
+unsafe fn copy_to_buffer(value: Option<String>, buffer: *mut c_char, buffer_len: usize) -> i64 {
+    let value = match value {
+        Some(value) => value,
+        None => return -1,
+    };
+    if !buffer.is_null() && buffer_len > 0 {
+        let count = value.len().min(buffer_len - 1);
+        std::ptr::copy_nonoverlapping(value.as_ptr() as *const c_char, buffer, count);
+        *buffer.add(count) = 0;
+    }
+    value.len() as i64
+}
+
+// The context pointer back to the plugin, and the engine if it's currently lent out
+unsafe fn host_call<'a>(context: *mut c_void) -> (&'a PluginContext, Option<(&'a mut EngineData, &'a mut EventManager)>) {
+    let plugin = &*(context as *const PluginContext);
+    let call = plugin.call.get();
+    if call.engine.is_null() || call.events.is_null() {
+        return (plugin, None);
+    }
+    (plugin, Some((&mut *call.engine, &mut *call.events)))
+}
+
+unsafe extern "C" fn host_log(context: *mut c_void, message: *const c_char) {
+    let (plugin, _) = host_call(context);
+    plugin.logger.log(&plugin.name, from_c_string(message).as_str());
+}
+
+unsafe extern "C" fn host_subscribe(context: *mut c_void, event: *const c_char) {
+    let (plugin, call) = host_call(context);
+    let Some((_, events)) = call else { return };
+    let Some(this) = plugin.this.upgrade() else { return };
+    let event = from_c_string(event);
+    let name = event.clone();
+    events.add_event_hook(&event, move |events, engine, args| this.on_event(engine, events, &name, args));
+}
+
+unsafe extern "C" fn host_trigger_event(context: *mut c_void, event: *const c_char, args: *const *const c_char, arg_count: usize) {
+    let (_, call) = host_call(context);
+    if let Some((engine, events)) = call {
+        events.trigger_event(&from_c_string(event), &from_c_args(args, arg_count), engine);
+    }
+}
+
+unsafe extern "C" fn host_register_block(context: *mut c_void, block: *const c_char) {
+    let (plugin, call) = host_call(context);
+    let Some((_, events)) = call else { return };
+    let Some(this) = plugin.this.upgrade() else { return };
+    let block = from_c_string(block);
+    let name = block.clone();
+    events.register_code_block(&block, move |engine, events, args| {
+        let args: Vec<String> = args.iter()
+            .map(|arg| events.get_expr(&ScriptingAPI::arg_to_string(arg), engine))
+            .collect();
+        this.run_block(engine, events, &name, &args)
+    });
+}
+
+unsafe extern "C" fn host_register_object_type(context: *mut c_void, kind: u32, object_type: *const c_char, click_event: *const c_char, click_sprite: bool) {
+    let (plugin, call) = host_call(context);
+    let Some((engine, _)) = call else { return };
+    let Some(this) = plugin.this.upgrade() else { return };
+    let object_type = from_c_string(object_type);
+    let click_event = from_c_string(click_event);
+    let click = (!click_event.is_empty()).then(|| ClickArea { event: click_event, sprite: click_sprite });
+
+    match kind {
+        PLUGIN_OBJECT_OFFICE => {
+            let name = object_type.clone();
+            engine.extensions.register_office_object(&object_type, OfficeObjectType {
+                init: Rc::new(OfficeManager::init_object),
+                click,
+                draw: Rc::new(move |ctx, engine, cache, object, position| {
+                    if !engine.officemgr.game_data.office.objects.get(&object.id).map_or(false, |object| object.visible) {
+                        return Ok(());
+                    }
+                    this.draw_object(ctx, engine, cache, PLUGIN_OBJECT_OFFICE, &name, &object.id, position)
+                }),
+            });
+        }
+        PLUGIN_OBJECT_MENU => {
+            let name = object_type.clone();
+            engine.extensions.register_menu_element(&object_type, MenuElementType {
+                click,
+                draw: Rc::new(move |ctx, engine, cache, element, position| {
+                    if element.hidden {
+                        return Ok(());
+                    }
+                    this.draw_object(ctx, engine, cache, PLUGIN_OBJECT_MENU, &name, &element.id, position)
+                }),
+            });
+        }
+        _ => plugin.logger.log_error(&plugin.name, format!("Can't register '{}', {} isn't an object kind.", object_type, kind).as_str()),
+    }
+}
+
+unsafe extern "C" fn host_run_block(context: *mut c_void, block: *const c_char, args: *const *const c_char, arg_count: usize) -> bool {
+    let (_, call) = host_call(context);
+    let Some((engine, events)) = call else { return false };
+    let code = Code {
+        negated: false,
+        args: from_c_args(args, arg_count).into_iter().map(Value::String).collect(),
+        block: from_c_string(block),
+        subcode: Vec::new(),
+        priority: 0,
+    };
+    events.call_code_block(engine, &code)
+}
+
+unsafe extern "C" fn host_evaluate(context: *mut c_void, expression: *const c_char, buffer: *mut c_char, buffer_len: usize) -> i64 {
+    let (_, call) = host_call(context);
+    let value = call.map(|(engine, events)| events.get_expr(&from_c_string(expression), engine));
+    copy_to_buffer(value, buffer, buffer_len)
+}
+
+unsafe extern "C" fn host_get_var(context: *mut c_void, name: *const c_char, buffer: *mut c_char, buffer_len: usize) -> i64 {
+    let (_, call) = host_call(context);
+    let value = call.and_then(|(_, events)| events.get_variable_value(&from_c_string(name)).map(|value| value.to_string()));
+    copy_to_buffer(value, buffer, buffer_len)
+}
+
+unsafe extern "C" fn host_set_var(context: *mut c_void, name: *const c_char, value: *const c_char) {
+    let (_, call) = host_call(context);
+    if let Some((_, events)) = call {
+        events.set_variable_value(from_c_string(name), ScriptValue::parse(&from_c_string(value)));
+    }
+}
+
+unsafe extern "C" fn host_get_data(context: *mut c_void, name: *const c_char, buffer: *mut c_char, buffer_len: usize) -> i64 {
+    let (_, call) = host_call(context);
+    let value = call.and_then(|(_, events)| events.get_data_value(&from_c_string(name)).map(|value| value.to_string()));
+    copy_to_buffer(value, buffer, buffer_len)
+}
+
+unsafe extern "C" fn host_set_data(context: *mut c_void, name: *const c_char, value: *const c_char) {
+    let (_, call) = host_call(context);
+    if let Some((_, events)) = call {
+        events.set_data_value(from_c_string(name), ScriptValue::parse(&from_c_string(value)));
+    }
+}
+
+unsafe fn push_draw(context: *mut c_void, draw: PluginDraw) {
+    let plugin = &*(context as *const PluginContext);
+    let draws = plugin.call.get().draws;
+    if !draws.is_null() {
+        (*draws).push(draw);
+    }
+}
+
+unsafe extern "C" fn host_draw_rect(context: *mut c_void, x: f32, y: f32, width: f32, height: f32, color: u32) {
+    push_draw(context, PluginDraw::Rect { x, y, width, height, color });
+}
+
+unsafe extern "C" fn host_draw_text(context: *mut c_void, text: *const c_char, x: f32, y: f32, font_size: i32, color: u32) {
+    push_draw(context, PluginDraw::Text { text: from_c_string(text), x, y, font_size, color });
+}
+
+unsafe extern "C" fn host_draw_sprite(context: *mut c_void, sprite: *const c_char, x: f32, y: f32) {
+    push_draw(context, PluginDraw::Sprite { sprite: from_c_string(sprite), x, y });
+}
+
+fn plugin_color(color: u32) -> Color {
+    Color::rgba8((color >> 24) as u8, (color >> 16) as u8, (color >> 8) as u8, color as u8)
+}
+
+// Draws what a plugin queued up, in order
+fn render_plugin_draws(ctx: &mut Context, cache: &mut CacheData, draws: Vec<PluginDraw>) -> tetra::Result {
+    for draw in draws {
+        match draw {
+            PluginDraw::Rect { x, y, width, height, color } => {
+                Mesh::rectangle(ctx, ShapeStyle::Fill, Rectangle::new(x, y, width, height))?
+                    .draw(ctx, DrawParams::new().color(plugin_color(color)));
+            }
+            PluginDraw::Text { text, x, y, font_size, color } => {
+                // Only sizes the game already loaded, plugins can't load fonts. Not cached, plugin
+                // text changes every frame as often as not and the cache would never let go of it.
+                if let Some(font) = cache.fonts.get(&font_size) {
+                    Text::new(text.as_str(), font.clone())
+                        .draw(ctx, DrawParams::new().position(Vec2::new(x, y)).color(plugin_color(color)));
+                }
+            }
+            PluginDraw::Sprite { sprite, x, y } => {
+                if let Some(texture) = cache.textures.get(&sprite) {
+                    texture.draw(ctx, Vec2::new(x, y));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+struct LoadedPlugin {
+    context: Rc<PluginContext>,
+    // Handed to the plugin at create, boxed so it doesn't move
+    _host: Box<PluginHost>,
+    // Dropped after the handle is destroyed, the code behind the table has to outlive it
+    _library: Library,
+}
+
+impl Drop for LoadedPlugin {
+    fn drop(&mut self) {
+        let context = &self.context;
+        context.alive.set(false);
+        if let Some(destroy) = context.vtable.destroy {
+            unsafe { destroy(context.handle.get()) };
+        }
+    }
+}
+
 pub struct PluginManager {
-    plugins: Vec<Box<dyn Plugin>>,
-    libraries: Vec<Library>, // Hold onto the libraries to ensure they aren't dropped
+    plugins: Vec<LoadedPlugin>,
+    shut_down: bool,
+    logger: Logger,
 }
 
 impl PluginManager {
-    pub fn new() -> Self {
+    pub fn new(logger: Logger) -> Self {
         PluginManager {
             plugins: Vec::new(),
-            libraries: Vec::new(),
+            shut_down: false,
+            logger,
         }
     }
 
-    pub unsafe fn load_plugin(&mut self, path: &str) {
-        let lib = Library::new(PathBuf::from(path)).unwrap();
-        self.libraries.push(lib);
+    // Loads every library in the folder, one that fails is logged and skipped.
+    // Unsafe because loading a library runs its code, the caller has to trust what's in the folder.
+    pub unsafe fn load_plugins(&mut self, dir: &Path) {
+        let mut paths: Vec<PathBuf> = match fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().map_or(false, |ext| ext == std::env::consts::DLL_EXTENSION))
+                .collect(),
+            Err(_) => return,
+        };
+        paths.sort();
+
+        for path in paths {
+            if let Err(e) = self.load_plugin(&path) {
+                self.logger.log_error("PluginManager", e.as_str());
+            }
+        }
+    }
+
+    fn load_plugin(&mut self, path: &Path) -> Result<(), String> {
+        // Loading a library runs its initialisers, which is why only the plugins folder gets looked at
+        let library = unsafe { Library::new(path) }
+            .map_err(|e| format!("Failed to load plugin {:?}: {}", path, e))?;
+        let entry: PluginEntry = unsafe {
+            let symbol: Symbol<PluginEntry> = library.get(PLUGIN_ENTRY_SYMBOL)
+                .map_err(|e| format!("{:?} has no fnaf_plugin_entry, it isn't an engine plugin: {}", path, e))?;
+            *symbol
+        };
+
+        let table = unsafe { entry(PLUGIN_API_VERSION) };
+        if table.is_null() {
+            return Err(format!("{:?} doesn't support plugin API version {}.", path, PLUGIN_API_VERSION));
+        }
+        let vtable = unsafe { *table };
+        if vtable.api_version != PLUGIN_API_VERSION {
+            return Err(format!("{:?} was built for plugin API version {}, the engine is on {}.", path, vtable.api_version, PLUGIN_API_VERSION));
+        }
+        let create = vtable.create.ok_or_else(|| format!("{:?} has no create function.", path))?;
 
-        let factory: Symbol<extern "C" fn() -> Box<dyn PluginFactory>> = {
-            self.libraries.last().unwrap().get(b"create_plugin").unwrap()
+        let name = if vtable.name.is_null() {
+            path.file_stem().unwrap_or_default().to_string_lossy().to_string()
+        } else {
+            unsafe { from_c_string(vtable.name) }
         };
+        let context = Rc::new_cyclic(|this| PluginContext {
+            name: name.clone(),
+            vtable,
+            handle: Cell::new(std::ptr::null_mut()),
+            alive: Cell::new(true),
+            call: Cell::new(HostCall::none()),
+            this: this.clone(),
+            logger: self.logger.clone(),
+        });
+        let host = Box::new(PluginHost {
+            api_version: PLUGIN_API_VERSION,
+            context: Rc::as_ptr(&context) as *mut c_void,
+            log: host_log,
+            subscribe: host_subscribe,
+            trigger_event: host_trigger_event,
+            register_block: host_register_block,
+            register_object_type: host_register_object_type,
+            run_block: host_run_block,
+            evaluate: host_evaluate,
+            get_var: host_get_var,
+            set_var: host_set_var,
+            get_data: host_get_data,
+            set_data: host_set_data,
+            draw_rect: host_draw_rect,
+            draw_text: host_draw_text,
+            draw_sprite: host_draw_sprite,
+        });
+
+        let handle = unsafe { create(&*host) };
+        if handle.is_null() {
+            return Err(format!("Plugin {} failed to start.", name));
+        }
+        context.handle.set(handle);
 
-        let plugin_factory = factory();
-        let plugin = plugin_factory.create_plugin();
-        self.plugins.push(plugin);
+        self.logger.log("PluginManager", format!("Loaded plugin {} from {:?}", name, path).as_str());
+        self.plugins.push(LoadedPlugin { context, _host: host, _library: library });
+        Ok(())
+    }
+
+    // Plugins subscribe to events and register blocks in here, so the scripts have to be running already
+    pub fn initialize_plugins(&mut self, engine: &mut EngineData, events: &mut EventManager) {
+        for plugin in self.plugins.iter() {
+            if let Some(initialize) = plugin.context.vtable.initialize {
+                plugin.context.enter(engine, events, std::ptr::null_mut(), |handle| unsafe { initialize(handle) });
+            }
+        }
     }
 
-    pub fn initialize_plugins(&mut self) {
-        for plugin in self.plugins.iter_mut() {
-            plugin.initialize();
+    pub fn update_plugins(&mut self, engine: &mut EngineData, events: &mut EventManager, delta_seconds: f64) {
+        for plugin in self.plugins.iter() {
+            if let Some(update) = plugin.context.vtable.update {
+                plugin.context.enter(engine, events, std::ptr::null_mut(), |handle| unsafe { update(handle, delta_seconds) });
+            }
         }
     }
 
-    pub fn update_plugins(&mut self, ctx: &mut Context) {
-        for plugin in self.plugins.iter_mut() {
-            plugin.update(ctx);
+    // Lets every plugin queue up its drawing for the layer, then draws it all in load order
+    pub fn draw_plugins(&mut self, ctx: &mut Context, engine: &mut EngineData, events: &mut EventManager, cache: &mut CacheData, layer: u32) -> tetra::Result {
+        let mut draws: Vec<PluginDraw> = Vec::new();
+        let scene = engine.scenes.current() as i32;
+        for plugin in self.plugins.iter() {
+            if let Some(draw) = plugin.context.vtable.draw {
+                plugin.context.enter(engine, events, &mut draws, |handle| unsafe { draw(handle, scene, layer) });
+            }
         }
+
+        render_plugin_draws(ctx, cache, draws)
+    }
+
+    pub fn plugin_names(&self) -> Vec<String> {
+        self.plugins.iter().map(|plugin| plugin.context.name.clone()).collect()
     }
 
+    // Safe to call more than once, only the first one reaches the plugins
     pub fn shutdown_plugins(&mut self) {
-        for plugin in self.plugins.iter_mut() {
-            plugin.shutdown();
+        if self.shut_down {
+            return;
+        }
+        self.shut_down = true;
+        for plugin in self.plugins.iter() {
+            if let Some(shutdown) = plugin.context.vtable.shutdown {
+                unsafe { shutdown(plugin.context.handle.get()) };
+            }
+            self.logger.log("PluginManager", format!("Shut down plugin {}", plugin.context.name).as_str());
         }
     }
 }
+
+// Closing the window skips any quit handling, this makes sure plugins still get told
+impl Drop for PluginManager {
+    fn drop(&mut self) {
+        self.shutdown_plugins();
+    }
+}
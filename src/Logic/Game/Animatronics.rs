@@ -0,0 +1,46 @@
+pub struct AnimatronicManager;
+
+impl AnimatronicManager {
+    // Copies the animatronics from game.json into the night's game data, everyone starts at the first node of their path
+    pub fn load_for_night(engine: &mut EngineData) {
+        let mut animatronics = HashMap::new();
+        for (name, animatronic) in &engine.game.animatronics {
+            let path: Vec<PathNode> = animatronic.path.iter().flatten().map(Self::convert_node).collect();
+            let jumpscare = animatronic.Jumpscare.clone().unwrap_or_default();
+            let location = path.first().cloned().unwrap_or_else(|| PathNode::new(String::new(), String::new(), 0, String::new(), Vec::new(), String::new()));
+            animatronics.insert(name.clone(), Animatronic {
+                ignores_mask: animatronic.Ignoremask,
+                path,
+                ai: animatronic.AI.clone().unwrap_or_default(),
+                jumpscare: AnimatronicJumpscare {
+                    jumpscare: jumpscare.get(0).cloned().unwrap_or_default(),
+                    sound: jumpscare.get(1).cloned().unwrap_or_default(),
+                    offset: 0,
+                },
+                location,
+                name: name.clone(),
+                // Moving them is up to the game's scripts
+                move_time: 0,
+                state: animatronic.State.clone(),
+                location_index: 0,
+                phantom: false,
+            });
+        }
+        engine.officemgr.game_data.animatronics = animatronics;
+    }
+
+    fn convert_node(node: &GameLoader::PathNode) -> PathNode {
+        PathNode::new(
+            node.id.clone(),
+            node.r#type.clone(),
+            node.chance,
+            node.state.clone(),
+            node.path.iter().flatten().map(Self::convert_node).collect(),
+            node.camid.clone(),
+        )
+    }
+
+    pub fn ai_level(animatronic: &Animatronic, night: i32) -> i32 {
+        animatronic.ai.get(night as usize).or(animatronic.ai.last()).copied().unwrap_or(0)
+    }
+}
@@ -1,24 +1,57 @@
-
 use tetra::audio::{Sound, SoundInstance, SoundState};
 use std::fs;
 use std::fs::File;
 use std::io::{BufReader, Read};
-pub struct AudioManager
-{
+use std::collections::HashSet;
+
+// Where sounds actually come out. AudioManager only does the channel bookkeeping on top.
+pub trait AudioBackend {
+    fn has_sound(&self, id: &str) -> bool;
+    fn play(&mut self, id: &str, loop_audio: bool);
+    fn stop(&mut self, id: &str);
+    fn is_playing(&self, id: &str) -> bool;
+    fn set_volume(&mut self, id: &str, volume: f32);
+    fn pause(&mut self, id: &str);
+    // Carries on from where pause left it
+    fn resume(&mut self, id: &str);
+}
+
+// Every file in the sound folders, keyed by file name. A later folder's file replaces an earlier one's,
+// that's how mods override sounds.
+fn sound_files(dirs: &[PathBuf]) -> Vec<(String, PathBuf)> {
+    let mut files: BTreeMap<String, PathBuf> = BTreeMap::new();
+    for dir in dirs {
+        match fs::read_dir(dir) {
+            Ok(entries) => {
+                for entry in entries {
+                    match entry {
+                        Ok(dir_entry) => {
+                            let path = dir_entry.path();
+                            if path.is_file() {
+                                if let Some(file_name) = path.file_name().and_then(|s| s.to_str()) {
+                                    files.insert(file_name.to_string(), path.clone());
+                                }
+                            }
+                        },
+                        Err(e) => println!("Error reading directory entry: {:?}", e),
+                    }
+                }
+            },
+            // Mods without sounds are the usual case
+            Err(e) if dir.exists() => println!("{:?}", e),
+            Err(_) => {}
+        }
+    }
+    files.into_iter().collect()
+}
+
+pub struct TetraAudio {
     sounds: HashMap<String, Arc<SoundInstance>>,
-    channels: Vec<String>,
 }
 
-impl AudioManager {
+impl TetraAudio {
     pub fn new() -> Self {
-        let mut channels = Vec::new();
-        for _ in 0..48 {
-            channels.push(String::new());
-        }
-        AudioManager {
-            sounds: HashMap::new(),
-            channels,
-        }
+        TetraAudio { sounds: HashMap::new() }
     }
 
     pub fn load_audio(&mut self, ctx: &mut Context, id: &str, file_path: &str) {
@@ -32,124 +65,327 @@ impl AudioManager {
         }
     }
 
-    pub fn load_audio_assets(&mut self, ctx: &mut Context, assets: &PathBuf) {
-        match fs::read_dir(assets.join("sounds")) {
-            Ok(entries) => {
-                for entry in entries {
-                    match entry {
-                        Ok(dir_entry) => {
-                            let path = dir_entry.path();
-                            if path.is_file() {
-                                if let Some(file_name) = path.file_name().and_then(|s| s.to_str()) {
-                                    self.load_audio(ctx, &file_name.to_string(), path.to_str().unwrap());
-                                }
-                            }
-                        },
-                        Err(e) => println!("Error reading directory entry: {:?}", e),
-                    }
-                }
-            },
-            Err(e) => println!("{:?}", e),
+    pub fn load_audio_assets(&mut self, ctx: &mut Context, sound_dirs: &[PathBuf]) {
+        for (id, path) in sound_files(sound_dirs) {
+            self.load_audio(ctx, &id, path.to_str().unwrap());
         }
     }
+}
 
-    pub fn play(&mut self, id: &str, loop_audio: bool) {
+impl AudioBackend for TetraAudio {
+    fn has_sound(&self, id: &str) -> bool {
+        self.sounds.contains_key(id)
+    }
+
+    fn play(&mut self, id: &str, loop_audio: bool) {
+        if let Some(sound) = self.sounds.get(id) {
+            sound.play();
+            if loop_audio {
+                sound.set_repeating(true);
+            }
+        }
+    }
+
+    fn stop(&mut self, id: &str) {
+        if let Some(sound) = self.sounds.get(id) {
+            if sound.state() == SoundState::Playing {
+                sound.stop();
+            }
+        }
+    }
+
+    fn is_playing(&self, id: &str) -> bool {
+        self.sounds.get(id).map_or(false, |sound| sound.state() == SoundState::Playing)
+    }
+
+    fn set_volume(&mut self, id: &str, volume: f32) {
+        if let Some(sound) = self.sounds.get(id) {
+            sound.set_volume(volume);
+        }
+    }
+
+    fn pause(&mut self, id: &str) {
+        if let Some(sound) = self.sounds.get(id) {
+            if sound.state() == SoundState::Playing {
+                sound.pause();
+            }
+        }
+    }
+
+    fn resume(&mut self, id: &str) {
         if let Some(sound) = self.sounds.get(id) {
-            let sound = sound.clone();
+            if sound.state() == SoundState::Paused {
+                sound.play();
+            }
+        }
+    }
+}
+
+// Knows which sounds exist but never decodes or plays them. Keeps a log of what would have played.
+pub struct NullAudio {
+    sounds: HashSet<String>,
+    playing: HashSet<String>,
+    paused: HashSet<String>,
+    pub played: Vec<String>,
+}
+
+impl NullAudio {
+    pub fn new(sound_dirs: &[PathBuf]) -> Self {
+        NullAudio {
+            // Same filter as the tetra backend so channels fill up the same way
+            sounds: sound_files(sound_dirs).into_iter().map(|(id, _)| id).filter(|id| !id.contains(".wav")).collect(),
+            playing: HashSet::new(),
+            paused: HashSet::new(),
+            played: Vec::new(),
+        }
+    }
+}
+
+impl AudioBackend for NullAudio {
+    fn has_sound(&self, id: &str) -> bool {
+        self.sounds.contains(id)
+    }
+
+    fn play(&mut self, id: &str, loop_audio: bool) {
+        if self.sounds.contains(id) {
+            self.playing.insert(id.to_string());
+            self.played.push(id.to_string());
+        }
+    }
+
+    fn stop(&mut self, id: &str) {
+        self.playing.remove(id);
+        self.paused.remove(id);
+    }
+
+    fn is_playing(&self, id: &str) -> bool {
+        self.playing.contains(id)
+    }
+
+    fn set_volume(&mut self, id: &str, volume: f32) {}
+
+    fn pause(&mut self, id: &str) {
+        if self.playing.remove(id) {
+            self.paused.insert(id.to_string());
+        }
+    }
+
+    fn resume(&mut self, id: &str) {
+        if self.paused.remove(id) {
+            self.playing.insert(id.to_string());
+        }
+    }
+}
+
+// A channel's volume on its way somewhere, how music cross-fades between scenes
+struct VolumeFade {
+    from: f32,
+    to: f32,
+    elapsed: f32,
+    duration: f32,
+}
+
+impl VolumeFade {
+    fn volume(&self) -> f32 {
+        if self.duration <= 0.0 {
+            return self.to;
+        }
+        self.from + (self.to - self.from) * (self.elapsed / self.duration).min(1.0)
+    }
+}
+
+pub struct AudioManager
+{
+    backend: Box<dyn AudioBackend>,
+    channels: Vec<String>,
+    // Channels pause_all stopped, while the game's paused
+    paused: Option<Vec<usize>>,
+    fades: HashMap<usize, VolumeFade>,
+    // What scripts set each channel to. Fades go from silent up to this, and a cut short one goes back to it.
+    volumes: Vec<f32>,
+}
+
+impl AudioManager {
+    pub fn new(backend: Box<dyn AudioBackend>) -> Self {
+        let mut channels = Vec::new();
+        for _ in 0..48 {
+            channels.push(String::new());
+        }
+        AudioManager {
+            backend,
+            volumes: vec![1.0; channels.len()],
+            channels,
+            paused: None,
+            fades: HashMap::new(),
+        }
+    }
+
+    pub fn play(&mut self, id: &str, loop_audio: bool) {
+        if self.backend.has_sound(id) {
             if let Some(channel) = self.get_available_channel() {
                 self.channels[channel] = id.to_string();
-                sound.play();
-                if loop_audio {
-                    sound.set_repeating(true);
-                }
+                self.backend.play(id, loop_audio);
             }
         }
     }
 
     pub fn play_on_channel(&mut self, id: &str, loop_audio: bool, channel_idx: usize) {
-        if let Some(sound) = self.sounds.get(id) {
-            let sound = sound.clone();
+        if self.backend.has_sound(id) {
             if channel_idx < self.channels.len() {
-                let existing_soundname = &self.channels[channel_idx];
-                if self.sounds.contains_key(existing_soundname) {
-                    self.sounds[existing_soundname].clone().stop();
+                let existing_soundname = self.channels[channel_idx].clone();
+                if self.backend.has_sound(&existing_soundname) {
+                    self.backend.stop(&existing_soundname);
                 }
+                self.cancel_fade(channel_idx, &existing_soundname);
                 self.channels[channel_idx] = id.to_string();
-                sound.play();
-                if loop_audio {
-                    sound.set_repeating(true);
-                }
+                self.backend.play(id, loop_audio);
             } else {
                 println!("Nonexistent sound: {}", channel_idx);
             }
         }
     }
 
-    pub fn stop(&self, id: &str) {
-        let mut sound = self.sounds[id].clone();
+    pub fn stop(&mut self, id: &str) {
      //   self.channels[id] = String::new(); // figure this shit out
-        if sound.state() == SoundState::Playing {
-            sound.stop();
-        }
+        self.backend.stop(id);
     }
 
     pub fn stop_channel(&mut self, index: usize) {
         if index < self.channels.len() {
-            let mut soundname = &self.channels[index];
-            if self.sounds.contains_key(soundname) {
-                let mut sound = self.sounds[soundname].clone();
+            let soundname = self.channels[index].clone();
+            if self.backend.has_sound(&soundname) {
                 self.channels[index] = String::new();
-                if sound.state() == SoundState::Playing {
-                    sound.stop();
-                }
+                self.backend.stop(&soundname);
             }
+            self.cancel_fade(index, &soundname);
         } else {
             println!("Invalid player index: {}", index);
         }
     }
 
     pub fn kill_all(&mut self) {
-        let mut update_idxs: Vec<usize> = Vec::new();
+        self.paused = None;
+        for index in 0..self.channels.len() {
+            let soundname = std::mem::take(&mut self.channels[index]);
+            if self.backend.has_sound(&soundname) {
+                self.backend.stop(&soundname);
+            }
+            self.cancel_fade(index, &soundname);
+        }
+    }
+
+    // Every playing channel fades to silent and then stops, ones already fading out carry on as they were
+    pub fn fade_out_all(&mut self, seconds: f32) {
         for (index, soundname) in self.channels.iter().enumerate() {
-            if self.sounds.contains_key(soundname) {
-                let mut sound = self.sounds[soundname].clone();
-                update_idxs.push(index);
-                if sound.state() == SoundState::Playing {
-                    sound.stop();
-                }
+            let fading_out = self.fades.get(&index).map_or(false, |fade| fade.to == 0.0);
+            if !fading_out && self.backend.is_playing(soundname) {
+                let from = self.fades.get(&index).map_or(1.0, VolumeFade::volume);
+                self.fades.insert(index, VolumeFade { from, to: 0.0, elapsed: 0.0, duration: seconds });
+            }
+        }
+    }
+
+    // Starts silent and fades up. If it's already playing, say it's fading out with the last menu that
+    // had the same music, it fades back up from there instead of starting over.
+    pub fn play_faded(&mut self, id: &str, loop_audio: bool, seconds: f32) {
+        if !self.backend.has_sound(id) {
+            return;
+        }
+        if let Some(channel) = self.channels.iter().position(|soundname| soundname == id && self.backend.is_playing(soundname)) {
+            let from = self.fades.get(&channel).map_or(1.0, VolumeFade::volume);
+            self.fades.insert(channel, VolumeFade { from, to: 1.0, elapsed: 0.0, duration: seconds });
+            return;
+        }
+        if let Some(channel) = self.get_available_channel() {
+            self.channels[channel] = id.to_string();
+            self.backend.set_volume(id, 0.0);
+            self.backend.play(id, loop_audio);
+            self.fades.insert(channel, VolumeFade { from: 0.0, to: 1.0, elapsed: 0.0, duration: seconds });
+        }
+    }
+
+    // Runs every tick, a channel that's done fading out gets stopped
+    pub fn tick(&mut self, delta: f32) {
+        let mut finished = Vec::new();
+        for (index, fade) in self.fades.iter_mut() {
+            fade.elapsed += delta;
+            self.backend.set_volume(&self.channels[*index], fade.volume() * self.volumes[*index]);
+            if fade.elapsed >= fade.duration {
+                finished.push(*index);
+            }
+        }
+        for index in finished {
+            if self.fades[&index].to == 0.0 {
+                self.stop_channel(index);
+            } else {
+                self.fades.remove(&index);
+            }
+        }
+    }
+
+    // Sounds get reused, so one stopped while it was faded has to be put back to its channel's volume for next time
+    fn cancel_fade(&mut self, index: usize, soundname: &str) {
+        if self.fades.remove(&index).is_some() {
+            self.backend.set_volume(soundname, self.volumes[index]);
+        }
+    }
+
+    pub fn pause_all(&mut self) {
+        if self.paused.is_some() {
+            return;
+        }
+        let mut paused = Vec::new();
+        for (index, soundname) in self.channels.iter().enumerate() {
+            if self.backend.is_playing(soundname) {
+                self.backend.pause(soundname);
+                paused.push(index);
             }
         }
-        for idx in update_idxs
-        {
-            self.channels[idx] = String::new();
+        self.paused = Some(paused);
+    }
+
+    // Picks the paused channels back up, anything started during the pause (like the pause menu's music) stops
+    pub fn resume_all(&mut self) {
+        let paused = match self.paused.take() {
+            Some(paused) => paused,
+            None => return,
+        };
+        for index in 0..self.channels.len() {
+            if paused.contains(&index) {
+                let soundname = self.channels[index].clone();
+                self.backend.resume(&soundname);
+            } else if self.backend.is_playing(&self.channels[index]) {
+                self.stop_channel(index);
+            }
         }
     }
 
-    pub fn set_channel_volume(&self, channel_idx: usize, volume: f32) {
+    pub fn set_channel_volume(&mut self, channel_idx: usize, volume: f32) {
         if channel_idx < self.channels.len() {
-            let soundname = &self.channels[channel_idx];
-            if self.sounds.contains_key(soundname) {
-                self.sounds[soundname].clone().set_volume(volume);
+            self.volumes[channel_idx] = volume;
+            let soundname = self.channels[channel_idx].clone();
+            if self.backend.has_sound(&soundname) {
+                let fade = self.fades.get(&channel_idx).map_or(1.0, VolumeFade::volume);
+                self.backend.set_volume(&soundname, volume * fade);
             }
         } else {
             println!("Invalid player index: {}", channel_idx);
         }
     }
 
-    pub fn set_all_volumes(&self, volume: f32) {
-        for soundname in &self.channels {
-            if self.sounds.contains_key(soundname) {
-                let mut sound = self.sounds[soundname].clone();
-                if sound.state() == SoundState::Playing {
-                    sound.set_volume(volume);
-                }
+    pub fn set_all_volumes(&mut self, volume: f32) {
+        for (index, soundname) in self.channels.iter().enumerate() {
+            if self.backend.is_playing(soundname) {
+                self.volumes[index] = volume;
+                let fade = self.fades.get(&index).map_or(1.0, VolumeFade::volume);
+                self.backend.set_volume(soundname, volume * fade);
             }
         }
     }
 
     fn get_available_channel(&self) -> Option<usize> {
         for (index, soundname) in self.channels.iter().enumerate() {
-            if !self.sounds.contains_key(soundname) {
+            if !self.backend.has_sound(soundname) {
                 return Some(index);
             }
         }
@@ -0,0 +1,211 @@
+use std::collections::{BTreeMap, BTreeSet};
+use tetra::input::Key;
+
+// Key names are tetra's Key names lowercased, e.g. "a", "space", "left", "num1".
+// Mouse buttons are "left", "middle" and "right".
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputState {
+    pub keys_down: BTreeSet<String>,
+    #[serde(default)]
+    pub mouse_down: BTreeSet<String>,
+    #[serde(default)]
+    pub mouse_x: f32,
+    #[serde(default)]
+    pub mouse_y: f32,
+}
+
+pub struct InputManager {
+    pub current: InputState,
+    pub previous: InputState,
+    // Keys seen down on any frame since the last tick, so taps shorter than a tick still register
+    pending: BTreeSet<String>,
+    pending_mouse: BTreeSet<String>,
+    mouse_position: Vec2<f32>,
+    pub bindings: BTreeMap<String, Vec<String>>,
+    recorder: Option<InputRecorder>,
+    // Where --record goes, the recorder only starts once the save is loaded so the header can hold it
+    record_path: Option<PathBuf>,
+    // While replaying the real keyboard and mouse are ignored
+    replay: Option<InputReplay>,
+}
+
+impl InputManager {
+    pub fn new(game_bindings: &HashMap<String, Vec<String>>) -> InputManager {
+        let mut bindings = Self::default_bindings();
+        for (action, keys) in game_bindings {
+            bindings.insert(action.clone(), keys.iter().map(|k| k.to_lowercase()).collect());
+        }
+        InputManager {
+            current: InputState::default(),
+            previous: InputState::default(),
+            pending: BTreeSet::new(),
+            pending_mouse: BTreeSet::new(),
+            mouse_position: Vec2::zero(),
+            bindings,
+            recorder: None,
+            record_path: None,
+            replay: None,
+        }
+    }
+
+    fn default_bindings() -> BTreeMap<String, Vec<String>> {
+        let mut bindings = BTreeMap::new();
+        bindings.insert("toggle_camera".to_string(), vec!["s".to_string()]);
+        bindings.insert("mask".to_string(), vec!["w".to_string()]);
+        bindings.insert("pause".to_string(), vec!["escape".to_string()]);
+        // Minigame movement
+        bindings.insert("move_up".to_string(), vec!["up".to_string()]);
+        bindings.insert("move_down".to_string(), vec!["down".to_string()]);
+        bindings.insert("move_left".to_string(), vec!["left".to_string()]);
+        bindings.insert("move_right".to_string(), vec!["right".to_string()]);
+        bindings
+    }
+
+    pub fn key_name(key: Key) -> String {
+        format!("{:?}", key).to_lowercase()
+    }
+
+    pub fn mouse_button_name(button: MouseButton) -> Option<&'static str> {
+        match button {
+            MouseButton::Left => Some("left"),
+            MouseButton::Middle => Some("middle"),
+            MouseButton::Right => Some("right"),
+            _ => None,
+        }
+    }
+
+    pub fn record_to(&mut self, path: PathBuf) {
+        self.record_path = Some(path);
+    }
+
+    pub fn take_record_path(&mut self) -> Option<PathBuf> {
+        self.record_path.take()
+    }
+
+    pub fn start_recording(&mut self, recorder: InputRecorder) {
+        self.recorder = Some(recorder);
+    }
+
+    pub fn start_replay(&mut self, replay: InputReplay) {
+        self.replay = Some(replay);
+    }
+
+    pub fn replay_header(&self) -> Option<&RecordingHeader> {
+        self.replay.as_ref().map(|replay| &replay.header)
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    // Hands input back to the player once the replay runs out, true on the tick that happens
+    pub fn finish_replay_if_done(&mut self) -> bool {
+        if self.replay.as_ref().map_or(false, |replay| replay.is_finished()) {
+            self.replay = None;
+            return true;
+        }
+        false
+    }
+
+    // Called every frame
+    pub fn poll(&mut self, ctx: &Context) {
+        if self.replay.is_some() {
+            return;
+        }
+        for key in input::get_keys_down(ctx) {
+            self.pending.insert(Self::key_name(*key));
+        }
+        for button in [MouseButton::Left, MouseButton::Middle, MouseButton::Right] {
+            if input::is_mouse_button_down(ctx, button) {
+                self.pending_mouse.insert(Self::mouse_button_name(button).unwrap().to_string());
+            }
+        }
+        self.mouse_position = input::get_mouse_position(ctx);
+    }
+
+    // Scripted input for headless runs, shows up on the next tick as if it was polled
+    pub fn inject(&mut self, state: &InputState) {
+        self.pending.extend(state.keys_down.iter().cloned());
+        self.pending_mouse.extend(state.mouse_down.iter().cloned());
+        self.mouse_position = Vec2::new(state.mouse_x, state.mouse_y);
+    }
+
+    // Called once per tick, everything the scripts see comes from the state made here
+    pub fn advance_tick(&mut self) {
+        let next = match &mut self.replay {
+            Some(replay) => replay.next_state(),
+            None => InputState {
+                keys_down: std::mem::take(&mut self.pending),
+                mouse_down: std::mem::take(&mut self.pending_mouse),
+                mouse_x: self.mouse_position.x,
+                mouse_y: self.mouse_position.y,
+            },
+        };
+        self.previous = std::mem::replace(&mut self.current, next);
+
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(e) = recorder.record(&self.previous, &self.current) {
+                println!("Failed to write input recording, stopping it: {}", e);
+                self.recorder = None;
+            }
+        }
+    }
+
+    pub fn mouse_position(&self) -> Vec2<f32> {
+        Vec2::new(self.current.mouse_x, self.current.mouse_y)
+    }
+
+    pub fn is_mouse_down(&self, button: &str) -> bool {
+        self.current.mouse_down.contains(button)
+    }
+
+    pub fn is_key_down(&self, key: &str) -> bool {
+        self.current.keys_down.contains(&key.to_lowercase())
+    }
+
+    pub fn pressed_keys(&self) -> Vec<String> {
+        self.current.keys_down.difference(&self.previous.keys_down).cloned().collect()
+    }
+
+    pub fn released_keys(&self) -> Vec<String> {
+        self.previous.keys_down.difference(&self.current.keys_down).cloned().collect()
+    }
+
+    pub fn held_keys(&self) -> Vec<String> {
+        self.current.keys_down.iter().cloned().collect()
+    }
+
+    fn action_down_in(&self, action: &str, state: &InputState) -> bool {
+        match self.bindings.get(action) {
+            Some(keys) => keys.iter().any(|key| state.keys_down.contains(key)),
+            None => false,
+        }
+    }
+
+    pub fn is_action_down(&self, action: &str) -> bool {
+        self.action_down_in(action, &self.current)
+    }
+
+    pub fn is_action_pressed(&self, action: &str) -> bool {
+        self.action_down_in(action, &self.current) && !self.action_down_in(action, &self.previous)
+    }
+
+    pub fn pressed_actions(&self) -> Vec<String> {
+        self.bindings.keys().filter(|action| self.is_action_pressed(action)).cloned().collect()
+    }
+
+    pub fn released_actions(&self) -> Vec<String> {
+        self.bindings.keys()
+            .filter(|action| !self.action_down_in(action, &self.current) && self.action_down_in(action, &self.previous))
+            .cloned()
+            .collect()
+    }
+
+    pub fn bind(&mut self, action: &str, keys: Vec<String>) {
+        self.bindings.insert(action.to_string(), keys.iter().map(|k| k.to_lowercase()).collect());
+    }
+
+    pub fn unbind(&mut self, action: &str) {
+        self.bindings.remove(action);
+    }
+}
@@ -0,0 +1,157 @@
+use std::io::BufRead;
+
+const RECORDING_FORMAT_VERSION: u32 = 2;
+
+// First line of a recording, the rest is one InputFrame per line
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordingHeader {
+    pub version: u32,
+    pub game_id: String,
+    pub seed: u64,
+    // The save file when recording started, None if there wasn't one. Replays start from it instead of the player's save.
+    #[serde(default)]
+    pub save: Option<String>,
+    // The data values the scripts started with
+    #[serde(default)]
+    pub data_values: HashMap<String, ScriptValue>,
+}
+
+// Only ticks where something changed get written, everything else carries over from the last frame
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct InputFrame {
+    pub t: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mouse: Option<[f32; 2]>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keys_pressed: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keys_released: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mouse_pressed: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mouse_released: Vec<String>,
+}
+
+impl InputFrame {
+    pub fn diff(t: u64, previous: &InputState, current: &InputState) -> InputFrame {
+        let moved = previous.mouse_x != current.mouse_x || previous.mouse_y != current.mouse_y;
+        InputFrame {
+            t,
+            mouse: if moved { Some([current.mouse_x, current.mouse_y]) } else { None },
+            keys_pressed: current.keys_down.difference(&previous.keys_down).cloned().collect(),
+            keys_released: previous.keys_down.difference(&current.keys_down).cloned().collect(),
+            mouse_pressed: current.mouse_down.difference(&previous.mouse_down).cloned().collect(),
+            mouse_released: previous.mouse_down.difference(&current.mouse_down).cloned().collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mouse.is_none() && self.keys_pressed.is_empty() && self.keys_released.is_empty()
+            && self.mouse_pressed.is_empty() && self.mouse_released.is_empty()
+    }
+
+    pub fn apply(&self, state: &mut InputState) {
+        if let Some([x, y]) = self.mouse {
+            state.mouse_x = x;
+            state.mouse_y = y;
+        }
+        for key in &self.keys_released {
+            state.keys_down.remove(key);
+        }
+        state.keys_down.extend(self.keys_pressed.iter().cloned());
+        for button in &self.mouse_released {
+            state.mouse_down.remove(button);
+        }
+        state.mouse_down.extend(self.mouse_pressed.iter().cloned());
+    }
+}
+
+pub struct InputRecorder {
+    writer: BufWriter<File>,
+    tick: u64,
+}
+
+impl InputRecorder {
+    pub fn create(path: &Path, header: &RecordingHeader) -> std::io::Result<InputRecorder> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{}", serde_json::to_string(header)?)?;
+        writer.flush()?;
+        Ok(InputRecorder { writer, tick: 0 })
+    }
+
+    // Flushed every time so a crash still leaves a usable recording for the bug report
+    pub fn record(&mut self, previous: &InputState, current: &InputState) -> std::io::Result<()> {
+        let frame = InputFrame::diff(self.tick, previous, current);
+        self.tick += 1;
+        if frame.is_empty() {
+            return Ok(());
+        }
+        writeln!(self.writer, "{}", serde_json::to_string(&frame)?)?;
+        self.writer.flush()
+    }
+}
+
+// An empty frame at the end marks how long the recording ran, so the replay doesn't stop at the last input
+impl Drop for InputRecorder {
+    fn drop(&mut self) {
+        let end = InputFrame { t: self.tick, ..InputFrame::default() };
+        if let Ok(json) = serde_json::to_string(&end) {
+            let _ = writeln!(self.writer, "{}", json);
+            let _ = self.writer.flush();
+        }
+    }
+}
+
+pub struct InputReplay {
+    pub header: RecordingHeader,
+    frames: Vec<InputFrame>,
+    next_frame: usize,
+    tick: u64,
+    state: InputState,
+}
+
+impl InputReplay {
+    pub fn load(path: &Path) -> Result<InputReplay, String> {
+        let file = File::open(path).map_err(|e| format!("Failed to open recording {:?}: {}", path, e))?;
+        let mut lines = BufReader::new(file).lines();
+
+        let header_line = lines.next()
+            .ok_or_else(|| format!("Recording {:?} is empty.", path))?
+            .map_err(|e| e.to_string())?;
+        let header: RecordingHeader = serde_json::from_str(&header_line)
+            .map_err(|e| format!("Recording {:?} has an invalid header: {}", path, e))?;
+        if header.version > RECORDING_FORMAT_VERSION {
+            return Err(format!("Recording {:?} is from a newer engine version.", path));
+        }
+
+        let mut frames = Vec::new();
+        for (number, line) in lines.enumerate() {
+            let line = line.map_err(|e| e.to_string())?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let frame: InputFrame = serde_json::from_str(&line)
+                .map_err(|e| format!("Recording {:?} line {} is invalid: {}", path, number + 2, e))?;
+            frames.push(frame);
+        }
+
+        Ok(InputReplay { header, frames, next_frame: 0, tick: 0, state: InputState::default() })
+    }
+
+    // The input state for the next tick
+    pub fn next_state(&mut self) -> InputState {
+        while let Some(frame) = self.frames.get(self.next_frame) {
+            if frame.t > self.tick {
+                break;
+            }
+            frame.apply(&mut self.state);
+            self.next_frame += 1;
+        }
+        self.tick += 1;
+        self.state.clone()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next_frame >= self.frames.len()
+    }
+}
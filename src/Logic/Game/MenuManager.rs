@@ -4,44 +4,55 @@ use std::rc::Rc;
 use std::borrow::BorrowMut;
 use std::ops::Deref;
 use crate::GameLoader::Element;
+use crate::GameLoader::Menu;
+
+const SLOT_PICKER_MENU: &str = "SlotPicker";
+const SLOT_PICKER_SLOTS: usize = 3;
+const SLOT_PICKER_FONT_SIZE: i32 = 24;
+const MOD_LIST_MENU: &str = "ModList";
+// Used for pausing when game_info doesn't name a pause menu
+const PAUSE_MENU: &str = "Pause";
+// FadeSpeed is how much of a fade happens each tick, in percent. This is for menus that don't set it.
+const DEFAULT_FADE_SPEED: i32 = 5;
 
 #[derive(Clone)]
 pub struct MenuManager
 {
     curmenu: String,
     arrows_enabled: bool,
-    selected_button_id: String
+    selected_button_id: String,
+    slot_picker_generated: bool,
+    mod_list_generated: bool
 }
 
 impl MenuManager {
     pub fn new() -> MenuManager {
         let curmenu = String::new();
-        MenuManager { curmenu,  arrows_enabled: true, selected_button_id: String::new()}
+        MenuManager { curmenu,  arrows_enabled: true, selected_button_id: String::new(), slot_picker_generated: false, mod_list_generated: false}
     }
 
-    pub fn recache_buttons(ctx: &mut Context, engine: &mut EngineData, event_manager: &mut EventManager, cache: &mut CacheData)
+    pub fn recache_buttons(platform: &mut dyn Platform, engine: &mut EngineData, event_manager: &mut EventManager)
     {
         engine.buttons.clear();
-        let x_scale: f32 = (window::get_width(ctx) / 1280) as f32;
-        let y_scale: f32 = (window::get_height(ctx)  / 720) as f32;
-        for element in &engine.game.menus[&engine.menumgr.curmenu].elements {
+        let window_size = platform.window_size();
+        let x_scale: f32 = (window_size.0 / 1280) as f32;
+        let y_scale: f32 = (window_size.1 / 720) as f32;
+        for element in engine.game.menus[&engine.menumgr.curmenu].elements.iter() {
             let pos_offset: Vec2<f32> =
                 Vec2::new(element.x as f32 * 2.15 * x_scale, element.y as f32 * 2.15 * y_scale);
 
-            match element.r#type.as_str() {
-                ("Button") if !engine.buttons.contains_key(&element.id) && !element.hidden  => {
-                    let uid = format!("{}-{}", &element.text, &element.fontsize);
-
-                    let text = cache.texts
-                        .entry(uid.clone())
-                        .or_insert_with(|| Text::new(&element.text, cache.fonts[&element.fontsize].clone()));
-
-                    engine.buttons.insert(element.id.clone(), Button2D::new(ctx, pos_offset, None, Some(text), "button_clicked".to_string(), vec![element.id.to_string()]));
-                }
-                "Image" if !element.hidden && !element.sprite.is_empty() => {
-                    let texture = &cache.textures[&element.sprite];
-                    engine.buttons.insert(element.id.clone(), Button2D::new(ctx, pos_offset, Option::from(texture), None, "image_clicked".to_string(), vec![element.id.to_string()]));
-
+            let click = engine.extensions.menu_element(&element.r#type).and_then(|element_type| element_type.click);
+            match click {
+                Some(click) if !engine.buttons.contains_key(&element.id) && !element.hidden => {
+                    if click.sprite && element.sprite.is_empty() {
+                        continue;
+                    }
+                    let size = if click.sprite {
+                        platform.texture_size(&element.sprite)
+                    } else {
+                        platform.text_size(&element.text, element.fontsize)
+                    };
+                    engine.buttons.insert(element.id.clone(), Button2D::new(window_size, pos_offset, size, click.sprite, click.event, vec![element.id.to_string()]));
                 }
                 _ => {}
             }
@@ -55,7 +66,7 @@ impl MenuManager {
 
     pub fn is_button_selected(mut element_id: String, engine: &mut EngineData) -> bool
     {
-        let element_option = engine.game.menus.get_mut(&engine.menumgr.curmenu).unwrap().elements.iter_mut().find(|x| x.id == element_id);
+        let element_option = Rc::make_mut(&mut engine.game.menus.get_mut(&engine.menumgr.curmenu).unwrap().elements).iter_mut().find(|x| x.id == element_id);
         if let Some(element) = element_option {
             let button = &engine.buttons.get(&element.id);
             if button.is_some() {
@@ -72,7 +83,7 @@ impl MenuManager {
 
     pub fn set_element_text(mut element_id: &str, data: String, engine: &mut EngineData)
     {
-        let element_option = engine.game.menus.get_mut(&engine.menumgr.curmenu).unwrap().elements.iter_mut().find(|x| x.id == element_id);
+        let element_option = Rc::make_mut(&mut engine.game.menus.get_mut(&engine.menumgr.curmenu).unwrap().elements).iter_mut().find(|x| x.id == element_id);
         if let Some(element) = element_option {
             element.text = data;
         }
@@ -80,7 +91,7 @@ impl MenuManager {
 
     pub fn set_element_sprite(mut element_id: &str, data: String, engine: &mut EngineData)
     {
-        let element_option = engine.game.menus.get_mut(&engine.menumgr.curmenu).unwrap().elements.iter_mut().find(|x| x.id == element_id);
+        let element_option = Rc::make_mut(&mut engine.game.menus.get_mut(&engine.menumgr.curmenu).unwrap().elements).iter_mut().find(|x| x.id == element_id);
         if let Some(element) = element_option {
             element.sprite = data;
         }
@@ -88,7 +99,7 @@ impl MenuManager {
 
     pub fn show_element(mut element_id: &str, engine: &mut EngineData)
     {
-        let element_option = engine.game.menus.get_mut(&engine.menumgr.curmenu).unwrap().elements.iter_mut().find(|x| x.id == element_id);
+        let element_option = Rc::make_mut(&mut engine.game.menus.get_mut(&engine.menumgr.curmenu).unwrap().elements).iter_mut().find(|x| x.id == element_id);
         if let Some(element) = element_option {
             element.hidden = false;
         }
@@ -96,28 +107,344 @@ impl MenuManager {
 
     pub fn hide_element(mut element_id: &str, engine: &mut EngineData)
     {
-        let element_option = engine.game.menus.get_mut(&engine.menumgr.curmenu).unwrap().elements.iter_mut().find(|x| x.id == element_id);
+        let element_option = Rc::make_mut(&mut engine.game.menus.get_mut(&engine.menumgr.curmenu).unwrap().elements).iter_mut().find(|x| x.id == element_id);
         if let Some(element) = element_option {
             element.hidden = true;
         }
     }
 
     pub fn goto_menu(menu: &str, engine: &mut EngineData, event_manager: & mut EventManager)
+    {
+        let fade = Self::menu_fade(engine, Some(menu));
+        SceneManager::transition(engine, event_manager, Rc::new(MenuScene::new(menu)), fade);
+    }
+
+    // The menu being left fades out if it has FadeOut, the next one fades in if it has FadeIn.
+    // Offices don't have either, going into one fades back in as fast as the menu faded out.
+    pub fn menu_fade(engine: &EngineData, next_menu: Option<&str>) -> Fade
+    {
+        let leaving = match engine.scenes.top() {
+            SceneKind::Menu | SceneKind::Pause => engine.game.menus.get(&engine.menumgr.curmenu),
+            _ => None,
+        };
+        let fade_out = leaving.filter(|menu| menu.properties.FadeOut).map_or(0.0, Self::fade_time);
+        let fade_in = match next_menu {
+            Some(next_menu) => engine.game.menus.get(next_menu).filter(|menu| menu.properties.FadeIn).map_or(0.0, Self::fade_time),
+            None => fade_out,
+        };
+        Fade::new(fade_out, fade_in)
+    }
+
+    fn fade_time(menu: &Menu) -> f32
+    {
+        let speed = match menu.properties.FadeSpeed {
+            speed if speed > 0 => speed.min(100),
+            _ => DEFAULT_FADE_SPEED,
+        };
+        (100.0 / speed as f32).ceil() * TickManager::tick_length().as_secs_f32()
+    }
+
+    fn start_menu(menu: &str, engine: &mut EngineData, event_manager: & mut EventManager)
     {
         engine.logger.log("Menu Manager",format!("Going to: {}", menu).as_str());
-        engine.audio.kill_all();
+        let music = engine.game.menus[menu].properties.BackgroundMusic.clone();
+        // Fading in, the last scene's sounds fade out under the new music instead of cutting off
+        match engine.scenes.fade_in_time() {
+            Some(seconds) => {
+                engine.audio.fade_out_all(seconds);
+                engine.audio.play_faded(&music, true, seconds);
+            }
+            None => {
+                engine.audio.kill_all();
+                engine.audio.play(&music, true);
+            }
+        }
         engine.needs_recache = true;
         engine.menumgr.curmenu = menu.to_string();
-        engine.audio.play(&engine.game.menus[menu].properties.BackgroundMusic, true);
-        event_manager.kill_all_listeners();
-        engine.scene = 0;
-        event_manager.run_script(&engine.game.menus[menu].code);
-        event_manager.trigger_event("on_menu_start", &*Vec::new(),engine);
-        engine.clock.clone().stop();
-        engine.clock.clone().reset();
-        engine.clock.clone().start();
+        event_manager.run_script(&engine.game.menus[menu].code, ListenerScope::Menu(menu.to_string()));
+        let menu_lua = engine.game.lua_scripts.menus.get(menu).cloned().unwrap_or_default();
+        event_manager.run_lua_scripts(&menu_lua, ListenerScope::Menu(menu.to_string()), engine);
+        engine.clock.stop();
+        engine.clock.reset();
+        engine.clock.start();
+
+    }
+
+    // The menu pausing shows, none if the game doesn't have one
+    pub fn pause_menu(engine: &EngineData) -> Option<String>
+    {
+        let menu = match engine.game.game_info.pause_menu.as_str() {
+            "" => PAUSE_MENU,
+            menu => menu,
+        };
+        engine.game.menus.contains_key(menu).then(|| menu.to_string())
+    }
+
+    // Freezes the night under the menu, false if there's no night to pause or it already is
+    pub fn pause_game(menu: &str, engine: &mut EngineData, event_manager: &mut EventManager) -> bool
+    {
+        if engine.scenes.top() != SceneKind::Office {
+            return false;
+        }
+        let scene = PauseScene { menu: menu.to_string(), previous_menu: engine.menumgr.curmenu.clone() };
+        SceneManager::push(engine, event_manager, Rc::new(scene));
+        event_manager.trigger_event("on_pause", &[], engine);
+        true
+    }
+
+    pub fn resume_game(engine: &mut EngineData, event_manager: &mut EventManager) -> bool
+    {
+        if engine.scenes.top() != SceneKind::Pause || !SceneManager::pop(engine, event_manager) {
+            return false;
+        }
+        event_manager.trigger_event("on_resume", &[], engine);
+        true
+    }
+
+    // Games can make their own "SlotPicker" menu, otherwise a plain one gets generated from the save file
+    pub fn open_slot_picker(return_menu: &str, engine: &mut EngineData, event_manager: &mut EventManager)
+    {
+        // Rebuilt every time so the slot info is current
+        if !engine.game.menus.contains_key(SLOT_PICKER_MENU) || engine.menumgr.slot_picker_generated {
+            let menu = Self::build_slot_picker(return_menu, engine);
+            engine.game.menus.insert(SLOT_PICKER_MENU.to_string(), menu);
+            engine.menumgr.slot_picker_generated = true;
+        }
+        Self::goto_menu(SLOT_PICKER_MENU, engine, event_manager);
+    }
+
+    fn build_slot_picker(return_menu: &str, engine: &EngineData) -> Menu
+    {
+        let mut slots: Vec<String> = (1..=SLOT_PICKER_SLOTS).map(|i| i.to_string()).collect();
+        for name in engine.save.slot_names() {
+            if !slots.contains(&name) {
+                slots.push(name);
+            }
+        }
+
+        let mut elements = vec![serde_json::json!({
+            "type": "StaticText", "id": "slot_picker_title", "text": "Select a Save Slot",
+            "fontsize": SLOT_PICKER_FONT_SIZE, "x": 40, "y": 30,
+        })];
+        let mut code = Vec::new();
+        for (i, name) in slots.iter().enumerate() {
+            let text = match engine.save.slot(name) {
+                Some(slot) => format!("Slot {} - Night {} - {}:{:02}", name, slot.last_night, slot.play_time / 3600, slot.play_time / 60 % 60),
+                None => format!("Slot {} - Empty", name),
+            };
+            let id = format!("slot_picker_{}", name);
+            elements.push(serde_json::json!({
+                "type": "Button", "id": id, "text": text,
+                "fontsize": SLOT_PICKER_FONT_SIZE, "x": 60, "y": 80 + i as i32 * 30,
+            }));
+            code.push(serde_json::json!({
+                "block": "button_clicked", "args": [id],
+                "subcode": [
+                    { "block": "select_slot", "args": [name] },
+                    { "block": "goto_menu", "args": [return_menu] },
+                ],
+            }));
+        }
+        elements.push(serde_json::json!({
+            "type": "Button", "id": "slot_picker_back", "text": "Back",
+            "fontsize": SLOT_PICKER_FONT_SIZE, "x": 60, "y": 100 + slots.len() as i32 * 30,
+        }));
+        code.push(serde_json::json!({
+            "block": "button_clicked", "args": ["slot_picker_back"],
+            "subcode": [{ "block": "goto_menu", "args": [return_menu] }],
+        }));
+
+        // Built from json since some of the menu properties are private to the loader
+        serde_json::from_value(serde_json::json!({
+            "code": code,
+            "elements": elements,
+            "properties": {
+                "ButtonArrows": true,
+                "Panorama": false,
+                "ButtonArrowStr": ">>",
+                "ButtonArrowColor": "255,255,255",
+                "ButtonArrowFont": "Arial",
+            },
+        })).expect("Generated slot picker menu is invalid")
+    }
+
+    // Same deal as the slot picker, a game's own "ModList" menu wins over the generated one
+    pub fn open_mod_list(return_menu: &str, engine: &mut EngineData, event_manager: &mut EventManager)
+    {
+        if !engine.game.menus.contains_key(MOD_LIST_MENU) || engine.menumgr.mod_list_generated {
+            let menu = Self::build_mod_list(return_menu, engine);
+            engine.game.menus.insert(MOD_LIST_MENU.to_string(), menu);
+            engine.menumgr.mod_list_generated = true;
+        }
+        Self::goto_menu(MOD_LIST_MENU, engine, event_manager);
+    }
+
+    fn build_mod_list(return_menu: &str, engine: &EngineData) -> Menu
+    {
+        // The slot picker's font size, so the font is already loaded
+        let mut elements = vec![serde_json::json!({
+            "type": "StaticText", "id": "mod_list_title", "text": "Mods",
+            "fontsize": SLOT_PICKER_FONT_SIZE, "x": 40, "y": 30,
+        })];
+        let mut code = Vec::new();
+        let mut y = 80;
+        if engine.mods.mods.is_empty() {
+            elements.push(serde_json::json!({
+                "type": "StaticText", "id": "mod_list_empty", "text": "No mods installed",
+                "fontsize": SLOT_PICKER_FONT_SIZE, "x": 60, "y": y,
+            }));
+            y += 30;
+        }
+        for info in &engine.mods.mods {
+            let mut text = format!("[{}] {} {}", if info.enabled { "on" } else { "off" }, info.manifest.name, info.manifest.version);
+            if info.enabled && info.enabled_at_start && !info.loaded {
+                text.push_str(" (failed to load)");
+            }
+            let id = format!("mod_list_{}", info.manifest.id);
+            elements.push(serde_json::json!({
+                "type": "Button", "id": id, "text": text,
+                "fontsize": SLOT_PICKER_FONT_SIZE, "x": 60, "y": y,
+            }));
+            code.push(serde_json::json!({
+                "block": "button_clicked", "args": [id],
+                "subcode": [
+                    { "block": "toggle_mod", "args": [info.manifest.id] },
+                    { "block": "open_mod_list", "args": [return_menu] },
+                ],
+            }));
+            y += 30;
+        }
+
+        // Problems get a line each so players can tell why a mod isn't doing anything
+        let notes = engine.mods.errors.iter().chain(engine.mods.conflicts.iter());
+        let restart = engine.mods.needs_restart().then(|| "Restart the game to apply changes".to_string());
+        for (i, note) in restart.iter().chain(notes).enumerate() {
+            elements.push(serde_json::json!({
+                "type": "StaticText", "id": format!("mod_list_note_{}", i), "text": note,
+                "fontsize": SLOT_PICKER_FONT_SIZE, "x": 60, "y": y + 10,
+            }));
+            y += 30;
+        }
+
+        elements.push(serde_json::json!({
+            "type": "Button", "id": "mod_list_back", "text": "Back",
+            "fontsize": SLOT_PICKER_FONT_SIZE, "x": 60, "y": y + 20,
+        }));
+        code.push(serde_json::json!({
+            "block": "button_clicked", "args": ["mod_list_back"],
+            "subcode": [{ "block": "goto_menu", "args": [return_menu] }],
+        }));
+
+        serde_json::from_value(serde_json::json!({
+            "code": code,
+            "elements": elements,
+            "properties": {
+                "ButtonArrows": true,
+                "Panorama": false,
+                "ButtonArrowStr": ">>",
+                "ButtonArrowColor": "255,255,255",
+                "ButtonArrowFont": "Arial",
+            },
+        })).expect("Generated mod list menu is invalid")
+    }
+}
+
+// Menus from game.json, goto_menu switches to one
+pub struct MenuScene {
+    menu: String,
+}
 
+impl MenuScene {
+    pub fn new(menu: &str) -> MenuScene {
+        MenuScene { menu: menu.to_string() }
     }
 }
 
+impl Scene for MenuScene {
+    fn kind(&self) -> SceneKind {
+        SceneKind::Menu
+    }
+
+    fn enter(&self, engine: &mut EngineData, event_manager: &mut EventManager) {
+        MenuManager::start_menu(&self.menu, engine, event_manager);
+    }
+
+    fn exit(&self, _engine: &mut EngineData, event_manager: &mut EventManager) {
+        event_manager.kill_scopes(|scope| matches!(scope, ListenerScope::Menu(_)));
+    }
+
+    // Held back until a fade in is done so menu scripts don't start on a black screen
+    fn shown(&self, engine: &mut EngineData, event_manager: &mut EventManager) {
+        event_manager.trigger_event("on_menu_start", &[], engine);
+    }
+
+    fn update(&self, ctx: &mut Context, state: &mut GameState) -> tetra::Result {
+        MenuManager::update(ctx, state)
+    }
+
+    fn draw(&self, ctx: &mut Context, engine: &mut EngineData, cache: &mut CacheData) -> tetra::Result {
+        if engine.needs_recache {
+            return Ok(());
+        }
+        MenuRenderer::render(ctx, engine, cache)
+    }
+
+    fn recache_buttons(&self, platform: &mut dyn Platform, engine: &mut EngineData, event_manager: &mut EventManager) {
+        MenuManager::recache_buttons(platform, engine, event_manager);
+    }
+}
+
+// A menu over the frozen office. The office stays on screen but stops ticking, and its sounds wait where they were.
+pub struct PauseScene {
+    menu: String,
+    // The office doesn't use curmenu, but it goes back to what it was so %game(menu) doesn't change
+    previous_menu: String,
+}
+
+impl Scene for PauseScene {
+    fn kind(&self) -> SceneKind {
+        SceneKind::Pause
+    }
+
+    fn enter(&self, engine: &mut EngineData, event_manager: &mut EventManager) {
+        engine.logger.log("Menu Manager", format!("Pausing with: {}", self.menu).as_str());
+        engine.clock.pause();
+        engine.audio.pause_all();
+        engine.menumgr.curmenu = self.menu.clone();
+        engine.audio.play(&engine.game.menus[&self.menu].properties.BackgroundMusic, true);
+        event_manager.run_script(&engine.game.menus[&self.menu].code, ListenerScope::Menu(self.menu.clone()));
+        let menu_lua = engine.game.lua_scripts.menus.get(&self.menu).cloned().unwrap_or_default();
+        event_manager.run_lua_scripts(&menu_lua, ListenerScope::Menu(self.menu.clone()), engine);
+    }
+
+    fn shown(&self, engine: &mut EngineData, event_manager: &mut EventManager) {
+        event_manager.trigger_event("on_menu_start", &[], engine);
+    }
+
+    fn exit(&self, engine: &mut EngineData, event_manager: &mut EventManager) {
+        event_manager.kill_scopes(|scope| matches!(scope, ListenerScope::Menu(_)));
+        engine.menumgr.curmenu = self.previous_menu.clone();
+        engine.audio.resume_all();
+        engine.clock.resume();
+    }
+
+    fn update(&self, ctx: &mut Context, state: &mut GameState) -> tetra::Result {
+        MenuManager::update(ctx, state)
+    }
+
+    fn draw(&self, ctx: &mut Context, engine: &mut EngineData, cache: &mut CacheData) -> tetra::Result {
+        if engine.needs_recache {
+            return Ok(());
+        }
+        MenuRenderer::draw_menu(ctx, engine, cache)
+    }
+
+    fn recache_buttons(&self, platform: &mut dyn Platform, engine: &mut EngineData, event_manager: &mut EventManager) {
+        MenuManager::recache_buttons(platform, engine, event_manager);
+    }
+
+    fn draws_below(&self) -> bool {
+        true
+    }
+}
 
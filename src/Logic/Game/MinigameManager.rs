@@ -0,0 +1,182 @@
+use crate::GameLoader::Minigame;
+
+// The minigame being played. Movement and triggers run on ticks so replays and headless runs match.
+pub struct MinigameManager {
+    pub curminigame: String,
+    // Top left of the player's collision box, in the minigame's unscaled pixels
+    pub player: Vec2<f32>,
+    // Where end_minigame goes back to when it isn't told
+    pub return_menu: String,
+    pub hidden_sprites: HashSet<String>,
+    // Triggers the player is standing in, they fire again only after being left
+    inside: HashSet<String>,
+    fired: HashSet<String>,
+}
+
+impl MinigameManager {
+    pub fn new() -> MinigameManager {
+        MinigameManager {
+            curminigame: String::new(),
+            player: Vec2::zero(),
+            return_menu: String::new(),
+            hidden_sprites: HashSet::new(),
+            inside: HashSet::new(),
+            fired: HashSet::new(),
+        }
+    }
+
+    pub fn goto_minigame(minigame: &str, engine: &mut EngineData, event_manager: &mut EventManager)
+    {
+        if engine.scenes.current() == SceneKind::Menu {
+            engine.minigamemgr.return_menu = engine.menumgr.curmenu.clone();
+        }
+        SceneManager::switch(engine, event_manager, Rc::new(MinigameScene { minigame: minigame.to_string() }));
+    }
+
+    fn start_minigame(minigame: &str, engine: &mut EngineData, event_manager: &mut EventManager)
+    {
+        engine.logger.log("Minigame Manager", format!("Starting minigame: {}", minigame).as_str());
+        let definition = engine.game.minigames[minigame].clone();
+        engine.audio.kill_all();
+        engine.needs_recache = true;
+
+        let state = &mut engine.minigamemgr;
+        state.curminigame = minigame.to_string();
+        state.player = Vec2::new(definition.player.x, definition.player.y);
+        state.hidden_sprites = definition.sprites.iter().filter(|sprite| sprite.hidden).map(|sprite| sprite.id.clone()).collect();
+        state.inside.clear();
+        state.fired.clear();
+        engine.audio.play(&definition.music, true);
+
+        event_manager.run_script(&definition.code, ListenerScope::Minigame(minigame.to_string()));
+        let minigame_lua = engine.game.lua_scripts.minigames.get(minigame).cloned().unwrap_or_default();
+        event_manager.run_lua_scripts(&minigame_lua, ListenerScope::Minigame(minigame.to_string()), engine);
+        engine.clock.stop();
+        engine.clock.reset();
+        engine.clock.start();
+        event_manager.trigger_event("on_minigame_start", &[minigame.to_string()], engine);
+    }
+
+    pub fn end_minigame(menu: &str, engine: &mut EngineData, event_manager: &mut EventManager)
+    {
+        let minigame = engine.minigamemgr.curminigame.clone();
+        event_manager.trigger_event("on_minigame_end", &[minigame], engine);
+        // The end event might have moved on already
+        if engine.scenes.current() == SceneKind::Minigame {
+            MenuManager::goto_menu(menu, engine, event_manager);
+        }
+    }
+
+    // Runs once per tick while a minigame is up
+    pub fn tick(engine: &mut EngineData, event_manager: &mut EventManager) {
+        let minigame = engine.minigamemgr.curminigame.clone();
+        let definition = match engine.game.minigames.get(&minigame) {
+            Some(definition) => definition,
+            None => return,
+        };
+
+        let input = &engine.input;
+        let axis = |negative: &str, positive: &str| {
+            input.is_action_down(positive) as i32 as f32 - input.is_action_down(negative) as i32 as f32
+        };
+        let step = Vec2::new(axis("move_left", "move_right"), axis("move_up", "move_down")) * definition.player.speed;
+
+        // One axis at a time so the player slides along walls instead of sticking to them
+        let mut player = engine.minigamemgr.player;
+        if step.x != 0.0 && !Self::collides(definition, &engine.minigamemgr.hidden_sprites, Vec2::new(player.x + step.x, player.y)) {
+            player.x += step.x;
+        }
+        if step.y != 0.0 && !Self::collides(definition, &engine.minigamemgr.hidden_sprites, Vec2::new(player.x, player.y + step.y)) {
+            player.y += step.y;
+        }
+        engine.minigamemgr.player = player;
+
+        let size = Vec2::new(definition.player.width, definition.player.height);
+        let mut entered = Vec::new();
+        let mut inside = HashSet::new();
+        for trigger in &definition.triggers {
+            let overlaps = Self::overlaps(player, size, Vec2::new(trigger.x, trigger.y), Vec2::new(trigger.width, trigger.height));
+            if !overlaps {
+                continue;
+            }
+            inside.insert(trigger.id.clone());
+            let state = &engine.minigamemgr;
+            if !state.inside.contains(&trigger.id) && !(trigger.once && state.fired.contains(&trigger.id)) {
+                entered.push(trigger.id.clone());
+            }
+        }
+        engine.minigamemgr.inside = inside;
+
+        for id in entered {
+            // A trigger can end the minigame or start another, the rest belong to the old one
+            if engine.scenes.current() != SceneKind::Minigame || engine.minigamemgr.curminigame != minigame {
+                break;
+            }
+            engine.minigamemgr.fired.insert(id.clone());
+            event_manager.trigger_event("on_minigame_trigger", &[id, minigame.clone()], engine);
+        }
+    }
+
+    // Whether the player's box at this spot hits a solid tile or sprite. Off the map counts as solid.
+    fn collides(definition: &Minigame, hidden: &HashSet<String>, position: Vec2<f32>) -> bool {
+        let size = Vec2::new(definition.player.width, definition.player.height);
+        let tile_size = definition.tile_size.max(1) as f32;
+        let rows = definition.map.len() as i32;
+
+        let first = (position / tile_size).map(|v| v.floor() as i32);
+        // Just inside the far edge, a box exactly one tile wide only covers one tile
+        let last = ((position + size - Vec2::broadcast(0.01)) / tile_size).map(|v| v.floor() as i32);
+        for row in first.y..=last.y {
+            for column in first.x..=last.x {
+                if row < 0 || column < 0 || row >= rows {
+                    return true;
+                }
+                let line = &definition.map[row as usize];
+                let tile = match line.chars().nth(column as usize) {
+                    Some(tile) => tile,
+                    None => return true,
+                };
+                if definition.tiles.get(&tile.to_string()).map_or(false, |tile| tile.solid) {
+                    return true;
+                }
+            }
+        }
+
+        definition.sprites.iter()
+            .filter(|sprite| sprite.solid && !hidden.contains(&sprite.id))
+            .any(|sprite| {
+                let sprite_size = Vec2::new(sprite.width.unwrap_or(tile_size), sprite.height.unwrap_or(tile_size));
+                Self::overlaps(position, size, Vec2::new(sprite.x, sprite.y), sprite_size)
+            })
+    }
+
+    fn overlaps(a: Vec2<f32>, a_size: Vec2<f32>, b: Vec2<f32>, b_size: Vec2<f32>) -> bool {
+        a.x < b.x + b_size.x && b.x < a.x + a_size.x && a.y < b.y + b_size.y && b.y < a.y + a_size.y
+    }
+}
+
+pub struct MinigameScene {
+    minigame: String,
+}
+
+impl Scene for MinigameScene {
+    fn kind(&self) -> SceneKind {
+        SceneKind::Minigame
+    }
+
+    fn enter(&self, engine: &mut EngineData, event_manager: &mut EventManager) {
+        MinigameManager::start_minigame(&self.minigame, engine, event_manager);
+    }
+
+    fn exit(&self, _engine: &mut EngineData, event_manager: &mut EventManager) {
+        event_manager.kill_scopes(|scope| matches!(scope, ListenerScope::Minigame(_)));
+    }
+
+    fn draw(&self, ctx: &mut Context, engine: &mut EngineData, cache: &mut CacheData) -> tetra::Result {
+        MinigameRenderer::render(ctx, engine, cache)
+    }
+
+    fn tick(&self, engine: &mut EngineData, event_manager: &mut EventManager) {
+        MinigameManager::tick(engine, event_manager);
+    }
+}
@@ -0,0 +1,91 @@
+// FNaF 1 timings: an in-game hour is 89 seconds and one usage bar drains 1% every 9.6 seconds
+const TICKS_PER_HOUR: i32 = 89 * TICKS_PER_SECOND as i32;
+const DRAIN_PER_PERCENT: i32 = 192;
+// The night is won when the clock hits 6 AM
+const LAST_HOUR: i32 = 6;
+
+// The clock and power for the night being played
+pub struct NightManager;
+
+impl NightManager {
+    pub fn start_night(engine: &mut EngineData) {
+        let power = &engine.game.offices[&engine.officemgr.curoffice].power;
+        let powerout = engine.game.offices[&engine.officemgr.curoffice].animations.powerout.clone();
+        let game_data = &mut engine.officemgr.game_data;
+        game_data.power.level = if power.starting_level > 0 { power.starting_level } else { 100 };
+        game_data.power.enabled = power.enabled;
+        game_data.power.ucn = power.ucn;
+        game_data.power.usage = 0;
+        game_data.power.drain = 0;
+        game_data.power.animatronic_jumpscare = power.animatronic.clone();
+        game_data.power.power_out_animation = powerout;
+        game_data.time = 0;
+        game_data.time_ticks = 0;
+        game_data.paused = false;
+    }
+
+    // One bar for just being there, plus one for every closed door, lit light and the camera
+    pub fn power_usage(game_data: &GameData) -> i32 {
+        1 + game_data.office.doors.values().filter(|door| door.is_closed).count() as i32
+            + game_data.office.lights.values().filter(|light| light.is_on).count() as i32
+            + game_data.player.is_camera_up as i32
+    }
+
+    // Runs once per tick during a night, only for offices with night_clock set
+    pub fn tick(engine: &mut EngineData, event_manager: &mut EventManager) {
+        let enabled = engine.game.offices.get(&engine.officemgr.curoffice).map_or(false, |office| office.night_clock);
+        if !enabled || engine.officemgr.game_data.paused {
+            return;
+        }
+
+        let game_data = &mut engine.officemgr.game_data;
+        game_data.time_ticks += 1;
+        let new_hour = game_data.time_ticks >= TICKS_PER_HOUR;
+        if new_hour {
+            game_data.time_ticks = 0;
+            game_data.time += 1;
+        }
+
+        let mut power_out = false;
+        if game_data.power.enabled && game_data.power.level > 0 {
+            game_data.power.usage = Self::power_usage(game_data);
+            game_data.power.drain += game_data.power.usage;
+            if game_data.power.drain >= DRAIN_PER_PERCENT {
+                game_data.power.drain -= DRAIN_PER_PERCENT;
+                game_data.power.level -= 1;
+                power_out = game_data.power.level <= 0;
+            }
+        }
+
+        if power_out {
+            Self::power_out(engine, event_manager);
+        }
+        if new_hour {
+            let hour = engine.officemgr.game_data.time;
+            event_manager.trigger_event("on_hour", &[hour.to_string()], engine);
+            if hour >= LAST_HOUR {
+                // Stops the clock so the night only ends once, scripts decide where to go from here
+                engine.officemgr.game_data.paused = true;
+                let night = engine.officemgr.game_data.night;
+                event_manager.trigger_event("on_night_end", &[night.to_string()], engine);
+            }
+        }
+    }
+
+    fn power_out(engine: &mut EngineData, event_manager: &mut EventManager) {
+        let game_data = &mut engine.officemgr.game_data;
+        game_data.power.level = 0;
+        game_data.power.usage = 0;
+        for door in game_data.office.doors.values_mut() {
+            door.is_closed = false;
+            door.button.is_on = false;
+            door.button.clickable = false;
+        }
+        for light in game_data.office.lights.values_mut() {
+            light.is_on = false;
+            light.clickable = false;
+        }
+        game_data.player.putdown();
+        event_manager.trigger_event("on_power_out", &[], engine);
+    }
+}
@@ -0,0 +1,74 @@
+const SNAPSHOT_FORMAT_VERSION: u32 = 1;
+
+// Everything needed to put a night back the way it was: the office's game data, script variables,
+// pending waits/timers and the tick. Listeners come back from the night's scripts on restore.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct NightSnapshot {
+    pub version: u32,
+    pub game_id: String,
+    pub office: String,
+    pub tick: u64,
+    // The RNG gets reseeded with this on restore, so every restore of the snapshot plays out the same
+    pub rng_seed: u64,
+    pub game_data: GameData,
+    pub scripts: ScriptState,
+}
+
+impl NightSnapshot {
+    pub fn capture(engine: &EngineData, event_manager: &EventManager) -> NightSnapshot {
+        // Drawn from a copy, taking a snapshot can't change what the running night rolls next
+        let rng_seed: u64 = engine.rng.clone().gen();
+        NightSnapshot {
+            version: SNAPSHOT_FORMAT_VERSION,
+            game_id: engine.save.game_id().to_string(),
+            office: engine.officemgr.curoffice.clone(),
+            tick: engine.clock.get_current_tick(),
+            rng_seed,
+            game_data: engine.officemgr.game_data.clone(),
+            scripts: event_manager.capture_script_state(),
+        }
+    }
+
+    pub fn restore(self, engine: &mut EngineData, event_manager: &mut EventManager) -> Result<(), String> {
+        if self.game_id != engine.save.game_id() {
+            return Err(format!("Snapshot belongs to '{}', not '{}'.", self.game_id, engine.save.game_id()));
+        }
+        if !engine.game.offices.contains_key(&self.office) {
+            return Err(format!("Snapshot office '{}' doesn't exist.", self.office));
+        }
+
+        // Only reload the office when the snapshot is from somewhere else, otherwise the running scripts are already right
+        if engine.scenes.current() != SceneKind::Office || engine.officemgr.curoffice != self.office || engine.officemgr.game_data.night != self.game_data.night {
+            OfficeManager::goto_office(self.office.clone(), self.game_data.night, engine, event_manager);
+        }
+
+        engine.officemgr.game_data = self.game_data;
+        // Hover state is whatever the mouse is on now, not what it was on back then
+        engine.officemgr.hovered_id = String::new();
+        for object in engine.officemgr.game_data.office.objects.values_mut() {
+            object.hovered = false;
+        }
+        engine.clock.set_tick(self.tick);
+        engine.rng = StdRng::seed_from_u64(self.rng_seed);
+        event_manager.restore_script_state(self.scripts);
+        engine.needs_recache = true;
+        Ok(())
+    }
+
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, json)
+    }
+
+    pub fn read(path: &Path) -> Result<NightSnapshot, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+        let snapshot: NightSnapshot = serde_json::from_str(&content).map_err(|e| format!("Snapshot {:?} is corrupted: {}", path, e))?;
+        if snapshot.version > SNAPSHOT_FORMAT_VERSION {
+            return Err(format!("Snapshot {:?} is from a newer engine version.", path));
+        }
+        Ok(snapshot)
+    }
+}
@@ -8,6 +8,9 @@ pub struct OfficeManager
     pub Loading_Lock: bool,
     pub Loaded: bool,
     pub scroll_x: f32,
+    // Set when the office's buttons are cached, scrolling runs on ticks and has no window to ask
+    pub view_width: f32,
+    pub scroll_max: f32,
     pub hovered_id: String,
 }
 
@@ -18,46 +21,38 @@ impl OfficeManager {
             game_data: GameData::new("", 0),
             Loading_Lock: false,
             Loaded: false, scroll_x: 0.0,
+            view_width: 0.0,
+            scroll_max: 0.0,
             hovered_id: String::new()
         }
     }
 
-    pub fn recache_buttons(ctx: &mut Context, engine: &mut EngineData, event_manager: &mut EventManager, cache: &mut CacheData)
+    pub fn recache_buttons(platform: &mut dyn Platform, engine: &mut EngineData, event_manager: &mut EventManager)
     {
         engine.buttons.clear();
-        for object in &engine.game.offices[&engine.officemgr.curoffice].objects {
+        let window_size = platform.window_size();
+        let state_sprite = engine.officemgr.game_data.office.states.get(&engine.officemgr.game_data.office.state).cloned().unwrap_or_default();
+        engine.officemgr.view_width = window_size.0 as f32;
+        let state_width = if state_sprite.is_empty() { 0.0 } else { platform.texture_size(&state_sprite).x };
+        engine.officemgr.scroll_max = state_width - window_size.0 as f32;
+        for object in engine.game.offices[&engine.officemgr.curoffice].objects.iter() {
             let pos_offset: Vec2<f32> =
                 Vec2::new(object.position[0] as f32 * 2.13, object.position[1] as f32 * 2.13);
 
-            match object.r#type.as_str() {
-                "sprite" | "light_button" | "door_button" => { // if engine.officemgr.Office.sprites[&object.id].visible && !object.sprite.is_empty() => {
-                    let texture = &cache.textures[&object.sprite];
-                    engine.buttons.insert(object.id.clone(), Button2D::new(ctx, pos_offset, Option::from(texture), None, "on_sprite_clicked".to_string(), vec![object.id.to_string()]));
-                } /*
-                "door_button" => { // if engine.officemgr.Office.sprites[&object.id].visible && !object.sprite.is_empty() => {
-                    let texture = &cache.textures[&object.sprite];
-                    engine.buttons.insert(object.id.clone(), Button2D::new(ctx, pos_offset, Option::from(texture), None, "on_sprite_clicked".to_string(), vec![object.id.to_string()]));
-                }
-                "light_button" => { // if engine.officemgr.Office.sprites[&object.id].visible && !object.sprite.is_empty() => {
-                    let texture = &cache.textures[&object.sprite];
-                    engine.buttons.insert(object.id.clone(), Button2D::new(ctx, pos_offset, Option::from(texture), None, "on_sprite_clicked".to_string(), vec![object.id.to_string()]));
-                }*/
-                _ => {}
+            let click = engine.extensions.office_object(&object.r#type).and_then(|object_type| object_type.click);
+            if let Some(click) = click {
+                // Office objects have no text, they're always as big as their sprite
+                let size = platform.texture_size(&object.sprite);
+                engine.buttons.insert(object.id.clone(), Button2D::new(window_size, pos_offset, size, true, click.event, vec![object.id.to_string()]));
             }
         }
     }
 
 
-    pub fn update(ctx: &mut Context, state: &mut GameState) -> tetra::Result
-    {
-        let viewport_width = window::get_width(ctx) as f32;
-
-        let curstate_width = state.cache.textures
-            .get(&state.engine.officemgr.game_data.office.states[&state.engine.officemgr.game_data.office.state])
-            .map_or(0.0, |curstate| curstate.width() as f32);
-
-        let mouse_position_x = input::get_mouse_position(ctx).x;
-        let delta_time = tetra::time::get_delta_time(ctx).as_secs_f32();
+    // Once a tick while the office is on top, so replays scroll to the same spot and hover the same objects
+    pub fn scroll(engine: &mut EngineData) {
+        let viewport_width = engine.officemgr.view_width;
+        let mouse_position_x = engine.input.mouse_position().x;
 
         let scroll_speed = if mouse_position_x < viewport_width * 0.1 {
             550.0
@@ -75,10 +70,25 @@ impl OfficeManager {
             0.0
         };
 
-        state.engine.officemgr.scroll_x += scroll_speed * delta_time * (mouse_position_x - viewport_width * 0.5).signum();
-        state.engine.officemgr.scroll_x = state.engine.officemgr.scroll_x.clamp(0.0, curstate_width - viewport_width);
+        let tick_seconds = TickManager::tick_length().as_secs_f32();
+        engine.officemgr.scroll_x += scroll_speed * tick_seconds * (mouse_position_x - viewport_width * 0.5).signum();
+        engine.officemgr.scroll_x = engine.officemgr.scroll_x.clamp(0.0, engine.officemgr.scroll_max.max(0.0));
+    }
 
-        Ok(())
+    // Moving to another office or on to the next night stays in the office scene, anything else switches to it
+    pub fn goto_office(office: String, night: i32, engine: &mut EngineData, event_manager: &mut EventManager)
+    {
+        // Checked before switching, the office scene can't draw an office that isn't there
+        if !engine.game.offices.contains_key(&office) {
+            engine.logger.log_error("OfficeManager", format!("Office '{}' doesn't exist.", office).as_str());
+            return;
+        }
+        if engine.scenes.current() == SceneKind::Office {
+            Self::init_office(office, night, engine, event_manager);
+        } else {
+            let fade = MenuManager::menu_fade(engine, None);
+            SceneManager::transition(engine, event_manager, Rc::new(OfficeScene { office, night }), fade);
+        }
     }
 
     pub fn init_office(office: String, night: i32, engine: &mut EngineData, event_manager: &mut EventManager) //, mut scheduler: &Scheduler)
@@ -98,114 +108,165 @@ impl OfficeManager {
         engine.officemgr.Loading_Lock = true;
         engine.logger.log("Office Manager", "Initializing Office");
 
-        engine.audio.kill_all();
+        // Coming out of a faded menu its music fades out instead of cutting off
+        match engine.scenes.fade_in_time() {
+            Some(seconds) => engine.audio.fade_out_all(seconds),
+            None => engine.audio.kill_all(),
+        }
         // TODO: ambience
 
-        let mut sprites: HashMap<String, Sprite> = HashMap::new();
-        let mut objects: HashMap<String, Sprite> = HashMap::new();
-        let mut animations: HashMap<String, Animation> = HashMap::new();
-        let mut doors: HashMap<String, Door> = HashMap::new();
-        let mut lights: HashMap<String, Light> = HashMap::new();
-
-        for object in &engine.game.offices[&office].objects
+        // Each object type sets up its own state, the type comes from whichever extension registered it
+        let mut office_data = OfficeData::empty();
+        for object in engine.game.offices[&office].objects.iter()
         {
-            match object.r#type.as_str()
-            {
-                "sprite" =>
-                    {
-                        sprites.insert(object.id.clone(), Sprite {
-                            visible: true,
-                            above_panorama: false,
-                            hovered: false,
-                        });
-                        objects.insert(object.id.clone(), Sprite {
-                            visible: true,
-                            above_panorama: false,
-                            hovered: false,
-                        });
-                    },
-                "door_button" =>
-                    {
-                        objects.insert(object.id.clone(), Sprite {
-                            visible: true,
-                            above_panorama: false,
-                            hovered: false,
-                        });
-                    }
-                "animation" =>
-                    {
-                        animations.insert(object.id.clone(), Animation {
-                            id: object.animation.clone(),
-                            is_playing: true,
-                            is_reversed: false
-                        });
-                        objects.insert(object.id.clone(), Sprite {
-                            visible: true,
-                            above_panorama: false,
-                            hovered: false,
-                        });
-                    },
-                "door" =>
-                    {
-                        doors.insert(object.id.clone(), Door {
-                            animation: object.animation.clone(),
-                            is_closed: false,
-                            button: Button {
-                                is_on: false,
-                                clickable: true
-                            }
-                        });
-                        objects.insert(object.id.clone(), Sprite {
-                            visible: true,
-                            above_panorama: false,
-                            hovered: false,
-                        });
-                    },
-                "light_button" =>
-                    {
-                        lights.insert(object.id.clone(), Light {
-                            is_on: false,
-                            clickable: false,
-                        });
-                        objects.insert(object.id.clone(), Sprite {
-                            visible: true,
-                            above_panorama: false,
-                            hovered: false,
-                        });
-                    }
-                _ =>
-                    {
-                        engine.logger.log_error("OfficeManager", "Unknown Object Type: {:?}");
-                    }
+            match engine.extensions.office_object(&object.r#type) {
+                Some(object_type) => (object_type.init)(&mut office_data, object),
+                None => engine.logger.log_error("OfficeManager", format!("Unknown Object Type: {:?}", object.r#type).as_str()),
             }
         }
+        // Switching offices mid night keeps the night's scripts running
+        let new_night = !engine.officemgr.Loaded || engine.officemgr.game_data.night != night;
         engine.officemgr.hovered_id = String::new();
         engine.officemgr.game_data.night = night;
         engine.officemgr.curoffice = office.to_string();
         engine.officemgr.game_data.office.states = engine.game.offices[&office].states.clone();
-        engine.officemgr.game_data.office.sprites = sprites;
-        engine.officemgr.game_data.office.animations = animations;
-        engine.officemgr.game_data.office.lights = lights;
-        engine.officemgr.game_data.office.doors = doors;
-        engine.officemgr.game_data.office.objects = objects;
+        engine.officemgr.game_data.office.sprites = office_data.sprites;
+        engine.officemgr.game_data.office.animations = office_data.animations;
+        engine.officemgr.game_data.office.lights = office_data.lights;
+        engine.officemgr.game_data.office.doors = office_data.doors;
+        engine.officemgr.game_data.office.objects = office_data.objects;
+        if new_night {
+            AnimatronicManager::load_for_night(engine);
+            NightManager::start_night(engine);
+        }
 
 
         // Script stuff
-        event_manager.kill_all_listeners();
+        event_manager.kill_scopes(|scope| matches!(scope, ListenerScope::Office(_)));
+        if new_night {
+            event_manager.kill_scopes(|scope| matches!(scope, ListenerScope::Night(_)));
+        }
         for (path, code) in &engine.game.office_scripts
         {
             println!("Starting Office Script {}", path);
-            event_manager.run_script(code);
+            event_manager.run_script(code, ListenerScope::Office(office.clone()));
+        }
+        let office_lua = engine.game.lua_scripts.office.clone();
+        event_manager.run_lua_scripts(&office_lua, ListenerScope::Office(office.clone()), engine);
+        if new_night {
+            if let Some(scripts) = engine.game.night_scripts.get(&night) {
+                for (path, code) in scripts
+                {
+                    println!("Starting Night {} Script {}", night, path);
+                    event_manager.run_script(code, ListenerScope::Night(night));
+                }
+            }
+            let night_lua = engine.game.lua_scripts.nights.get(&night).cloned().unwrap_or_default();
+            event_manager.run_lua_scripts(&night_lua, ListenerScope::Night(night), engine);
         }
-        engine.clock.clone().stop();
-        engine.clock.clone().reset();
-        engine.clock.clone().start();
+        engine.clock.stop();
+        engine.clock.reset();
+        engine.clock.start();
         event_manager.trigger_event("on_engine_start", &*Vec::new(), engine);
-        engine.scene = 1; // This needs to be the last thing we do so engine doesn't try rendering the office before it exists
+        engine.officemgr.Loaded = true;
         engine.needs_recache = true;
         event_manager.trigger_event("on_night_start", &*Vec::new(), engine);
         engine.officemgr.Loading_Lock = false;
     }
+
+    fn visible_sprite() -> Sprite {
+        Sprite {
+            visible: true,
+            above_panorama: false,
+            hovered: false,
+        }
+    }
+
+    pub fn init_sprite(office: &mut OfficeData, object: &OfficeObject) {
+        office.sprites.insert(object.id.clone(), Self::visible_sprite());
+        office.objects.insert(object.id.clone(), Self::visible_sprite());
+    }
+
+    // Just the visibility, for objects with no state of their own
+    pub fn init_object(office: &mut OfficeData, object: &OfficeObject) {
+        office.objects.insert(object.id.clone(), Self::visible_sprite());
+    }
+
+    pub fn init_animation(office: &mut OfficeData, object: &OfficeObject) {
+        office.animations.insert(object.id.clone(), Animation {
+            id: object.animation.clone(),
+            is_playing: true,
+            is_reversed: false
+        });
+        office.objects.insert(object.id.clone(), Self::visible_sprite());
+    }
+
+    pub fn init_door(office: &mut OfficeData, object: &OfficeObject) {
+        office.doors.insert(object.id.clone(), Door {
+            animation: object.animation.clone(),
+            is_closed: false,
+            button: Button {
+                is_on: false,
+                clickable: true
+            }
+        });
+        office.objects.insert(object.id.clone(), Self::visible_sprite());
+    }
+
+    // A click on a door button, doors that lost power stay open
+    pub fn toggle_door(id: &str, engine: &mut EngineData) {
+        let door = match engine.officemgr.game_data.office.doors.get_mut(id) {
+            Some(door) => door,
+            None => return,
+        };
+        if door.button.clickable {
+            door.is_closed = !door.is_closed;
+            door.button.is_on = door.is_closed;
+        }
+    }
+
+    pub fn init_light_button(office: &mut OfficeData, object: &OfficeObject) {
+        office.lights.insert(object.id.clone(), Light {
+            is_on: false,
+            clickable: false,
+        });
+        office.objects.insert(object.id.clone(), Self::visible_sprite());
+    }
+}
+
+// The office during a night. Its state is in OfficeManager, so moving between offices doesn't leave the scene.
+pub struct OfficeScene {
+    office: String,
+    night: i32,
 }
 
+impl Scene for OfficeScene {
+    fn kind(&self) -> SceneKind {
+        SceneKind::Office
+    }
+
+    fn enter(&self, engine: &mut EngineData, event_manager: &mut EventManager) {
+        OfficeManager::init_office(self.office.clone(), self.night, engine, event_manager);
+    }
+
+    // Leaving the office ends the night, global listeners are the only ones that stay
+    fn exit(&self, engine: &mut EngineData, event_manager: &mut EventManager) {
+        event_manager.kill_scopes(|scope| matches!(scope, ListenerScope::Office(_) | ListenerScope::Night(_)));
+        engine.officemgr.Loaded = false;
+    }
+
+    fn draw(&self, ctx: &mut Context, engine: &mut EngineData, cache: &mut CacheData) -> tetra::Result {
+        OfficeRenderer::render(ctx, engine, cache, tetra::time::get_delta_time(ctx))
+    }
+
+    fn tick(&self, engine: &mut EngineData, event_manager: &mut EventManager) {
+        if engine.scenes.top() == SceneKind::Office {
+            OfficeManager::scroll(engine);
+        }
+        NightManager::tick(engine, event_manager);
+    }
 
+    fn recache_buttons(&self, platform: &mut dyn Platform, engine: &mut EngineData, event_manager: &mut EventManager) {
+        OfficeManager::recache_buttons(platform, engine, event_manager);
+    }
+}
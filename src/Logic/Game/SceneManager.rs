@@ -0,0 +1,267 @@
+// Which scene something is. Plugins and %game(scene) get these as numbers, 0 is still the menu and 1 the office.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SceneKind {
+    Menu = 0,
+    Office = 1,
+    Minigame = 2,
+    Pause = 3,
+}
+
+// A screen the game can be on. The state lives in its manager, a scene only says how to start, stop,
+// run and draw it. Scenes are cloned out of the stack before a hook runs, so hooks are free to switch scenes.
+pub trait Scene {
+    fn kind(&self) -> SceneKind;
+
+    fn enter(&self, _engine: &mut EngineData, _event_manager: &mut EventManager) {}
+
+    fn exit(&self, _engine: &mut EngineData, _event_manager: &mut EventManager) {}
+
+    // Once the scene's fully on screen, after enter and any fade in
+    fn shown(&self, _engine: &mut EngineData, _event_manager: &mut EventManager) {}
+
+    // Once a frame, for things that follow real time. Only the top scene gets it.
+    fn update(&self, _ctx: &mut Context, _state: &mut GameState) -> tetra::Result {
+        Ok(())
+    }
+
+    fn draw(&self, _ctx: &mut Context, _engine: &mut EngineData, _cache: &mut CacheData) -> tetra::Result {
+        Ok(())
+    }
+
+    // Once a tick, game logic goes here so replays and headless runs match
+    fn tick(&self, _engine: &mut EngineData, _event_manager: &mut EventManager) {}
+
+    // Only the top scene can be clicked
+    fn recache_buttons(&self, _platform: &mut dyn Platform, engine: &mut EngineData, _event_manager: &mut EventManager) {
+        engine.buttons.clear();
+    }
+
+    // Overlays can leave the scene under them on screen, and running
+    fn draws_below(&self) -> bool {
+        false
+    }
+
+    fn ticks_below(&self) -> bool {
+        false
+    }
+}
+
+// Seconds spent fading to black before a switch and back from it after
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Fade {
+    pub fade_out: f32,
+    pub fade_in: f32,
+}
+
+impl Fade {
+    pub fn none() -> Fade {
+        Fade::default()
+    }
+
+    pub fn new(fade_out: f32, fade_in: f32) -> Fade {
+        Fade { fade_out, fade_in }
+    }
+}
+
+struct Transition {
+    // Switched to once the screen is black, none once that's happened and it's fading back in
+    next: Option<Rc<dyn Scene>>,
+    fade: Fade,
+    elapsed: f32,
+}
+
+// The bottom scene is the one the game is in, anything above it is an overlay like a pause menu.
+// Switching scenes exits the whole stack. Fades advance on ticks so a transition lands on the same tick every run.
+pub struct SceneManager {
+    stack: Vec<Rc<dyn Scene>>,
+    transition: Option<Transition>,
+}
+
+impl SceneManager {
+    // The first scene's already running when the engine starts, so it isn't entered
+    pub fn new(first: Rc<dyn Scene>) -> SceneManager {
+        SceneManager {
+            stack: vec![first],
+            transition: None,
+        }
+    }
+
+    pub fn current(&self) -> SceneKind {
+        self.stack.first().map_or(SceneKind::Menu, |scene| scene.kind())
+    }
+
+    pub fn top(&self) -> SceneKind {
+        self.stack.last().map_or(SceneKind::Menu, |scene| scene.kind())
+    }
+
+    pub fn is_fading(&self) -> bool {
+        self.transition.is_some()
+    }
+
+    // Seconds left on the fade in, for a scene that's starting and wants its music to fade in with it
+    pub fn fade_in_time(&self) -> Option<f32> {
+        match &self.transition {
+            Some(transition) if transition.next.is_none() && transition.fade.fade_in > 0.0 => {
+                Some((transition.fade.fade_in - transition.elapsed).max(0.0))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn switch(engine: &mut EngineData, event_manager: &mut EventManager, scene: Rc<dyn Scene>) {
+        Self::transition(engine, event_manager, scene, Fade::none());
+    }
+
+    pub fn transition(engine: &mut EngineData, event_manager: &mut EventManager, scene: Rc<dyn Scene>, fade: Fade) {
+        if fade.fade_out > 0.0 {
+            // Cutting into a fade that's under way starts from however dark it already is
+            let elapsed = engine.scenes.fade_alpha(0.0) * fade.fade_out;
+            engine.scenes.transition = Some(Transition { next: Some(scene), fade, elapsed });
+            // The music fades out across the whole transition, the next scene's fades in over the second half
+            engine.audio.fade_out_all(fade.fade_out - elapsed + fade.fade_in);
+            return;
+        }
+        // Set first so a scene that switches again as it starts overrides it
+        engine.scenes.transition = if fade.fade_in > 0.0 {
+            Some(Transition { next: None, fade, elapsed: 0.0 })
+        } else {
+            None
+        };
+        Self::replace(engine, event_manager, scene.clone());
+        if fade.fade_in <= 0.0 {
+            Self::show(engine, event_manager, &scene);
+        }
+    }
+
+    fn replace(engine: &mut EngineData, event_manager: &mut EventManager, scene: Rc<dyn Scene>) {
+        // Data values are saved in batches, leaving a scene is one of the points they get written
+        event_manager.flush_dirty_data(engine);
+        while let Some(top) = engine.scenes.stack.last().cloned() {
+            top.exit(engine, event_manager);
+            engine.scenes.stack.pop();
+        }
+        engine.scenes.stack.push(scene.clone());
+        engine.needs_recache = true;
+        scene.enter(engine, event_manager);
+    }
+
+    // Unless it's been switched away from already
+    fn show(engine: &mut EngineData, event_manager: &mut EventManager, scene: &Rc<dyn Scene>) {
+        if engine.scenes.stack.iter().any(|running| Rc::ptr_eq(running, scene)) {
+            scene.shown(engine, event_manager);
+        }
+    }
+
+    pub fn push(engine: &mut EngineData, event_manager: &mut EventManager, scene: Rc<dyn Scene>) {
+        engine.scenes.stack.push(scene.clone());
+        engine.needs_recache = true;
+        scene.enter(engine, event_manager);
+        Self::show(engine, event_manager, &scene);
+    }
+
+    // The bottom scene can only be switched away from, false if there's no overlay to pop
+    pub fn pop(engine: &mut EngineData, event_manager: &mut EventManager) -> bool {
+        if engine.scenes.stack.len() < 2 {
+            return false;
+        }
+        let top = engine.scenes.stack.last().cloned().unwrap();
+        top.exit(engine, event_manager);
+        engine.scenes.stack.pop();
+        engine.needs_recache = true;
+        true
+    }
+
+    // From the lowest scene that still shows through (or runs) up to the top
+    fn through(&self, lets_through: fn(&dyn Scene) -> bool) -> Vec<Rc<dyn Scene>> {
+        let mut start = self.stack.len().saturating_sub(1);
+        while start > 0 && lets_through(&*self.stack[start]) {
+            start -= 1;
+        }
+        self.stack[start..].to_vec()
+    }
+
+    pub fn tick(engine: &mut EngineData, event_manager: &mut EventManager) {
+        Self::advance_fade(engine, event_manager);
+
+        for scene in engine.scenes.through(|scene| scene.ticks_below()) {
+            // An earlier tick can switch scenes, the ones it got rid of don't run
+            if engine.scenes.stack.iter().any(|running| Rc::ptr_eq(running, &scene)) {
+                scene.tick(engine, event_manager);
+            }
+        }
+    }
+
+    fn advance_fade(engine: &mut EngineData, event_manager: &mut EventManager) {
+        let transition = match &mut engine.scenes.transition {
+            Some(transition) => transition,
+            None => return,
+        };
+        transition.elapsed += TickManager::tick_length().as_secs_f32();
+        match transition.next.take() {
+            Some(next) if transition.elapsed >= transition.fade.fade_out => {
+                transition.elapsed = 0.0;
+                let fades_in = transition.fade.fade_in > 0.0;
+                if !fades_in {
+                    engine.scenes.transition = None;
+                }
+                Self::replace(engine, event_manager, next.clone());
+                if !fades_in {
+                    Self::show(engine, event_manager, &next);
+                }
+            }
+            Some(next) => transition.next = Some(next),
+            None if transition.elapsed >= transition.fade.fade_in => {
+                engine.scenes.transition = None;
+                // What was switched to is at the bottom, anything over it was pushed during the fade and shown already
+                if let Some(scene) = engine.scenes.stack.first().cloned() {
+                    scene.shown(engine, event_manager);
+                }
+            }
+            None => {}
+        }
+    }
+
+    pub fn update(ctx: &mut Context, state: &mut GameState) -> tetra::Result {
+        match state.engine.scenes.stack.last().cloned() {
+            Some(scene) => scene.update(ctx, state),
+            None => Ok(()),
+        }
+    }
+
+    pub fn draw(ctx: &mut Context, engine: &mut EngineData, cache: &mut CacheData) -> tetra::Result {
+        for scene in engine.scenes.through(|scene| scene.draws_below()) {
+            scene.draw(ctx, engine, cache)?;
+        }
+        Ok(())
+    }
+
+    // Over everything else, plugins included
+    pub fn draw_fade(ctx: &mut Context, engine: &EngineData) -> tetra::Result {
+        let alpha = engine.scenes.fade_alpha(engine.clock.tick_fraction());
+        if alpha > 0.0 {
+            Mesh::rectangle(ctx, ShapeStyle::Fill, Rectangle::new(0.0, 0.0, 1280.0, 720.0))?
+                .draw(ctx, DrawParams::new().color(Color::rgba(0.0, 0.0, 0.0, alpha)));
+        }
+        Ok(())
+    }
+
+    pub fn recache_buttons(platform: &mut dyn Platform, engine: &mut EngineData, event_manager: &mut EventManager) {
+        if let Some(scene) = engine.scenes.stack.last().cloned() {
+            scene.recache_buttons(platform, engine, event_manager);
+        }
+    }
+
+    // How black the screen is, tick_fraction smooths it out between ticks
+    fn fade_alpha(&self, tick_fraction: f32) -> f32 {
+        let transition = match &self.transition {
+            Some(transition) => transition,
+            None => return 0.0,
+        };
+        let elapsed = transition.elapsed + tick_fraction * TickManager::tick_length().as_secs_f32();
+        if transition.next.is_some() {
+            (elapsed / transition.fade.fade_out).min(1.0)
+        } else {
+            1.0 - (elapsed / transition.fade.fade_in).min(1.0)
+        }
+    }
+}
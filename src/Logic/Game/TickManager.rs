@@ -1,81 +1,93 @@
-use std::sync::{atomic::{AtomicBool, Ordering}};
-use std::thread;
-use std::thread::sleep;
 use std::time::Duration;
 
+// Ticks are 50ms apart
+const TICKS_PER_SECOND: u64 = 20;
+// After a long stall (loading, dragging the window) only catch up this much instead of running hundreds of ticks at once
+const MAX_CATCH_UP_TICKS: u32 = TICKS_PER_SECOND as u32;
+
+// Fixed step clock. The game loop feeds it frame time and runs every tick that's due, in order,
+// so a slow frame runs several ticks instead of dropping or merging them.
 struct TickManager {
-    current_tick: Arc<Mutex<u64>>,
-    ticked: Arc<Mutex<bool>>,
-    stop_signal: Arc<AtomicBool>,
-    callbacks: Arc<Mutex<Vec<Box<dyn Fn() + Send + 'static>>>>,
+    current_tick: u64,
+    accumulator: Duration,
+    running: bool,
+    // Paused ticks still come round so input gets handled, they just don't count
+    paused: bool,
 }
 
 impl TickManager {
     fn new() -> Self {
         TickManager {
-            current_tick: Arc::new(Mutex::new(0)),
-            ticked: Arc::new(Mutex::new(false)),
-            stop_signal: Arc::new(AtomicBool::new(false)),
-            callbacks: Arc::new(Mutex::new(Vec::new())),
+            current_tick: 0,
+            accumulator: Duration::ZERO,
+            running: false,
+            paused: false,
+        }
+    }
+
+    fn tick_length() -> Duration {
+        Duration::from_millis(1000 / TICKS_PER_SECOND)
+    }
+
+    // Adds frame time to be spent on ticks
+    fn advance(&mut self, delta: Duration) {
+        if !self.running {
+            return;
+        }
+        self.accumulator = (self.accumulator + delta).min(Self::tick_length() * MAX_CATCH_UP_TICKS);
+    }
+
+    // Moves on to the next tick if one is due. A reset mid loop throws away the time that was left.
+    fn next_tick(&mut self) -> bool {
+        if !self.running || self.accumulator < Self::tick_length() {
+            return false;
+        }
+        self.accumulator -= Self::tick_length();
+        if !self.paused {
+            self.current_tick += 1;
         }
+        true
     }
 
-    fn increment(&self) {
-        let mut tick = self.current_tick.lock().unwrap();
-        *tick += 1;
-        self.trigger_callbacks();
+    // How far the next tick is along, 0 to 1, for drawing things smoothly between ticks
+    fn tick_fraction(&self) -> f32 {
+        self.accumulator.as_secs_f32() / Self::tick_length().as_secs_f32()
     }
 
     fn current_tick_equals(&self, tick: u64) -> bool {
-        let current_tick = self.current_tick.lock().unwrap();
-        *current_tick == tick
+        self.current_tick == tick
     }
 
     fn get_current_tick(&self) -> u64 {
-        let tick = self.current_tick.lock().unwrap();
-        *tick
+        self.current_tick
     }
 
-    fn reset(&self) {
-        let mut tick = self.current_tick.lock().unwrap();
-        *tick = 0;
+    fn set_tick(&mut self, tick: u64) {
+        self.current_tick = tick;
     }
 
-    fn start(self: Arc<Self>) {
-        let stop_signal = Arc::clone(&self.stop_signal);
-        let ticked = Arc::clone(&self.ticked);
-        let current_tick = Arc::clone(&self.current_tick);
-
-        thread::spawn(move || {
-            while !stop_signal.load(Ordering::Relaxed) {
-                {
-                    let mut tick = current_tick.lock().unwrap();
-                    *tick += 1;
-                    *ticked.lock().unwrap() = true;
-                }
-                self.trigger_callbacks();
-                thread::sleep(Duration::from_millis(50));
-            }
-            stop_signal.store(false, Ordering::Relaxed)
-        });
+    fn reset(&mut self) {
+        self.current_tick = 0;
+        self.accumulator = Duration::ZERO;
     }
 
-    fn stop(&self) {
-        self.stop_signal.store(true, Ordering::Relaxed);
+    fn start(&mut self) {
+        self.running = true;
     }
 
-    fn on_tick<F>(&self, callback: F)
-    where
-        F: Fn() + Send + 'static,
-    {
-        let mut callbacks = self.callbacks.lock().unwrap();
-        callbacks.push(Box::new(callback));
+    fn stop(&mut self) {
+        self.running = false;
     }
 
-    fn trigger_callbacks(&self) {
-        let callbacks = self.callbacks.lock().unwrap();
-        for callback in callbacks.iter() {
-            callback();
-        }
+    fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    fn is_paused(&self) -> bool {
+        self.paused
     }
-}
\ No newline at end of file
+}
@@ -14,21 +14,11 @@ pub struct Button2D {
 }
 
 impl Button2D {
-    pub fn new(ctx: &mut Context, position: Vec2<f32>, texture: Option<&Texture>, mut text: Option<&mut Text>, event: String, event_args: Vec<String>) -> Self {
-        let x_scale: f32 = (window::get_width(ctx) / 1280) as f32;
-        let y_scale: f32 = (window::get_height(ctx)  / 720) as f32;
-        let mut bounds: Rectangle = match &texture {
-            Some(tex) => Rectangle::new(-5.0, -5.0, tex.width() as f32 + 5.0, tex.height() as f32 + 5.0),
-            None => {
-                let mut bound = text.expect("FAILED TO GET BOUNDS").get_bounds(ctx).unwrap();
-                bound.x -= 5.0;
-                bound.y -= 5.0;
-                bound.height += 5.0;
-                bound.width += 5.0;
-
-                bound
-            },
-        };
+    // size is the texture or text size, the click bounds get a bit of padding around it
+    pub fn new(window_size: (i32, i32), position: Vec2<f32>, size: Vec2<f32>, is_image: bool, event: String, event_args: Vec<String>) -> Self {
+        let x_scale: f32 = (window_size.0 / 1280) as f32;
+        let y_scale: f32 = (window_size.1 / 720) as f32;
+        let mut bounds: Rectangle = Rectangle::new(-5.0, -5.0, size.x + 5.0, size.y + 5.0);
 
         bounds.x = position[0] - 5.0;
         bounds.y = position[1] - 5.0;
@@ -42,19 +32,21 @@ impl Button2D {
             is_hovered: false,
             is_clicked: false,
             is_clickable: true,
-            is_image: texture.is_some(),
+            is_image,
             x: bounds.x
         }
     }
 
-    pub fn update(&mut self, ctx: &mut Context, x_offset: f32) {
+    // Works off the tick's input state instead of the live mouse so replays click the same things
+    pub fn update(&mut self, input: &InputState, x_offset: f32) {
         self.bounds.x = self.x - x_offset;
-        self.is_hovered = self.bounds.contains_point(input::get_mouse_position(ctx));
+        self.is_hovered = self.bounds.contains_point(Vec2::new(input.mouse_x, input.mouse_y));
 
-        self.is_clicked = self.is_clickable && input::is_mouse_button_down(ctx, MouseButton::Left) &&
+        let left_down = input.mouse_down.contains("left");
+        self.is_clicked = self.is_clickable && left_down &&
             self.is_hovered;
 
-        self.is_clickable = input::is_mouse_button_up(ctx, MouseButton::Left) &&
+        self.is_clickable = !left_down &&
             !self.is_clicked;
     }
 }
@@ -1,5 +1,6 @@
 use crate::GameLoader::CamUI;
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct OfficeData {
     pub states: HashMap<String, String>,
     pub state: String,
@@ -32,34 +33,40 @@ impl OfficeData {
     }
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Sprite {
     pub visible: bool,
     pub above_panorama: bool,
     pub hovered: bool,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Animation {
     pub id: String,
     pub is_playing: bool,
     pub is_reversed: bool
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Door {
     pub is_closed: bool,
     pub button: Button,
     pub animation: String
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Button {
     pub is_on: bool,
     pub clickable: bool
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Light {
     pub is_on: bool,
     pub clickable: bool
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Settings {
     pub mask: bool,
     pub flashlight: bool,
@@ -68,6 +75,7 @@ pub struct Settings {
     pub power: bool,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Power {
     pub level: i32,
     pub usage: i32,
@@ -75,8 +83,12 @@ pub struct Power {
     pub ucn: bool,
     pub power_out_animation: String,
     pub animatronic_jumpscare: String,
+    // Usage points spent towards the next percent
+    #[serde(default)]
+    pub drain: i32,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Camera {
     pub panorama: bool,
     pub static_camera: bool,
@@ -91,6 +103,7 @@ impl Camera {
     }
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Animatronic {
     pub ignores_mask: bool,
     pub path: Vec<PathNode>,
@@ -103,12 +116,14 @@ pub struct Animatronic {
     pub location_index: i32,
     pub phantom: bool,
 }
+#[derive(Clone, Serialize, Deserialize)]
 pub struct AnimatronicJumpscare {
     pub sound: String,
     pub jumpscare: String,
     pub offset: i32
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct GameData {
     pub path: String,
     pub night: i32,
@@ -121,6 +136,9 @@ pub struct GameData {
     pub animatronics: HashMap<String, Animatronic>,
     pub power: Power,
     pub time: i32,
+    // Ticks into the current hour
+    #[serde(default)]
+    pub time_ticks: i32,
     pub paused: bool
 }
 
@@ -152,14 +170,17 @@ impl GameData {
                 ucn: false,
                 power_out_animation: String::new(),
                 animatronic_jumpscare: String::new(),
+                drain: 0,
             },
             time: 0,
+            time_ticks: 0,
             paused: false
         }
     }
 
     pub fn set_time(&mut self, time: i32) {
         self.time = time;
+        self.time_ticks = 0;
     }
 
     pub fn quit(&mut self) {
@@ -179,6 +200,7 @@ impl GameData {
 }
 
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Player {
     pub is_camera_up: bool,
     pub camera_button_toggle: bool,
@@ -221,6 +243,7 @@ impl Player {
     }
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct PathNode {
     pub id: String,
     pub type_: String,
@@ -1,91 +1,846 @@
 use std::error::Error;
 use std::sync::{Arc, Mutex};
 use libc::rand;
-use rand::{random, Rng, thread_rng};
+use rand::Rng;
 use regex::Regex;
 use serde_json::Value;
 
 use crate::GameLoader::Code;
 
 include!("MathEvaluator.rs");
+include!("ScriptValue.rs");
+include!("ListenerIndex.rs");
 include!("ScriptingAPI.rs");
 
 
 type CodeBlockFunction = fn(&mut EngineData, &mut EventManager, &[Value]) -> bool;
+// Built in blocks are plain fns, ones added at runtime by Lua or plugins are closures
+type CodeBlock = Rc<dyn Fn(&mut EngineData, &mut EventManager, &[Value]) -> bool>;
+// Handler for %custom(name, args...), the args come in already evaluated
+type ExpressionFunction = Rc<dyn Fn(&EventManager, &mut EngineData, &[String]) -> Result<String, String>>;
+// Native code listening to an event, plugins subscribe through these
+type EventHook = Rc<dyn Fn(&mut EventManager, &mut EngineData, &[String])>;
+
+const MAX_LOOP_ITERATIONS: usize = 10000;
+const MAX_CALL_DEPTH: usize = 64;
+// Made by loops that wait, never by scripts. Runs the rest of the iteration, then the loop in its first arg.
+const RESUME_LOOP_BLOCK: &str = "resume_loop";
+// Made for each Lua On() handler, its arg is the handler's id
+const LUA_HANDLER_BLOCK: &str = "lua_handler";
+
+// What a list of blocks tells whoever ran it, so loops and functions know when to stop early
+#[derive(Debug, Clone)]
+pub enum BlockFlow {
+    Next,
+    Break,
+    Continue,
+    Return,
+    // Suspends for this many ticks, the blocks are what's left to run afterwards
+    Wait(u64, Vec<Code>),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ScheduledTask {
+    pub id: u64,
+    pub name: String,
+    pub due_tick: u64,
+    pub interval: Option<u64>,
+    pub code: Vec<Code>,
+    // The event that scheduled this, so %event() still works after a wait
+    pub event: Option<EventPayload>,
+    pub scope: ListenerScope,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EventPayload {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+// The script side of a night snapshot, listeners aren't in here since the scripts register them again
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ScriptState {
+    // Variables don't belong to a scope, so all of them go back to how they were, global and menu ones included
+    pub variables: HashMap<String, ScriptValue>,
+    pub scheduled: Vec<ScheduledTask>,
+    // What the tasks' due ticks count from
+    pub script_tick: u64,
+}
+
+#[derive(Clone)]
+pub struct ScriptFunction {
+    pub params: Vec<String>,
+    pub body: Vec<Code>,
+    // Goes away with the script that defined it
+    pub scope: ListenerScope,
+}
 
 pub struct EventManager {
-    listeners: Arc<Mutex<Vec<(String, Vec<String>, Vec<Code>)>>>,
-    code_blocks: HashMap<String, CodeBlockFunction>,
-    variables: HashMap<String, String>,
-    data_values: HashMap<String, String>,
+    listeners: ListenerIndex,
+    code_blocks: HashMap<String, CodeBlock>,
+    expressions: HashMap<String, ExpressionFunction>,
+    variables: HashMap<String, ScriptValue>,
+    data_values: HashMap<String, ScriptValue>,
+    // Something in the save changed since it was last written. The data blocks write straight away,
+    // the rest (plugins, the last night played) waits for the next scene change or exit.
+    data_dirty: bool,
+    // Oldest first, a call gets the newest. A menu defining a function the global scripts also have
+    // only hides the global one until the menu unloads.
+    functions: HashMap<String, Vec<ScriptFunction>>,
+    return_value: Option<ScriptValue>,
+    call_depth: usize,
+    scheduled: Vec<ScheduledTask>,
+    next_task_id: u64,
+    script_tick: u64,
+    current_event: Option<EventPayload>,
+    propagation_stopped: bool,
+    // Scope of whatever is running right now, timers it starts die with it
+    active_scope: ListenerScope,
+    // Every event fired while this is on, listened to or not. Scenario runs check it afterwards.
+    event_log: Option<Vec<EventPayload>>,
+    // Only started once a game actually has a .lua script
+    lua: Option<Rc<LuaBindings>>,
+    // Blocks and expressions Lua registered as (name, expression, script, load), they go when their script does
+    lua_callbacks: Vec<(String, bool, String, u64)>,
+    // Outlive scopes, killing listeners leaves these alone
+    event_hooks: HashMap<String, Vec<EventHook>>,
+    verbose: bool,
     logger: Logger,
 }
 
 impl EventManager {
     fn new(logger: Logger) -> Self {
         EventManager {
-            listeners: Arc::new(Mutex::new(Vec::new())),
-            code_blocks: ScriptingAPI::init_api().unwrap().actions,
+            listeners: ListenerIndex::new(),
+            code_blocks: ScriptingAPI::init_api().unwrap().actions.into_iter()
+                .map(|(name, func)| (name, Rc::new(func) as CodeBlock))
+                .collect(),
+            expressions: HashMap::new(),
             variables: HashMap::new(),
             data_values: HashMap::new(),
+            data_dirty: false,
+            functions: HashMap::new(),
+            return_value: None,
+            call_depth: 0,
+            scheduled: Vec::new(),
+            next_task_id: 0,
+            script_tick: 0,
+            current_event: None,
+            propagation_stopped: false,
+            active_scope: ListenerScope::Global,
+            event_log: None,
+            lua: None,
+            lua_callbacks: Vec::new(),
+            event_hooks: HashMap::new(),
+            verbose: true,
             logger,
         }
     }
 
-    pub fn register_listener(&mut self, event_name: String, args: Vec<String>, subcode: Vec<Code>)
+    pub fn register_listener(&mut self, event_name: String, args: Vec<String>, subcode: Vec<Code>, priority: i32, scope: ListenerScope) -> u64
     {
-        self.logger.log("Event Manager", format!("Registering Event: {:0} With args: {:?}", event_name, args).as_str());
-        let listener = (event_name, args, subcode);
-        self.listeners.lock().unwrap().push(listener);
+        if self.verbose {
+            self.logger.log("Event Manager", format!("Registering Event: {:0} With args: {:?} ({:?})", event_name, args, scope).as_str());
+        }
+        self.listeners.insert(event_name, args, subcode, priority, scope)
+    }
+
+    // Registration logging is useful for game authors but floods the log when thousands of listeners get made
+    pub fn set_verbose(&mut self, verbose: bool) {
+        self.verbose = verbose;
     }
 
-    pub fn run_script(&mut self, script: &Vec<Code>) {
+    pub fn run_script(&mut self, script: &Vec<Code>, scope: ListenerScope) {
         for event in script {
+            if event.block.to_lowercase() == "function" {
+                self.define_function(event, scope.clone());
+                continue;
+            }
             let args: Vec<String> = event.args.iter().map(|v| v.to_string().trim_matches('"').to_string()).collect();
-            self.register_listener(event.block.clone(), args, event.subcode.clone());
+            self.register_listener(event.block.clone(), args, event.subcode.clone(), event.priority, scope.clone());
+        }
+    }
+
+    pub fn run_lua_scripts(&mut self, scripts: &HashMap<String, String>, scope: ListenerScope, engine_data: &mut EngineData) {
+        if scripts.is_empty() {
+            return;
+        }
+        if self.lua.is_none() {
+            self.lua = Some(Rc::new(LuaBindings::new(self.logger.clone())));
+        }
+        self.with_lua(engine_data, |lua, engine_data, event_manager| lua.load_scripts(scripts, &scope, engine_data, event_manager));
+    }
+
+    // Calls a global Lua function, a result other than nil or false counts as true
+    pub fn call_lua(&mut self, name: &str, args: Vec<String>, engine_data: &mut EngineData) -> bool {
+        let result = self.with_lua(engine_data, |lua, engine_data, event_manager| {
+            let (script, function) = lua.find_function(name)?;
+            let running = LuaRunning { script, scope: event_manager.active_scope.clone() };
+            lua.with_game(engine_data, Some(event_manager), running, |_| {
+                function.call::<_, mlua::Value>(args.into_iter().collect::<mlua::Variadic<String>>())
+                    .map(|result| !matches!(result, mlua::Value::Nil | mlua::Value::Boolean(false)))
+            })
+        });
+        match result {
+            Some(Ok(result)) => result,
+            Some(Err(e)) => {
+                self.logger.log_error("Lua", format!("Calling {} failed: {}", name, e).as_str());
+                false
+            }
+            None => {
+                self.logger.log_error("Lua", format!("Can't call {}, no Lua scripts are loaded.", name).as_str());
+                false
+            }
         }
     }
 
+    // Turns a RegisterBlock/RegisterExpression call into a block or expression FEScript can use.
+    // Lua can only replace what Lua registered, the built in blocks and plugin ones stay put.
+    fn register_lua_callback(&mut self, registration: LuaRegistration) {
+        let LuaRegistration { name, expression, script, load, key } = registration;
+        let kind = if expression { "expression" } else { "block" };
+        let stored = if expression { name.clone() } else { name.to_lowercase() };
+        match self.lua_callbacks.iter().position(|(owned, is_expression, _, _)| *is_expression == expression && *owned == stored) {
+            Some(index) => {
+                let (_, _, owner, _) = self.lua_callbacks.remove(index);
+                if owner != script {
+                    self.logger.log_warn("Lua", format!("{} replaced {} '{}' from {}.", script, kind, name, owner).as_str());
+                }
+                self.remove_callback(&stored, expression);
+            }
+            None if (expression && self.expressions.contains_key(&stored)) || (!expression && self.code_blocks.contains_key(&stored)) => {
+                self.logger.log_error("Lua", format!("{} can't replace the built in {} '{}'.", script, kind, name).as_str());
+                return;
+            }
+            None => {}
+        }
+        self.lua_callbacks.push((stored, expression, script.clone(), load));
+        let key = Rc::new(key);
+        self.logger.log("Lua", format!("{} registered {} '{}'", script, kind, name).as_str());
+
+        if expression {
+            let label = name.clone();
+            self.register_expression(&name, move |event_manager, engine_data, args| {
+                let lua = event_manager.lua.as_ref().ok_or_else(|| "Lua isn't running.".to_string())?;
+                if !lua.is_loaded(&script, load) {
+                    return Err(format!("%custom({}) belongs to {}, which isn't running anymore.", label, script));
+                }
+                // Only has the event manager to look at, the listeners of a script this turns off go on the next trigger
+                let running = LuaRunning { script: script.clone(), scope: event_manager.active_scope.clone() };
+                lua.call_registered(&key, running, args, engine_data, None, |value| LuaBindings::lua_to_text(&value))
+                    .map_err(|e| {
+                        lua.disable_script(&script, &e);
+                        format!("%custom({}) from {} failed, {} has been turned off.", label, script, script)
+                    })
+            });
+        } else {
+            let label = name.clone();
+            self.register_code_block(&name, move |engine_data, event_manager, args| {
+                if !event_manager.lua.as_ref().map_or(false, |lua| lua.is_loaded(&script, load)) {
+                    engine_data.logger.log_error("Lua", format!("Block {} belongs to {}, which isn't running anymore.", label, script).as_str());
+                    return false;
+                }
+                let args: Vec<String> = args.iter()
+                    .map(|arg| event_manager.get_expr(&ScriptingAPI::arg_to_string(arg), engine_data))
+                    .collect();
+                let running = LuaRunning { script: script.clone(), scope: event_manager.active_scope.clone() };
+                let result = event_manager.with_lua(engine_data, |lua, engine_data, event_manager| {
+                    lua.call_registered(&key, running, &args, engine_data, Some(event_manager), |value| !matches!(value, mlua::Value::Nil | mlua::Value::Boolean(false)))
+                        .unwrap_or_else(|e| {
+                            lua.disable_script(&script, &e);
+                            false
+                        })
+                });
+                result.unwrap_or(false)
+            });
+        }
+    }
+
+    // Lua can run blocks while it has the event manager, so it's lent out rather than taken.
+    // What the scripts registered or lost on the way gets picked up once it returns.
+    fn with_lua<R, F>(&mut self, engine_data: &mut EngineData, run: F) -> Option<R>
+    where
+        F: FnOnce(&LuaBindings, &mut EngineData, &mut EventManager) -> R,
+    {
+        let lua = self.lua.clone()?;
+        let result = run(&lua, engine_data, self);
+        self.apply_lua_changes(&lua);
+        Some(result)
+    }
+
+    // Each On() handler gets a listener of its own, so it takes its turn by priority with the FEScript ones
+    fn apply_lua_changes(&mut self, lua: &LuaBindings) {
+        for registration in lua.take_registrations() {
+            self.register_lua_callback(registration);
+        }
+        for (id, event, priority, scope) in lua.take_added_handlers() {
+            let handler = Code { negated: false, args: vec![Value::from(id)], block: LUA_HANDLER_BLOCK.to_string(), subcode: Vec::new(), priority };
+            self.register_listener(event, vec!["*".to_string()], vec![handler], priority, scope);
+        }
+        let dropped = lua.take_dropped_handlers();
+        if !dropped.is_empty() {
+            self.listeners.remove_where(|listener| listener.subcode.iter().any(|code| {
+                code.block == LUA_HANDLER_BLOCK && code.args.get(0).and_then(Value::as_u64).map_or(false, |id| dropped.contains(&id))
+            }));
+        }
+        self.drop_dead_lua_callbacks(lua);
+    }
+
+    // Blocks and expressions of a script that got turned off or unloaded go with it
+    fn drop_dead_lua_callbacks(&mut self, lua: &LuaBindings) {
+        let (live, dead): (Vec<_>, Vec<_>) = std::mem::take(&mut self.lua_callbacks).into_iter()
+            .partition(|(_, _, script, load)| lua.is_loaded(script, *load));
+        self.lua_callbacks = live;
+        for (name, expression, _, _) in dead {
+            self.remove_callback(&name, expression);
+        }
+    }
+
+    fn remove_callback(&mut self, name: &str, expression: bool) {
+        if expression {
+            self.expressions.remove(name);
+        } else {
+            self.code_blocks.remove(name);
+        }
+    }
+
+    fn run_lua_handler(&mut self, engine_data: &mut EngineData, code: &Code) {
+        let id = match code.args.get(0).and_then(Value::as_u64) {
+            Some(id) => id,
+            None => return,
+        };
+        let args = self.current_event.as_ref().map(|event| event.args.clone()).unwrap_or_default();
+        self.with_lua(engine_data, |lua, engine_data, event_manager| lua.run_handler(id, &args, engine_data, event_manager));
+    }
+
 
     pub fn kill_listener(&mut self, event_name: &str, args: &[String]) {
-        let mut listeners = self.listeners.lock().unwrap();
-        listeners.retain(|(name, arguments, _)| name != event_name || arguments != args);
+        self.listeners.remove_where(|listener| listener.event == event_name && listener.args == args);
+    }
+
+    pub fn kill_listener_by_id(&mut self, id: u64) -> bool {
+        self.listeners.remove(id)
+    }
+
+    pub fn listener_count(&self) -> usize {
+        self.listeners.len()
+    }
+
+    // Tears down every listener and timer owned by a matching scope
+    pub fn kill_scopes<F>(&mut self, predicate: F)
+    where
+        F: Fn(&ListenerScope) -> bool,
+    {
+        let count = self.listeners.remove_where(|listener| predicate(&listener.scope));
+        self.scheduled.retain(|task| !predicate(&task.scope));
+        for definitions in self.functions.values_mut() {
+            definitions.retain(|function| !predicate(&function.scope));
+        }
+        self.functions.retain(|_, definitions| !definitions.is_empty());
+        if let Some(lua) = self.lua.clone() {
+            lua.kill_scopes(&predicate);
+            self.drop_dead_lua_callbacks(&lua);
+        }
+        self.logger.log("Event Manager", format!("Killed {} Listeners", count).as_str());
     }
 
     pub fn kill_all_listeners(&mut self) {
-        let mut listeners = self.listeners.lock().unwrap();
-        let count = listeners.iter().count();
-        listeners.clear();
+        let count = self.listeners.clear();
+        self.scheduled.clear();
+        self.functions.clear();
+        if let Some(lua) = self.lua.clone() {
+            lua.kill_scopes(|_| true);
+            self.drop_dead_lua_callbacks(&lua);
+        }
         self.logger.log("Event Manager", format!("Killed {} Listeners", count).as_str());
     }
 
-    pub fn register_code_block(&mut self, block_name: &str, func: CodeBlockFunction) {
-        self.code_blocks.insert(block_name.to_string(), func);
+    // Replaces a block of the same name, built in ones included
+    pub fn register_code_block<F>(&mut self, block_name: &str, func: F)
+    where
+        F: Fn(&mut EngineData, &mut EventManager, &[Value]) -> bool + 'static,
+    {
+        if self.code_blocks.insert(block_name.to_lowercase(), Rc::new(func)).is_some() {
+            self.logger.log_warn("Event Manager", format!("Code block '{}' was replaced by a new one.", block_name).as_str());
+        }
+    }
+
+    pub fn register_expression<F>(&mut self, name: &str, func: F)
+    where
+        F: Fn(&EventManager, &mut EngineData, &[String]) -> Result<String, String> + 'static,
+    {
+        self.expressions.insert(name.to_string(), Rc::new(func));
+    }
+
+    pub fn add_event_hook<F>(&mut self, event_name: &str, hook: F)
+    where
+        F: Fn(&mut EventManager, &mut EngineData, &[String]) + 'static,
+    {
+        self.event_hooks.entry(event_name.to_string()).or_default().push(Rc::new(hook));
     }
 
     pub fn trigger_event(&mut self, event_name: &str, args: &[String], engine_data: &mut EngineData) {
-        let listeners = self.listeners.lock().unwrap().clone();
-        for listener in listeners.iter() {
-            if listener.0 == event_name && listener.1 == args {
-                let subcode = &listener.2;
-                for code in subcode.iter() {
-                    self.run_block(engine_data, code);
+        self.trigger_event_in(event_name, args, engine_data, |_| true);
+    }
+
+    // Only listeners whose scope passes `in_scope` run, plugin hooks always do
+    pub fn trigger_event_in<F>(&mut self, event_name: &str, args: &[String], engine_data: &mut EngineData, in_scope: F)
+    where
+        F: Fn(&ListenerScope) -> bool,
+    {
+        if let Some(log) = &mut self.event_log {
+            log.push(EventPayload { name: event_name.to_string(), args: args.to_vec() });
+        }
+        // Scripts a %custom expression turned off still have listeners, expressions can't get to them
+        if let Some(lua) = self.lua.clone() {
+            self.apply_lua_changes(&lua);
+        }
+        // Plugin hooks go first, Lua handlers are listeners like the FEScript ones
+        if let Some(hooks) = self.event_hooks.get(event_name).cloned() {
+            for hook in hooks {
+                hook(self, engine_data, args);
+            }
+        }
+
+        // The handler list is fixed when the event fires. Listeners registered while it runs wait for
+        // the next trigger, and ones killed before their turn don't run.
+        let handlers = self.listeners.resolve(event_name, args);
+        if handlers.is_empty() {
+            return;
+        }
+
+        let payload = EventPayload { name: event_name.to_string(), args: args.to_vec() };
+        let previous_event = self.current_event.replace(payload);
+        let previous_stopped = std::mem::replace(&mut self.propagation_stopped, false);
+
+        for id in handlers.iter() {
+            let listener = match self.listeners.get(*id) {
+                Some(listener) if in_scope(&listener.scope) => listener,
+                _ => continue,
+            };
+            let previous_scope = std::mem::replace(&mut self.active_scope, listener.scope.clone());
+            if let BlockFlow::Wait(ticks, rest) = self.run_blocks(engine_data, &listener.subcode) {
+                self.schedule(String::new(), ticks, None, rest);
+            }
+            self.active_scope = previous_scope;
+            if self.propagation_stopped {
+                break;
+            }
+        }
+
+        self.current_event = previous_event;
+        self.propagation_stopped = previous_stopped;
+    }
+
+    pub fn start_event_log(&mut self) {
+        self.event_log = Some(Vec::new());
+    }
+
+    pub fn event_log(&self) -> &[EventPayload] {
+        self.event_log.as_deref().unwrap_or(&[])
+    }
+
+    pub fn stop_propagation(&mut self) {
+        self.propagation_stopped = true;
+    }
+
+    // %event(name), %event(count), or %event(0) / %event(arg0) for the args
+    fn event_expression(&self, content: &str) -> Result<String, String> {
+        let payload = match &self.current_event {
+            Some(payload) => payload,
+            None => return Err("Event expression used outside of an event.".to_string()),
+        };
+        let content = content.trim();
+        match content {
+            "name" => Ok(payload.name.clone()),
+            "count" => Ok(payload.args.len().to_string()),
+            _ => {
+                let index = content.trim_start_matches("arg").parse::<usize>()
+                    .map_err(|_| format!("Unknown event field '{}'.", content))?;
+                payload.args.get(index).cloned().ok_or_else(|| format!("Event has no arg {}.", index))
+            }
+        }
+    }
+
+    pub fn run_block(&mut self, engine_data: &mut EngineData, code: &Code) -> BlockFlow {
+        self.run_blocks(engine_data, std::slice::from_ref(code))
+    }
+
+    pub fn run_blocks(&mut self, engine_data: &mut EngineData, codes: &[Code]) -> BlockFlow {
+        // Some(false) means the last if/else_if in this list didn't run, so an else may
+        let mut last_condition: Option<bool> = None;
+
+        for (i, code) in codes.iter().enumerate() {
+            let flow = match code.block.to_lowercase().as_str() {
+                "else" => {
+                    let flow = match last_condition {
+                        Some(false) => self.run_blocks(engine_data, &code.subcode),
+                        Some(true) => BlockFlow::Next,
+                        None => {
+                            self.logger.log_warn("Event Manager", "'else' without a condition before it, skipping.");
+                            BlockFlow::Next
+                        }
+                    };
+                    last_condition = None;
+                    flow
+                }
+                "else_if" => match last_condition {
+                    Some(false) => {
+                        let result = self.condition_from_args(engine_data, &code.args) != code.negated;
+                        last_condition = Some(result);
+                        if result { self.run_blocks(engine_data, &code.subcode) } else { BlockFlow::Next }
+                    }
+                    Some(true) => BlockFlow::Next,
+                    None => {
+                        self.logger.log_warn("Event Manager", "'else_if' without a condition before it, skipping.");
+                        BlockFlow::Next
+                    }
+                },
+                "repeat" => {
+                    last_condition = None;
+                    self.run_repeat(engine_data, code)
+                }
+                "while" => {
+                    last_condition = None;
+                    self.run_while(engine_data, code)
+                }
+                // wait/after/every count ticks, their _seconds versions count seconds
+                "wait" | "wait_seconds" => {
+                    let ticks = self.ticks_from_arg(engine_data, code, code.block.to_lowercase() == "wait_seconds");
+                    BlockFlow::Wait(ticks, Vec::new())
+                }
+                "after" | "after_seconds" => {
+                    last_condition = None;
+                    let ticks = self.ticks_from_arg(engine_data, code, code.block.to_lowercase() == "after_seconds");
+                    let name = code.args.get(1).map(ScriptingAPI::arg_to_string).unwrap_or_default();
+                    self.schedule(name, ticks, None, code.subcode.clone());
+                    BlockFlow::Next
+                }
+                "every" | "every_seconds" => {
+                    last_condition = None;
+                    let ticks = self.ticks_from_arg(engine_data, code, code.block.to_lowercase() == "every_seconds").max(1);
+                    let name = code.args.get(1).map(ScriptingAPI::arg_to_string).unwrap_or_default();
+                    self.schedule(name, ticks, Some(ticks), code.subcode.clone());
+                    BlockFlow::Next
+                }
+                RESUME_LOOP_BLOCK => {
+                    last_condition = None;
+                    self.resume_loop(engine_data, code)
+                }
+                LUA_HANDLER_BLOCK => {
+                    last_condition = None;
+                    self.run_lua_handler(engine_data, code);
+                    BlockFlow::Next
+                }
+                "break" => BlockFlow::Break,
+                "continue" => BlockFlow::Continue,
+                "return" => {
+                    self.return_value = match code.args.get(0) {
+                        Some(value) => {
+                            let expr = self.get_expr(&ScriptingAPI::arg_to_string(value), engine_data);
+                            Some(ScriptValue::parse(&expr))
+                        }
+                        None => None,
+                    };
+                    BlockFlow::Return
+                }
+                "function" => {
+                    let scope = self.active_scope.clone();
+                    self.define_function(code, scope);
+                    BlockFlow::Next
                 }
+                _ => {
+                    let result = self.call_code_block(engine_data, code);
+                    last_condition = Some(result);
+                    if result { self.run_blocks(engine_data, &code.subcode) } else { BlockFlow::Next }
+                }
+            };
+
+            match flow {
+                BlockFlow::Next => {}
+                BlockFlow::Wait(ticks, mut rest) => {
+                    rest.extend(Self::continuation_after(codes, i));
+                    return BlockFlow::Wait(ticks, rest);
+                }
+                other => return other,
+            }
+        }
+        BlockFlow::Next
+    }
+
+    // Whatever comes after codes[index], minus any else branches belonging to the chain we're in
+    fn continuation_after(codes: &[Code], index: usize) -> Vec<Code> {
+        codes.iter()
+            .skip(index + 1)
+            .skip_while(|code| matches!(code.block.to_lowercase().as_str(), "else" | "else_if"))
+            .cloned()
+            .collect()
+    }
+
+    // The rest of the body is wrapped so a break or continue in it only ends the loop, not the blocks after it
+    fn loop_continuation(rest: Vec<Code>, next: Option<Code>) -> Code {
+        Code {
+            negated: false,
+            args: next.and_then(|next| serde_json::to_value(next).ok()).into_iter().collect(),
+            block: RESUME_LOOP_BLOCK.to_string(),
+            subcode: rest,
+            priority: 0,
+        }
+    }
+
+    fn resume_loop(&mut self, engine_data: &mut EngineData, code: &Code) -> BlockFlow {
+        let next: Option<Code> = code.args.get(0).and_then(|next| serde_json::from_value(next.clone()).ok());
+        match self.run_blocks(engine_data, &code.subcode) {
+            BlockFlow::Break => BlockFlow::Next,
+            BlockFlow::Return => BlockFlow::Return,
+            BlockFlow::Wait(ticks, rest) => BlockFlow::Wait(ticks, vec![Self::loop_continuation(rest, next)]),
+            BlockFlow::Next | BlockFlow::Continue => match next {
+                Some(next) => self.run_block(engine_data, &next),
+                None => BlockFlow::Next,
+            },
+        }
+    }
+
+    fn ticks_from_arg(&mut self, engine_data: &mut EngineData, code: &Code, in_seconds: bool) -> u64 {
+        let amount = match code.args.get(0) {
+            Some(value) => ScriptValue::parse(&self.get_expr(&ScriptingAPI::arg_to_string(value), engine_data)).as_f64(),
+            None => None,
+        };
+        match amount {
+            Some(amount) if in_seconds => (amount.max(0.0) * TICKS_PER_SECOND as f64).round() as u64,
+            Some(amount) => amount.max(0.0) as u64,
+            None => {
+                self.logger.log_error("Event Manager", format!("'{}' needs a number of {}.", code.block, if in_seconds { "seconds" } else { "ticks" }).as_str());
+                0
             }
         }
     }
 
-    pub fn run_block(&mut self, engine_data: &mut EngineData, code: &Code) {
-        if let Some(func) = self.code_blocks.get(&code.block.to_lowercase()) {
-            let args: Vec<Value> = code.args.clone();
-            let result = func(engine_data, self, &args);
-            if result {
-                for subcode in code.subcode.iter() {
-                    self.run_block(engine_data, subcode);
+    pub fn schedule(&mut self, name: String, ticks: u64, interval: Option<u64>, code: Vec<Code>) {
+        if !name.is_empty() {
+            // Restarting a named timer replaces the old one instead of running both
+            self.cancel_timer(&name);
+        }
+        self.next_task_id += 1;
+        self.scheduled.push(ScheduledTask {
+            id: self.next_task_id,
+            name,
+            due_tick: self.script_tick + ticks,
+            interval,
+            code,
+            event: self.current_event.clone(),
+            scope: self.active_scope.clone(),
+        });
+    }
+
+    pub fn cancel_timer(&mut self, name: &str) -> bool {
+        let count = self.scheduled.len();
+        self.scheduled.retain(|task| task.name != name);
+        count != self.scheduled.len()
+    }
+
+    pub fn is_timer_running(&self, name: &str) -> bool {
+        self.scheduled.iter().any(|task| task.name == name)
+    }
+
+    // Called once per tick, runs every task that's due in the order they were scheduled
+    pub fn run_scheduled(&mut self, engine_data: &mut EngineData) {
+        self.run_scheduled_in(engine_data, |_| true);
+    }
+
+    // Tasks outside the scopes sit the tick out, their due tick moves along so they don't count it
+    pub fn run_scheduled_in<F>(&mut self, engine_data: &mut EngineData, in_scope: F)
+    where
+        F: Fn(&ListenerScope) -> bool,
+    {
+        self.script_tick += 1;
+        let tick = self.script_tick;
+        for task in self.scheduled.iter_mut().filter(|task| !in_scope(&task.scope)) {
+            task.due_tick += 1;
+        }
+        let due: Vec<u64> = self.scheduled.iter()
+            .filter(|task| task.due_tick <= tick && in_scope(&task.scope))
+            .map(|task| task.id)
+            .collect();
+
+        for id in due {
+            // A task that ran earlier this tick may have cancelled this one, or loaded a snapshot that replaced them all
+            let index = match self.scheduled.iter().position(|task| task.id == id && task.due_tick <= self.script_tick) {
+                Some(index) => index,
+                None => continue,
+            };
+            let mut task = self.scheduled.remove(index);
+            let event = task.event.clone();
+            let scope = task.scope.clone();
+            let code = match task.interval {
+                Some(interval) => {
+                    let code = task.code.clone();
+                    task.due_tick = tick + interval;
+                    self.scheduled.push(task);
+                    code
                 }
+                None => task.code,
+            };
+            let previous_event = std::mem::replace(&mut self.current_event, event);
+            let previous_scope = std::mem::replace(&mut self.active_scope, scope);
+            if let BlockFlow::Wait(ticks, rest) = self.run_blocks(engine_data, &code) {
+                self.schedule(String::new(), ticks, None, rest);
             }
+            self.current_event = previous_event;
+            self.active_scope = previous_scope;
+        }
+    }
+
+    fn call_code_block(&mut self, engine_data: &mut EngineData, code: &Code) -> bool {
+        if let Some(func) = self.code_blocks.get(&code.block.to_lowercase()).cloned() {
+            func(engine_data, self, &code.args) != code.negated
         } else {
             self.logger.log("Event Manager", format!("Function for code block '{}' not found", code.block).as_str());
+            false
+        }
+    }
+
+    fn run_repeat(&mut self, engine_data: &mut EngineData, code: &Code) -> BlockFlow {
+        let count = match code.args.get(0) {
+            Some(value) => ScriptValue::parse(&self.get_expr(&ScriptingAPI::arg_to_string(value), engine_data)).as_i64(),
+            None => None,
+        };
+        let count = match count {
+            Some(count) => count.max(0) as usize,
+            None => {
+                self.logger.log_error("Event Manager", "'repeat' needs a number of times to repeat.");
+                return BlockFlow::Next;
+            }
+        };
+        // Optional second arg names a variable that holds the current iteration, third is where it starts counting
+        let index_var = code.args.get(1).map(ScriptingAPI::arg_to_string).unwrap_or_default();
+        let start = code.args.get(2).and_then(|value| ScriptValue::from_json(value).as_i64()).unwrap_or(0);
+        let count = count.min(MAX_LOOP_ITERATIONS);
+
+        for i in 0..count {
+            if !index_var.is_empty() {
+                self.set_variable_value(index_var.clone(), ScriptValue::Int(start + i as i64));
+            }
+            match self.run_blocks(engine_data, &code.subcode) {
+                BlockFlow::Break => break,
+                BlockFlow::Return => return BlockFlow::Return,
+                BlockFlow::Wait(ticks, rest) => {
+                    // Pick the loop back up with whatever iterations are left once the wait is over
+                    let remaining = count - i - 1;
+                    let next = (remaining > 0).then(|| Code {
+                        negated: false,
+                        args: vec![Value::from(remaining as u64), Value::from(index_var.clone()), Value::from(start + i as i64 + 1)],
+                        block: "repeat".to_string(),
+                        subcode: code.subcode.clone(),
+                        priority: 0,
+                    });
+                    return BlockFlow::Wait(ticks, vec![Self::loop_continuation(rest, next)]);
+                }
+                _ => {}
+            }
+        }
+        BlockFlow::Next
+    }
+
+    fn run_while(&mut self, engine_data: &mut EngineData, code: &Code) -> BlockFlow {
+        let mut iterations = 0;
+        while self.condition_from_args(engine_data, &code.args) != code.negated {
+            if iterations >= MAX_LOOP_ITERATIONS {
+                self.logger.log_error("Event Manager", format!("'while' loop hit the {} iteration limit, stopping it.", MAX_LOOP_ITERATIONS).as_str());
+                break;
+            }
+            iterations += 1;
+            match self.run_blocks(engine_data, &code.subcode) {
+                BlockFlow::Break => break,
+                BlockFlow::Return => return BlockFlow::Return,
+                BlockFlow::Wait(ticks, rest) => {
+                    // The loop runs again once the wait is over, condition and all
+                    return BlockFlow::Wait(ticks, vec![Self::loop_continuation(rest, Some(code.clone()))]);
+                }
+                _ => {}
+            }
+        }
+        BlockFlow::Next
+    }
+
+    // else_if and while take either condition blocks or the same lhs/op/rhs args as compare_values
+    fn condition_from_args(&mut self, engine_data: &mut EngineData, args: &[Value]) -> bool {
+        match args.get(0) {
+            Some(Value::Object(_)) => args.iter().all(|condition| self.evaluate_condition(engine_data, condition)),
+            Some(_) => ScriptingAPI::compare_values(engine_data, self, args),
+            None => true,
+        }
+    }
+
+    fn define_function(&mut self, code: &Code, scope: ListenerScope) {
+        let mut names = code.args.iter().map(ScriptingAPI::arg_to_string);
+        let name = match names.next() {
+            Some(name) if !name.is_empty() => name,
+            _ => {
+                self.logger.log_error("Event Manager", "Function definition is missing a name.");
+                return;
+            }
+        };
+        self.logger.log("Event Manager", format!("Registering Function: {}", name).as_str());
+        // Defining it again from the same scope replaces that scope's version
+        let definitions = self.functions.entry(name).or_default();
+        definitions.retain(|function| function.scope != scope);
+        definitions.push(ScriptFunction {
+            params: names.collect(),
+            body: code.subcode.clone(),
+            scope,
+        });
+    }
+
+    // Ok(None) when the function finished without returning anything
+    pub fn call_function(&mut self, engine_data: &mut EngineData, name: &str, args: Vec<ScriptValue>) -> Result<Option<ScriptValue>, String> {
+        let function = match self.functions.get(name).and_then(|definitions| definitions.last()) {
+            Some(function) => function.clone(),
+            None => return Err(format!("Function '{}' not found", name)),
+        };
+        if self.call_depth >= MAX_CALL_DEPTH {
+            return Err(format!("Function '{}' went over the max call depth of {}.", name, MAX_CALL_DEPTH));
+        }
+
+        // Params shadow variables of the same name for the duration of the call
+        let mut shadowed = Vec::with_capacity(function.params.len());
+        for (i, param) in function.params.iter().enumerate() {
+            let value = args.get(i).cloned().unwrap_or(ScriptValue::Str(String::new()));
+            shadowed.push((param.clone(), self.variables.insert(param.clone(), value)));
+        }
+
+        self.call_depth += 1;
+        self.return_value = None;
+        if let BlockFlow::Wait(_, _) = self.run_blocks(engine_data, &function.body) {
+            self.logger.log_warn("Event Manager", format!("Function '{}' tried to wait, waits aren't supported inside functions.", name).as_str());
+        }
+        self.call_depth -= 1;
+
+        for (param, previous) in shadowed {
+            match previous {
+                Some(value) => self.variables.insert(param, value),
+                None => self.variables.remove(&param),
+            };
+        }
+        Ok(self.return_value.take())
+    }
+
+    // Runs a block only for its result, used by and/or/not where the args are blocks themselves
+    pub fn evaluate_condition(&mut self, engine_data: &mut EngineData, condition: &Value) -> bool {
+        let code: Code = match serde_json::from_value(condition.clone()) {
+            Ok(code) => code,
+            Err(e) => {
+                self.logger.log_error("Event Manager", format!("Invalid condition block: {}", e).as_str());
+                return false;
+            }
+        };
+
+        match self.code_blocks.get(&code.block.to_lowercase()).cloned() {
+            Some(func) => func(engine_data, self, &code.args) != code.negated,
+            None => {
+                self.logger.log_error("Event Manager", format!("Function for condition block '{}' not found", code.block).as_str());
+                false
+            }
         }
     }
 
@@ -100,13 +855,21 @@ impl EventManager {
         let mut result = expression.to_string();
 
         let re_random = Regex::new(r"%random\(([^,]+),([^)]+)\)").unwrap();
-        let mut rng = rand::thread_rng();
 
-        while re_random.is_match(&result) {
+        // A bad one is left as it is, and stops the loop since it would match forever
+        let mut bad_random = false;
+        while !bad_random && re_random.is_match(&result) {
             result = re_random.replace_all(&result, |caps: &regex::Captures| {
-                let a = self.get_expr(caps.get(1).unwrap().as_str(), engine_data).trim().parse::<i32>().unwrap();
-                let b = self.get_expr(caps.get(2).unwrap().as_str(), engine_data).trim().parse::<i32>().unwrap();
-                rng.gen_range(a..b).to_string()
+                let a = self.get_expr(caps.get(1).unwrap().as_str(), engine_data).trim().parse::<i32>();
+                let b = self.get_expr(caps.get(2).unwrap().as_str(), engine_data).trim().parse::<i32>();
+                match (a, b) {
+                    (Ok(a), Ok(b)) if a < b => engine_data.rng.gen_range(a..b).to_string(),
+                    _ => {
+                        engine_data.logger.log_error("EventManager", format!("{} needs two whole numbers, the first lower than the second.", &caps[0]).as_str());
+                        bad_random = true;
+                        caps[0].to_string()
+                    }
+                }
             }).to_string();
         }
 
@@ -116,7 +879,11 @@ impl EventManager {
             ("math", r"%math\((.*?)\)"),
             ("ai", r"%ai\((.*?)\)"),
             ("mouse", r"%mouse\((.*?)\)"),
-            ("game", r"%game\((.*?)\)")
+            ("game", r"%game\((.*?)\)"),
+            ("event", r"%event\((.*?)\)"),
+            ("slot", r"%slot\((.*?)\)"),
+            // Last, so the other expressions in its args are already filled in
+            ("custom", r"%custom\((.*?)\)")
         ];
 
         for (expr_type, pattern) in &patterns {
@@ -126,12 +893,15 @@ impl EventManager {
                 result = re.replace_all(&result, |caps: &regex::Captures| {
                     let content = caps.get(1).unwrap().as_str();
                     let replacement = match *expr_type {
-                        "var" => self.variables.get(content).cloned().ok_or_else(|| format!("Variable '{}' not found.", content)),
-                        "data" => self.data_values.get(content).cloned().ok_or_else(|| format!("Data Value '{}' not found.", content)),
+                        "var" => self.variables.get(content).map(|v| v.to_string()).ok_or_else(|| format!("Variable '{}' not found.", content)),
+                        "data" => self.data_values.get(content).map(|v| v.to_string()).ok_or_else(|| format!("Data Value '{}' not found.", content)),
                         "math" => Ok(self.evaluate_math_expression(content, engine_data).unwrap()),
-                        "ai" => Ok(self.evaluate_ai_expression(content, engine_data).unwrap().to_string()),
+                        "ai" => self.evaluate_ai_expression(content, engine_data).map(|level| level.to_string()).map_err(|e| e.to_string()),
                         "mouse" => Err("Mouse expressions are not implemented".to_string()),
-                        "game" => Err("Game expressions are not implemented".to_string()),
+                        "game" => Self::game_expression(content, engine_data),
+                        "event" => self.event_expression(content),
+                        "slot" => engine_data.save.slot_expression(content),
+                        "custom" => self.custom_expression(content, engine_data),
                         _ => Err(format!("Unknown expression type: {}", expr_type)),
                     };
 
@@ -143,12 +913,95 @@ impl EventManager {
         Ok(result)
     }
 
-    fn set_variable_value(&mut self, name: String, data: String) {
+    fn set_variable_value(&mut self, name: String, data: ScriptValue) {
         self.variables.insert(name, data);
     }
 
-    fn set_data_value(&mut self, name: String, data: String) {
+    fn set_data_value(&mut self, name: String, data: ScriptValue) {
         self.data_values.insert(name, data);
+        self.data_dirty = true;
+    }
+
+    pub fn get_variable_value(&self, name: &str) -> Option<&ScriptValue> {
+        self.variables.get(name)
+    }
+
+    pub fn get_data_value(&self, name: &str) -> Option<&ScriptValue> {
+        self.data_values.get(name)
+    }
+
+    pub fn load_data_values(&mut self, data_values: HashMap<String, ScriptValue>) {
+        self.data_values = data_values;
+        self.data_dirty = false;
+    }
+
+    fn remove_data_value(&mut self, name: &str) -> Option<ScriptValue> {
+        let removed = self.data_values.remove(name);
+        self.data_dirty |= removed.is_some();
+        removed
+    }
+
+    fn clear_data_values(&mut self) {
+        self.data_values.clear();
+        self.data_dirty = true;
+    }
+
+    pub fn flush_data_values(&mut self, engine_data: &mut EngineData) {
+        match engine_data.save.save(&self.data_values) {
+            Ok(()) => self.data_dirty = false,
+            Err(e) => engine_data.logger.log_error("Event Manager", format!("Failed to write save file {:?}: {}", engine_data.save.path(), e).as_str()),
+        }
+    }
+
+    // Writes the save only if something changed since the last write
+    pub fn flush_dirty_data(&mut self, engine_data: &mut EngineData) {
+        if self.data_dirty {
+            self.flush_data_values(engine_data);
+        }
+    }
+
+    pub fn mark_data_dirty(&mut self) {
+        self.data_dirty = true;
+    }
+
+    // Only the night's own waits and timers go in, menu and global timers keep running through a restore
+    fn is_night_task(task: &ScheduledTask) -> bool {
+        matches!(task.scope, ListenerScope::Office(_) | ListenerScope::Night(_))
+    }
+
+    pub fn capture_script_state(&self) -> ScriptState {
+        ScriptState {
+            variables: self.variables.clone(),
+            scheduled: self.scheduled.iter().filter(|task| Self::is_night_task(task)).cloned().collect(),
+            script_tick: self.script_tick,
+        }
+    }
+
+    // The clock keeps going, the night's tasks come back with as many ticks left as they had and new
+    // ids, so they can't be mixed up with the menu and global tasks that stayed
+    pub fn restore_script_state(&mut self, state: ScriptState) {
+        self.variables = state.variables;
+        self.scheduled.retain(|task| !Self::is_night_task(task));
+        for mut task in state.scheduled.into_iter().filter(|task| Self::is_night_task(task)) {
+            self.next_task_id += 1;
+            task.id = self.next_task_id;
+            task.due_tick = task.due_tick.saturating_sub(state.script_tick) + self.script_tick;
+            self.scheduled.push(task);
+        }
+    }
+
+    // Saves the slot being left and swaps in the data values of the new one
+    pub fn select_save_slot(&mut self, slot: &str, engine_data: &mut EngineData) -> bool {
+        match engine_data.save.select_slot(slot, &self.data_values) {
+            Ok(data_values) => {
+                self.data_values = data_values;
+                true
+            }
+            Err(e) => {
+                engine_data.logger.log_error("Event Manager", format!("Failed to switch to save slot '{}': {}", slot, e).as_str());
+                false
+            }
+        }
     }
 
     pub fn evaluate_math_expression(&self, expression: &str, engine_data: &mut EngineData) -> Result<String, Box<dyn Error>> {
@@ -161,22 +1014,56 @@ impl EventManager {
         }
     }
 
+    // %ai(name), the animatronic's AI level for the current night
     fn evaluate_ai_expression(&self, expression: &str, engine_data: &mut EngineData) -> Result<i32, Box<dyn std::error::Error>> {
-  //      let regex = Regex::new(r"%ai\((.*?)\)").unwrap();
-  //      let args = regex.captures(expression)
-  //          .and_then(|caps| caps.get(1))
-   //         .map(|m| m.as_str())
-    //        .ok_or_else(|| "Invalid AI expression.").unwrap();
-    //    let arg_array: Vec<&str> = args.split(',').map(|s| s.trim()).collect();
-    //    if arg_array.len() != 1 {
-   //         //  Err("Invalid number of arguments for %ai().".into()).expect("etghedtshwjhewtrsh");
-  //      }
-   //     let animatronic = arg_array[0];
-        return Ok(0);
-        // TODO: make compiler happy
-        // engine_data.game.animatronics.get(animatronic)
-        //      .and_then(|animatronic_map| animatronic_map.AI.clone().expect("Error accessing AI vector for Animatronic").get(engine_data.officemgr.Office.night as usize))
-        //      .map_or_else(|| Err(format!("Error accessing AI value for Animatronic '{}' for night '{}'.", animatronic, &engine_data.officemgr.Office.night).into()), |ai_value: &i32| Ok(ai_value.clone()))
+        let name = expression.trim();
+        let game_data = &engine_data.officemgr.game_data;
+        match game_data.animatronics.get(name) {
+            Some(animatronic) => Ok(AnimatronicManager::ai_level(animatronic, game_data.night)),
+            None => Err(format!("Animatronic '{}' not found.", name).into()),
+        }
+    }
+
+    // %custom(name, args...), handled by whatever registered the name
+    fn custom_expression(&self, content: &str, engine_data: &mut EngineData) -> Result<String, String> {
+        let mut parts = content.split(',').map(|part| part.trim().to_string());
+        let name = parts.next().unwrap_or_default();
+        let args: Vec<String> = parts.collect();
+        let handler = self.expressions.get(&name).cloned().ok_or_else(|| format!("Custom expression '{}' not found.", name))?;
+        handler(self, engine_data, &args)
+    }
+
+    // %game(field): power, usage, time, night, tick, scene, menu, office, paused, minigame, player_x, player_y,
+    // or location:<animatronic> / camera:<animatronic> / door:<id> / light:<id>
+    fn game_expression(content: &str, engine_data: &EngineData) -> Result<String, String> {
+        let game_data = &engine_data.officemgr.game_data;
+        let content = content.trim();
+        match content {
+            "power" => return Ok(game_data.power.level.to_string()),
+            "usage" => return Ok(NightManager::power_usage(game_data).to_string()),
+            "time" => return Ok(game_data.time.to_string()),
+            "night" => return Ok(game_data.night.to_string()),
+            "tick" => return Ok(engine_data.clock.get_current_tick().to_string()),
+            "scene" => return Ok((engine_data.scenes.current() as i32).to_string()),
+            "menu" => return Ok(engine_data.menumgr.curmenu.clone()),
+            "office" => return Ok(engine_data.officemgr.curoffice.clone()),
+            "minigame" => return Ok(engine_data.minigamemgr.curminigame.clone()),
+            "player_x" => return Ok(engine_data.minigamemgr.player.x.to_string()),
+            "player_y" => return Ok(engine_data.minigamemgr.player.y.to_string()),
+            "paused" => return Ok(engine_data.clock.is_paused().to_string()),
+            "camera_up" => return Ok(game_data.player.is_camera_up.to_string()),
+            _ => {}
+        }
+        let (field, name) = content.split_once(':').ok_or_else(|| format!("Unknown game field '{}'.", content))?;
+        match field {
+            "location" | "camera" => {
+                let animatronic = game_data.animatronics.get(name).ok_or_else(|| format!("Animatronic '{}' not found.", name))?;
+                Ok(if field == "location" { animatronic.location.id.clone() } else { animatronic.location.camid.clone() })
+            }
+            "door" => game_data.office.doors.get(name).map(|door| door.is_closed.to_string()).ok_or_else(|| format!("Door '{}' not found.", name)),
+            "light" => game_data.office.lights.get(name).map(|light| light.is_on.to_string()).ok_or_else(|| format!("Light '{}' not found.", name)),
+            _ => Err(format!("Unknown game field '{}'.", field)),
+        }
     }
 
     fn evaluate_math(&self, expression: &str) -> String {
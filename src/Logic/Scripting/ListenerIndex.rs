@@ -0,0 +1,214 @@
+// Listeners are looked up by event name, then by their exact args, with wildcard listeners kept
+// on the side. Resolved handler lists get cached until a listener is added or killed.
+
+// Events like current_tick_equals never repeat their args, so the cache has to be capped
+const MAX_CACHED_ARGS_PER_EVENT: usize = 256;
+
+// Who owns a listener, it lives until that owner unloads
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ListenerScope {
+    Global,
+    Menu(String),
+    Office(String),
+    Night(i32),
+    Minigame(String),
+}
+
+pub struct Listener {
+    pub id: u64,
+    pub event: String,
+    // Patterns, "*" matches any arg. The arg counts have to line up, unless the last one is
+    // a lone "*", which takes every arg from there on, none included. No args at all takes any
+    // payload, so listeners written before an event had args still hear it.
+    pub args: Vec<String>,
+    pub subcode: Vec<Code>,
+    pub priority: i32,
+    pub scope: ListenerScope,
+}
+
+impl Listener {
+    pub fn matches(&self, event_name: &str, args: &[String]) -> bool {
+        let takes_rest = self.args.last().map_or(false, |last| last == "*");
+        let lengths_match = if takes_rest { self.args.len() - 1 <= args.len() } else { self.args.len() == args.len() };
+        self.event == event_name
+            && (self.args.is_empty() || lengths_match)
+            && self.args.iter().zip(args).all(|(pattern, arg)| glob_match(pattern, arg))
+    }
+
+    fn is_pattern(&self) -> bool {
+        self.args.is_empty() || self.args.iter().any(|arg| arg.contains('*'))
+    }
+}
+
+#[derive(Default)]
+struct EventListeners {
+    exact: HashMap<Vec<String>, Vec<u64>>,
+    patterns: Vec<u64>,
+}
+
+struct CachedDispatch {
+    generation: u64,
+    handlers: Rc<[u64]>,
+}
+
+pub struct ListenerIndex {
+    listeners: HashMap<u64, Rc<Listener>>,
+    by_event: HashMap<String, EventListeners>,
+    cache: HashMap<String, HashMap<Vec<String>, CachedDispatch>>,
+    next_id: u64,
+    generation: u64,
+}
+
+impl ListenerIndex {
+    pub fn new() -> ListenerIndex {
+        ListenerIndex {
+            listeners: HashMap::new(),
+            by_event: HashMap::new(),
+            cache: HashMap::new(),
+            next_id: 0,
+            generation: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.listeners.len()
+    }
+
+    pub fn insert(&mut self, event: String, args: Vec<String>, subcode: Vec<Code>, priority: i32, scope: ListenerScope) -> u64 {
+        self.next_id += 1;
+        let listener = Listener { id: self.next_id, event, args, subcode, priority, scope };
+
+        let entry = self.by_event.entry(listener.event.clone()).or_default();
+        if listener.is_pattern() {
+            entry.patterns.push(listener.id);
+        } else {
+            entry.exact.entry(listener.args.clone()).or_default().push(listener.id);
+        }
+        self.listeners.insert(listener.id, Rc::new(listener));
+        self.generation += 1;
+        self.next_id
+    }
+
+    pub fn get(&self, id: u64) -> Option<Rc<Listener>> {
+        self.listeners.get(&id).cloned()
+    }
+
+    pub fn remove(&mut self, id: u64) -> bool {
+        let listener = match self.listeners.remove(&id) {
+            Some(listener) => listener,
+            None => return false,
+        };
+        if let Some(entry) = self.by_event.get_mut(&listener.event) {
+            entry.patterns.retain(|other| *other != id);
+            if let Some(ids) = entry.exact.get_mut(&listener.args) {
+                ids.retain(|other| *other != id);
+                if ids.is_empty() {
+                    entry.exact.remove(&listener.args);
+                }
+            }
+        }
+        self.generation += 1;
+        true
+    }
+
+    pub fn remove_where<F>(&mut self, predicate: F) -> usize
+    where
+        F: Fn(&Listener) -> bool,
+    {
+        let ids: Vec<u64> = self.listeners.values()
+            .filter(|listener| predicate(listener))
+            .map(|listener| listener.id)
+            .collect();
+        for id in &ids {
+            self.remove(*id);
+        }
+        ids.len()
+    }
+
+    pub fn clear(&mut self) -> usize {
+        let count = self.listeners.len();
+        self.listeners.clear();
+        self.by_event.clear();
+        self.cache.clear();
+        self.generation += 1;
+        count
+    }
+
+    // Ids of every listener for this event, highest priority first then in registration order
+    pub fn resolve(&mut self, event_name: &str, args: &[String]) -> Rc<[u64]> {
+        if let Some(cached) = self.cache.get(event_name).and_then(|by_args| by_args.get(args)) {
+            if cached.generation == self.generation {
+                return cached.handlers.clone();
+            }
+        }
+
+        let mut ids: Vec<u64> = Vec::new();
+        if let Some(entry) = self.by_event.get(event_name) {
+            if let Some(matched) = entry.exact.get(args) {
+                ids.extend(matched);
+            }
+            ids.extend(entry.patterns.iter().filter(|id| self.listeners[*id].matches(event_name, args)));
+        }
+        ids.sort_by(|a, b| self.listeners[b].priority.cmp(&self.listeners[a].priority).then(a.cmp(b)));
+
+        let handlers: Rc<[u64]> = ids.into();
+        let by_args = self.cache.entry(event_name.to_string()).or_default();
+        if by_args.len() >= MAX_CACHED_ARGS_PER_EVENT {
+            by_args.clear();
+        }
+        by_args.insert(args.to_vec(), CachedDispatch { generation: self.generation, handlers: handlers.clone() });
+        handlers
+    }
+}
+
+// Only understands '*', which is all listener patterns need
+fn glob_match(pattern: &str, text: &str) -> bool {
+    if !pattern.contains('*') {
+        return pattern == text;
+    }
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !text.starts_with(first) || text.len() < first.len() + last.len() || !text.ends_with(last) {
+        return false;
+    }
+    let mut rest = &text[first.len()..text.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod listener_index {
+    use super::*;
+
+    fn listener(index: &mut ListenerIndex, event: &str, args: &[&str]) -> u64 {
+        index.insert(event.to_string(), args.iter().map(|arg| arg.to_string()).collect(), Vec::new(), 0, ListenerScope::Global)
+    }
+
+    #[test]
+    fn listeners_without_args_hear_any_payload() {
+        let mut index = ListenerIndex::new();
+        let legacy = listener(&mut index, "any_button_selected", &[]);
+        let play = listener(&mut index, "any_button_selected", &["Play"]);
+
+        assert_eq!(&*index.resolve("any_button_selected", &["Play".to_string()]), &[legacy, play]);
+        assert_eq!(&*index.resolve("any_button_selected", &["Quit".to_string()]), &[legacy]);
+        assert_eq!(&*index.resolve("any_button_selected", &[]), &[legacy]);
+    }
+
+    #[test]
+    fn arg_counts_still_have_to_match() {
+        let mut index = ListenerIndex::new();
+        let pair = listener(&mut index, "on_hour", &["1", "*"]);
+        let exact = listener(&mut index, "on_hour", &["1"]);
+
+        assert_eq!(&*index.resolve("on_hour", &["1".to_string()]), &[pair, exact]);
+        assert!(index.resolve("on_hour", &["2".to_string()]).is_empty());
+        assert!(index.resolve("on_hour", &["1".to_string(), "2".to_string(), "3".to_string()]).contains(&pair));
+        assert!(!index.resolve("on_hour", &["1".to_string(), "2".to_string()]).contains(&exact));
+    }
+}
@@ -1,71 +1,502 @@
-use mlua::{Lua, Function, UserData, UserDataMethods, FromLua, FromLuaMulti, IntoLuaMulti};
+use std::cell::Cell;
+use mlua::{Lua, Function, RegistryKey, Table, AnyUserData, UserData, UserDataMethods, Variadic};
 
+// Luau has no per instruction hook, its interrupt fires on every call and loop back edge instead.
+// This many of those per script run is plenty for a handler and stops `while true do end` cold.
+const LUA_INSTRUCTION_LIMIT: u32 = 1_000_000;
+const LUA_MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+// Registry name of the table between the script environments and the globals, Game and RunBlock live in it
+const LUA_HOST_TABLE: &str = "host";
+
+// A function handed to On(event, handler), it lives as long as the scope of the file that registered it.
+// The event manager gives each one a listener, so it takes its turn by priority with the FEScript ones.
+struct LuaHandler {
+    event: String,
+    priority: i32,
+    scope: ListenerScope,
+    // File it came from, a crash turns off everything from the same file
+    script: String,
+    key: RegistryKey,
+}
+
+// The file whose code is running, On() and RegisterBlock() file what they get under it
+#[derive(Clone)]
+struct LuaRunning {
+    script: String,
+    scope: ListenerScope,
+}
+
+// A loaded file. Its globals go in its own environment, so files can't step on each other.
+struct LuaScript {
+    // Which load of the file this is, things it registered only work while it's still this one
+    load: u64,
+    scope: ListenerScope,
+    env: RegistryKey,
+}
+
+// A function handed to RegisterBlock or RegisterExpression, waiting for the event manager to pick it up
+struct LuaRegistration {
+    name: String,
+    expression: bool,
+    script: String,
+    load: u64,
+    key: RegistryKey,
+}
+
+// Kept in the Lua app data so the globals can get to it from inside a script
+struct LuaState {
+    running: LuaRunning,
+    // Set while a %custom expression runs, the event manager can't pick up anything new from there
+    expression: bool,
+    next_id: u64,
+    scripts: HashMap<String, LuaScript>,
+    handlers: HashMap<u64, LuaHandler>,
+    // Handlers the event manager still has to add listeners for, and ones whose listeners have to go
+    added: Vec<u64>,
+    dropped: Vec<u64>,
+    registrations: Vec<LuaRegistration>,
+}
+
+// Lua scripts run next to FEScript. Game state is handed over as the Game table, which only exists
+// while a script is running:
+//   Game.Night, Game.Time                   numbers
+//   Game.Power, Game.Player, Game.Settings  read and written like fields
+//   Game.Office, Game.Animatronics[name], Game.Cameras[name]
+//   Game.IsKeyDown(key), Game.GotoMenu(menu)
+// Changes land in the game when the script returns, or before a RunBlock so the block sees them.
+// On(event, function(...) end, priority) listens for engine events, the event args come in as strings.
+// Handlers and FEScript listeners run highest priority first, the priority is optional and defaults to 0.
+// RunBlock(block, ...) runs any FEScript code block with the given args right away and returns its result.
+// RegisterBlock(name, function(...) end) adds a code block, its result is the block's result.
+// RegisterExpression(name, function(...) end) answers %custom(name, args...) with what it returns.
+// Neither can replace a built in block or one a plugin registered.
+// Scripts run in Luau's sandbox with an environment each and without os or debug, the standard
+// library is read only and globals one file sets aren't seen by another. A script that runs too
+// long, eats too much memory or errors gets turned off, along with its handlers, blocks and
+// expressions, and the error logged with its file and line.
 struct LuaBindings {
     lua: Lua,
+    // Interrupts left before the running script gets stopped
+    budget: Rc<Cell<u32>>,
+    logger: Logger,
 }
 
 impl LuaBindings {
-    fn new() -> Self {
-        LuaBindings { lua: Lua::new() }
+    fn new(logger: Logger) -> Self {
+        let lua = Lua::new();
+        lua.set_app_data(LuaState {
+            running: LuaRunning { script: String::new(), scope: ListenerScope::Global },
+            expression: false,
+            next_id: 0,
+            scripts: HashMap::new(),
+            handlers: HashMap::new(),
+            added: Vec::new(),
+            dropped: Vec::new(),
+            registrations: Vec::new(),
+        });
+
+        let budget = Rc::new(Cell::new(LUA_INSTRUCTION_LIMIT));
+        let remaining = budget.clone();
+        lua.set_interrupt(move |_| {
+            // Stays at zero, so a pcall around the loop just trips it again
+            match remaining.get() {
+                0 => Err(mlua::Error::RuntimeError("script ran too long and was stopped".to_string())),
+                left => {
+                    remaining.set(left - 1);
+                    Ok(mlua::VmState::Continue)
+                }
+            }
+        });
+        if let Err(e) = lua.set_memory_limit(LUA_MEMORY_LIMIT) {
+            logger.log_error("Lua", format!("Failed to set the Lua memory limit: {}", e).as_str());
+        }
+
+        let bindings = LuaBindings { lua, budget, logger };
+        if let Err(e) = bindings.register_globals() {
+            bindings.logger.log_error("Lua", format!("Failed to set up the Lua globals: {}", e).as_str());
+        }
+        bindings
     }
 
-    fn exec_script(&self, script: &str) -> mlua::Result<()> {
-        self.lua.load(script).exec()
+    fn state(lua: &Lua) -> mlua::Result<std::cell::RefMut<'_, LuaState>> {
+        lua.app_data_mut::<LuaState>().ok_or_else(|| mlua::Error::RuntimeError("Lua state is missing".to_string()))
     }
 
-    fn set_global<T: 'static + mlua::UserData>(&self, name: &str, value: T)  -> mlua::Result<()>
+    // The sandbox makes the globals read only, so ours have to be in before it's turned on
+    fn register_globals(&self) -> mlua::Result<()> {
+        let globals = self.lua.globals();
+        // Lua::new opens os and debug on Luau too, scripts get neither
+        globals.raw_set("os", mlua::Value::Nil)?;
+        globals.raw_set("debug", mlua::Value::Nil)?;
+        globals.set("On", self.lua.create_function(|lua, (event, handler, priority): (String, Function, Option<i32>)| {
+            let mut state = Self::state(lua)?;
+            Self::refuse_in_expression(&state, "On")?;
+            let key = lua.create_registry_value(handler)?;
+            let LuaRunning { script, scope } = state.running.clone();
+            state.next_id += 1;
+            let id = state.next_id;
+            state.handlers.insert(id, LuaHandler { event, priority: priority.unwrap_or(0), scope, script, key });
+            state.added.push(id);
+            Ok(())
+        })?)?;
+        globals.set("RegisterBlock", self.lua.create_function(|lua, (name, function): (String, Function)| {
+            Self::register(lua, "RegisterBlock", name, false, function)
+        })?)?;
+        globals.set("RegisterExpression", self.lua.create_function(|lua, (name, function): (String, Function)| {
+            Self::register(lua, "RegisterExpression", name, true, function)
+        })?)?;
+        self.lua.sandbox(true)?;
+
+        let host = self.lua.create_table()?;
+        host.set_metatable(Some(Self::proxy_to(&self.lua, self.lua.globals())?));
+        self.lua.set_named_registry_value(LUA_HOST_TABLE, host)?;
+        Ok(())
+    }
+
+    // Metatable that reads missing keys from `table`. Scripts can't swap it out with setmetatable.
+    fn proxy_to<'lua>(lua: &'lua Lua, table: Table<'lua>) -> mlua::Result<Table<'lua>> {
+        let metatable = lua.create_table()?;
+        metatable.set("__index", table)?;
+        metatable.set("__metatable", "locked")?;
+        Ok(metatable)
+    }
+
+    fn host(&self) -> mlua::Result<Table<'_>> {
+        self.lua.named_registry_value(LUA_HOST_TABLE)
+    }
+
+    fn register(lua: &Lua, global: &str, name: String, expression: bool, function: Function) -> mlua::Result<()> {
+        let mut state = Self::state(lua)?;
+        Self::refuse_in_expression(&state, global)?;
+        let key = lua.create_registry_value(function)?;
+        let script = state.running.script.clone();
+        let load = state.scripts.get(&script).map_or(0, |loaded| loaded.load);
+        state.registrations.push(LuaRegistration { name, expression, script, load, key });
+        Ok(())
+    }
+
+    fn refuse_in_expression(state: &LuaState, global: &str) -> mlua::Result<()> {
+        match state.expression {
+            true => Err(mlua::Error::RuntimeError(format!("{} can't be used inside a %custom expression", global))),
+            false => Ok(()),
+        }
+    }
+
+    // Blocks take their args as text like they would from a script file
+    fn lua_to_json(value: &mlua::Value) -> Value {
+        match value {
+            mlua::Value::Boolean(b) => Value::String(b.to_string()),
+            mlua::Value::Integer(int) => Value::String(int.to_string()),
+            mlua::Value::Number(number) => Value::String(number.to_string()),
+            mlua::Value::String(s) => Value::String(s.to_string_lossy().to_string()),
+            _ => Value::Null,
+        }
+    }
+
+    fn lua_to_text(value: &mlua::Value) -> String {
+        match Self::lua_to_json(value) {
+            Value::String(text) => text,
+            _ => String::new(),
+        }
+    }
+
+    // New On() handlers as (id, event, priority, scope), for the event manager to add listeners for
+    fn take_added_handlers(&self) -> Vec<(u64, String, i32, ListenerScope)> {
+        let mut state = match self.lua.app_data_mut::<LuaState>() {
+            Some(state) => state,
+            None => return Vec::new(),
+        };
+        let added = std::mem::take(&mut state.added);
+        added.into_iter()
+            .filter_map(|id| state.handlers.get(&id).map(|handler| (id, handler.event.clone(), handler.priority, handler.scope.clone())))
+            .collect()
+    }
+
+    fn take_dropped_handlers(&self) -> Vec<u64> {
+        self.lua.app_data_mut::<LuaState>().map(|mut state| std::mem::take(&mut state.dropped)).unwrap_or_default()
+    }
+
+    fn take_registrations(&self) -> Vec<LuaRegistration> {
+        self.lua.app_data_mut::<LuaState>().map(|mut state| std::mem::take(&mut state.registrations)).unwrap_or_default()
+    }
+
+    // Blocks and expressions a file registered stop working once it's turned off or its scope ends
+    fn is_loaded(&self, script: &str, load: u64) -> bool {
+        self.lua.app_data_ref::<LuaState>()
+            .map_or(false, |state| state.scripts.get(script).map_or(false, |loaded| loaded.load == load))
+    }
+
+    fn set_running(&self, running: LuaRunning, expression: bool) -> (LuaRunning, bool) {
+        match self.lua.app_data_mut::<LuaState>() {
+            Some(mut state) => (std::mem::replace(&mut state.running, running), std::mem::replace(&mut state.expression, expression)),
+            None => (running, expression),
+        }
+    }
+
+    // Runs a function from RegisterBlock or RegisterExpression with the game state lent out
+    fn call_registered<R, F>(&self, key: &RegistryKey, running: LuaRunning, args: &[String], engine: &mut EngineData, events: Option<&mut EventManager>, convert: F) -> mlua::Result<R>
     where
-        T: IntoLuaMulti<'static>,
+        F: FnOnce(mlua::Value) -> R,
     {
-        self.lua.globals().set(name, value)
+        let function: Function = self.lua.registry_value(key)?;
+        self.with_game(engine, events, running, |_| {
+            function.call::<_, mlua::Value>(args.iter().cloned().collect::<Variadic<String>>()).map(convert)
+        })
+    }
+
+    // Replaces what's in the table with the game's current copy. Userdata already in it is updated in
+    // place, so a script holding on to Game.Power sees the new values.
+    fn copy_in<T: UserData + Clone + 'static>(table: &Table, key: &str, value: &T) -> mlua::Result<()> {
+        match table.get::<_, Option<AnyUserData>>(key)? {
+            Some(data) => *data.borrow_mut::<T>()? = value.clone(),
+            None => table.set(key, value.clone())?,
+        }
+        Ok(())
+    }
+
+    fn copy_out<T: UserData + Clone + 'static>(table: &Table, key: &str, value: &mut T) -> mlua::Result<()> {
+        if let Some(data) = table.get::<_, Option<AnyUserData>>(key)? {
+            *value = data.borrow::<T>()?.clone();
+        }
+        Ok(())
+    }
+
+    fn copy_game_in(lua: &Lua, game: &Table, engine: &EngineData) -> mlua::Result<()> {
+        let game_data = &engine.officemgr.game_data;
+        game.set("Night", game_data.night)?;
+        game.set("Time", game_data.time)?;
+        Self::copy_in(game, "Power", &game_data.power)?;
+        Self::copy_in(game, "Player", &game_data.player)?;
+        Self::copy_in(game, "Settings", &game_data.settings)?;
+        Self::copy_in(game, "Office", &game_data.office)?;
+
+        let animatronics: Table = match game.get::<_, Option<Table>>("Animatronics")? {
+            Some(table) => table,
+            None => lua.create_table()?,
+        };
+        for (name, animatronic) in &game_data.animatronics {
+            Self::copy_in(&animatronics, name, animatronic)?;
+        }
+        game.set("Animatronics", animatronics)?;
+        let cameras: Table = match game.get::<_, Option<Table>>("Cameras")? {
+            Some(table) => table,
+            None => lua.create_table()?,
+        };
+        for (name, camera) in &game_data.cameras {
+            Self::copy_in(&cameras, name, camera)?;
+        }
+        game.set("Cameras", cameras)?;
+        Ok(())
     }
 
-    fn call_function<A, R>(&self, func_name: &str, args: A)
+    fn copy_game_out(game: &Table, engine: &mut EngineData) -> mlua::Result<()> {
+        let game_data = &mut engine.officemgr.game_data;
+        game_data.night = game.get("Night")?;
+        let time: i32 = game.get("Time")?;
+        if time != game_data.time {
+            game_data.time = time;
+            game_data.time_ticks = 0;
+        }
+        Self::copy_out(game, "Power", &mut game_data.power)?;
+        Self::copy_out(game, "Player", &mut game_data.player)?;
+        Self::copy_out(game, "Settings", &mut game_data.settings)?;
+        Self::copy_out(game, "Office", &mut game_data.office)?;
+
+        let animatronics: Table = game.get("Animatronics")?;
+        for (name, animatronic) in game_data.animatronics.iter_mut() {
+            Self::copy_out(&animatronics, name, animatronic)?;
+        }
+        let cameras: Table = game.get("Cameras")?;
+        for (name, camera) in game_data.cameras.iter_mut() {
+            Self::copy_out(&cameras, name, camera)?;
+        }
+        Ok(())
+    }
+
+    // Lends the game to Lua as the Game table for as long as `run` takes. Handlers can run blocks that fire
+    // events with Lua handlers of their own, so whatever was lent before is put back afterwards.
+    // Without an event manager, as in a %custom expression, RunBlock and GotoMenu refuse to run.
+    fn with_game<R, F>(&self, engine: &mut EngineData, events: Option<&mut EventManager>, running: LuaRunning, run: F) -> mlua::Result<R>
     where
-        A: IntoLuaMulti<'static>,
-        R: FromLuaMulti<'static>,
+        F: FnOnce(&Lua) -> mlua::Result<R>,
     {
-    //    let func: Function = self.lua.globals().get(func_name).expect("UNABLE TO FIND FUNCTION");
-       // func.call(args).expect("FAILED TO RUN FUNCTION");
+        let lua = &self.lua;
+        let host = self.host()?;
+        let game = lua.create_table()?;
+        Self::copy_game_in(lua, &game, engine)?;
+        let previous_game: mlua::Value = host.get("Game")?;
+        let previous_run_block: mlua::Value = host.get("RunBlock")?;
+        let (previous_running, previous_expression) = self.set_running(running, events.is_none());
+        let previous_budget = self.budget.replace(LUA_INSTRUCTION_LIMIT);
+        let lent = RefCell::new((engine, events));
+
+        let result = lua.scope(|scope| {
+            game.set("IsKeyDown", scope.create_function(|_, key: String| {
+                let lent = lent.try_borrow().map_err(|_| mlua::Error::RuntimeError("IsKeyDown can't be used from here".to_string()))?;
+                Ok(lent.0.input.is_key_down(&key))
+            })?)?;
+            game.set("GotoMenu", scope.create_function(|lua, menu: String| {
+                Self::run_block_now(lua, &game, &lent, "goto_menu", vec![Value::String(menu)]).map(|_| ())
+            })?)?;
+            host.set("RunBlock", scope.create_function(|lua, (block, args): (String, Variadic<mlua::Value>)| {
+                Self::run_block_now(lua, &game, &lent, &block, args.iter().map(Self::lua_to_json).collect())
+            })?)?;
+            host.set("Game", game.clone())?;
+            run(lua)
+        });
+
+        let (engine, _) = lent.into_inner();
+        let written = Self::copy_game_out(&game, engine);
+        host.set("Game", previous_game)?;
+        host.set("RunBlock", previous_run_block)?;
+        self.set_running(previous_running, previous_expression);
+        self.budget.set(previous_budget);
+        written?;
+        result
     }
-}
 
+    // RunBlock and Game.GotoMenu. The block gets what the script changed so far, and the script gets
+    // the block's result and whatever the block changed.
+    fn run_block_now(lua: &Lua, game: &Table, lent: &RefCell<(&mut EngineData, Option<&mut EventManager>)>, block: &str, args: Vec<Value>) -> mlua::Result<bool> {
+        let mut lent = lent.try_borrow_mut().map_err(|_| mlua::Error::RuntimeError(format!("{} can't be used from here", block)))?;
+        let (engine, events) = &mut *lent;
+        let events = events.as_deref_mut()
+            .ok_or_else(|| mlua::Error::RuntimeError(format!("{} can't run inside a %custom expression", block)))?;
+        Self::copy_game_out(game, engine)?;
+        let code = Code { negated: false, args, block: block.to_string(), subcode: Vec::new(), priority: 0 };
+        let result = events.call_code_block(engine, &code);
+        Self::copy_game_in(lua, game, engine)?;
+        Ok(result)
+    }
 
+    // Runs each file top to bottom, anything it passes to On() belongs to the scope
+    fn load_scripts(&self, scripts: &HashMap<String, String>, scope: &ListenerScope, engine: &mut EngineData, events: &mut EventManager) {
+        let mut names: Vec<&String> = scripts.keys().collect();
+        names.sort();
+        for name in names {
+            self.logger.log("Lua", format!("Starting Lua Script {} ({:?})", name, scope).as_str());
+            let env = match self.new_env(name, scope) {
+                Ok(env) => env,
+                Err(e) => {
+                    self.logger.log_error("Lua", format!("Failed to set up {}: {}", name, e).as_str());
+                    continue;
+                }
+            };
+            let running = LuaRunning { script: name.clone(), scope: scope.clone() };
+            // The @ makes errors read "name.lua:12: ..." instead of quoting the source
+            let result = self.with_game(engine, Some(&mut *events), running, |lua| {
+                lua.load(scripts[name].as_str()).set_name(format!("@{}", name)).set_environment(env).exec()
+            });
+            if let Err(e) = result {
+                self.disable_script(name, &e);
+            }
+        }
+    }
 
-impl UserData for GameData {
-    fn add_fields<'lua, F: mlua::UserDataFields<'lua, Self>>(fields: &mut F) {
-        fields.add_field_method_get("Night", |_, this| Ok(this.night));
-        fields.add_field_method_get("Time", |_, this| Ok(this.time));
-      //  fields.add_field_method_get("Office", |_, this| Ok(this.office));
-       // fields.add_field_method_get("Settings", |_, this| Ok(&this.settings));
-     //   fields.add_field_method_get("Player", |_, this| Ok(&this.player));
-        //fields.add_field_method_get("Animations", |_, this| Ok(&this.office.animations));
-       // fields.add_field_method_get("Cameras", |_, this| Ok(&this.cameras));
-      //  fields.add_field_method_get("CameraUI", |_, this| Ok(&this.cam_ui));
+    // A fresh environment for the file, loading it again throws out what the last load left behind
+    fn new_env(&self, script: &str, scope: &ListenerScope) -> mlua::Result<Table<'_>> {
+        let env = self.lua.create_table()?;
+        env.set_metatable(Some(Self::proxy_to(&self.lua, self.host()?)?));
+        let key = self.lua.create_registry_value(env.clone())?;
+        let mut state = Self::state(&self.lua)?;
+        state.next_id += 1;
+        let loaded = LuaScript { load: state.next_id, scope: scope.clone(), env: key };
+        let old = state.scripts.insert(script.to_string(), loaded);
+        drop(state);
+        if let Some(old) = old {
+            let _ = self.lua.remove_registry_value(old.env);
+        }
+        Ok(env)
+    }
 
-        // TODO: Mouse
+    // Drops the script's environment and every handler it registered, the rest of the game carries on without it
+    fn disable_script(&self, script: &str, error: &mlua::Error) {
+        self.logger.log_error("Lua", format!("{} crashed and has been turned off: {}", script, error).as_str());
+        let (env, dead): (Option<LuaScript>, Vec<LuaHandler>) = match self.lua.app_data_mut::<LuaState>() {
+            Some(mut state) => {
+                let ids: Vec<u64> = state.handlers.iter().filter(|(_, handler)| handler.script == script).map(|(id, _)| *id).collect();
+                state.dropped.extend(ids.iter().copied());
+                (state.scripts.remove(script), ids.iter().filter_map(|id| state.handlers.remove(id)).collect())
+            }
+            None => return,
+        };
+        if let Some(env) = env {
+            let _ = self.lua.remove_registry_value(env.env);
+        }
+        for handler in dead {
+            let _ = self.lua.remove_registry_value(handler.key);
+        }
     }
 
-    fn add_methods<'lua, M: mlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
-    //    methods.add_method("SetTime", |_, &mut this, val| Ok(this.set_time(val)));
-    //    methods.add_method("Quit", |_, &mut this, ()| Ok(this.quit()));
-      //  methods.add_method("DisableTime", |_, &mut this, ()| Ok(this.disable_time()));
-      //  methods.add_method("EnableTime", |_, &mut this, ()| Ok(this.enable_time()));
-        methods.add_method("GotoMenu", |_, mut this, ()| Ok(())); // TODO
-        methods.add_method("IsKeyDown", |_, mut this, ()| Ok(())); // TODO
+    // One On() handler for the event that's firing. Each gets its own instruction budget, and one
+    // crashing only takes its own script down.
+    fn run_handler(&self, id: u64, args: &[String], engine: &mut EngineData, events: &mut EventManager) {
+        let (function, running): (Function, LuaRunning) = match self.lua.app_data_ref::<LuaState>() {
+            Some(state) => match state.handlers.get(&id) {
+                Some(handler) => match self.lua.registry_value(&handler.key) {
+                    Ok(function) => (function, LuaRunning { script: handler.script.clone(), scope: handler.scope.clone() }),
+                    Err(_) => return,
+                },
+                None => return,
+            },
+            None => return,
+        };
+        let script = running.script.clone();
+        let result = self.with_game(engine, Some(events), running, |_| {
+            function.call::<_, ()>(args.iter().cloned().collect::<Variadic<String>>())
+        });
+        if let Err(e) = result {
+            self.disable_script(&script, &e);
+        }
+    }
 
+    // A global function lives in the environment of the file that defined it, the first file by name wins
+    fn find_function(&self, name: &str) -> mlua::Result<(String, Function<'_>)> {
+        let state = Self::state(&self.lua)?;
+        let mut scripts: Vec<(&String, &LuaScript)> = state.scripts.iter().collect();
+        scripts.sort_by(|a, b| a.0.cmp(b.0));
+        for (script, loaded) in scripts {
+            let env: Table = self.lua.registry_value(&loaded.env)?;
+            if let Some(function) = env.raw_get::<_, Option<Function>>(name)? {
+                return Ok((script.clone(), function));
+            }
+        }
+        Err(mlua::Error::RuntimeError(format!("no script defines a function called {}", name)))
+    }
+
+    // Their listeners belong to the same scope, the event manager takes care of those
+    fn kill_scopes<F>(&self, predicate: F)
+    where
+        F: Fn(&ListenerScope) -> bool,
+    {
+        let mut keys: Vec<RegistryKey> = Vec::new();
+        match self.lua.app_data_mut::<LuaState>() {
+            Some(mut state) => {
+                let ids: Vec<u64> = state.handlers.iter().filter(|(_, handler)| predicate(&handler.scope)).map(|(id, _)| *id).collect();
+                keys.extend(ids.iter().filter_map(|id| state.handlers.remove(id)).map(|handler| handler.key));
+                let scripts: Vec<String> = state.scripts.iter().filter(|(_, loaded)| predicate(&loaded.scope)).map(|(name, _)| name.clone()).collect();
+                keys.extend(scripts.iter().filter_map(|name| state.scripts.remove(name)).map(|loaded| loaded.env));
+            }
+            None => return,
+        }
+        for key in keys {
+            let _ = self.lua.remove_registry_value(key);
+        }
     }
 }
 
 impl UserData for OfficeData {
     fn add_fields<'lua, F: mlua::UserDataFields<'lua, Self>>(fields: &mut F) {
-      //  fields.add_field_method_get("States", |_, this| Ok(&this.states));
-    //    fields.add_field_method_set("States", |_, mut this, val| Ok(this.states = val));
+        fields.add_field_method_get("States", |_, this| Ok(this.states.clone()));
+        fields.add_field_method_set("States", |_, this, val| Ok(this.states = val));
         fields.add_field_method_get("State", |_, this| Ok(this.state.clone()));
+        fields.add_field_method_set("State", |_, this, val| Ok(this.state = val));
     }
 
     fn add_methods<'lua, M: mlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
-      //  methods.add_method("SetState", |_, mut this, val| Ok(this.set_state(val)));
+        methods.add_method_mut("SetState", |_, this, val: String| Ok(this.set_state(val)));
+        methods.add_method("IsDoorClosed", |_, this, id: String| Ok(this.doors.get(&id).map_or(false, |door| door.is_closed)));
+        methods.add_method("IsLightOn", |_, this, id: String| Ok(this.lights.get(&id).map_or(false, |light| light.is_on)));
     }
 }
 
@@ -102,19 +533,17 @@ impl UserData for Player {
     }
 
     fn add_methods<'lua, M: mlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
-    //    methods.add_method("SetCamera", |_, mut this, val| Ok(this.set_camera(val)));
+        methods.add_method_mut("SetCamera", |_, this, val: String| Ok(this.set_camera(val)));
     }
 }
 
-// TODO: ANIMTION DATA STRUCTURES
-impl UserData for Animation { // TODO
+impl UserData for Animation {
     fn add_fields<'lua, F: mlua::UserDataFields<'lua, Self>>(fields: &mut F) {
-   //     fields.add_field_method_get("ID", |_, this| Ok(&this.id));
-//        fields.add_field_method_set("ID", |_, this, val| Ok(this.id = val)); // ID should be readonly
-   //     fields.add_field_method_get("IsPlaying", |_, this| Ok(&this.is_playing));
-  //      fields.add_field_method_set("IsPlaying", |_, this, val| Ok(this.is_playing = val));
- //       fields.add_field_method_get("IsReversed", |_, this| Ok(&this.is_reversed));
-    //    fields.add_field_method_set("IsReversed", |_, this, val| Ok(this.is_reversed = val));
+        fields.add_field_method_get("ID", |_, this| Ok(this.id.clone()));
+        fields.add_field_method_get("IsPlaying", |_, this| Ok(this.is_playing));
+        fields.add_field_method_set("IsPlaying", |_, this, val| Ok(this.is_playing = val));
+        fields.add_field_method_get("IsReversed", |_, this| Ok(this.is_reversed));
+        fields.add_field_method_set("IsReversed", |_, this, val| Ok(this.is_reversed = val));
     }
 
     fn add_methods<'lua, M: mlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
@@ -136,7 +565,7 @@ impl UserData for Camera {
     }
 
     fn add_methods<'lua, M: mlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
-       // methods.add_method("SetState", |_, mut this, val| Ok(this.set_state(val)));
+        methods.add_method_mut("SetState", |_, this, val: String| Ok(this.set_state(val)));
     }
 }
 
@@ -160,16 +589,15 @@ impl UserData for Animatronic {
         fields.add_field_method_set("State", |_, this, val| Ok(this.state = val));
         fields.add_field_method_get("Name", |_, this| Ok(this.name.clone()));
         fields.add_field_method_set("Name", |_, this, val| Ok(this.name = val));
-      //  fields.add_field_method_get("Path", |_, this| Ok(this.path));
-      //  fields.add_field_method_set("Path", |_, this, val| Ok(this.path = val));
-       // fields.add_field_method_get("AI", |_, this| Ok(this.ai));
+        // Copies, changing a node you got from here doesn't move the animatronic
+        fields.add_field_method_get("Path", |_, this| Ok(this.path.clone()));
+        fields.add_field_method_get("AI", |_, this| Ok(this.ai.clone()));
         fields.add_field_method_set("AI", |_, this, val| Ok(this.ai = val));
         fields.add_field_method_get("IgnoresMask", |_, this| Ok(this.ignores_mask));
         fields.add_field_method_set("IgnoresMask", |_, this, val| Ok(this.ignores_mask = val));
        // fields.add_field_method_get("Jumpscare", |_, this| Ok(this.jumpscare));
       //  fields.add_field_method_set("Jumpscare", |_, this, val| Ok(this.jumpscare = val));
-       // fields.add_field_method_get("Location", |_, this| Ok(this.location.clone()));
-    //    fields.add_field_method_set("Location", |_, this, val| Ok(this.location = val));
+        fields.add_field_method_get("Location", |_, this| Ok(this.location.clone()));
         fields.add_field_method_get("LocationIndex", |_, this| Ok(this.location_index));
         fields.add_field_method_set("LocationIndex", |_, this, val| Ok(this.location_index = val));
         fields.add_field_method_get("MoveTime", |_, this| Ok(this.move_time));
@@ -184,8 +612,7 @@ impl UserData for Animatronic {
 
 impl UserData for PathNode {
     fn add_fields<'lua, F: mlua::UserDataFields<'lua, Self>>(fields: &mut F) {
-   //     fields.add_field_method_get("Path", |_, this| Ok(this.path.clone()));
-        //fields.add_field_method_set("Path", |_, this, val| Ok(this.path = val));
+        fields.add_field_method_get("Path", |_, this| Ok(this.path.clone()));
         fields.add_field_method_get("State", |_, this| Ok(this.state.clone()));
         fields.add_field_method_set("State", |_, this, val| Ok(this.state = val));
         fields.add_field_method_get("ID", |_, this| Ok(this.id.clone()));
@@ -0,0 +1,124 @@
+use std::fmt::Display;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ScriptValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+}
+
+impl ScriptValue {
+    // Scripts only ever hand us text, so the type is inferred from what the text looks like.
+    pub fn parse(raw: &str) -> ScriptValue {
+        let trimmed = raw.trim();
+        if let Ok(int) = trimmed.parse::<i64>() {
+            return ScriptValue::Int(int);
+        }
+        if let Ok(float) = trimmed.parse::<f64>() {
+            // "inf" and "NaN" parse as floats, those should stay strings
+            if float.is_finite() {
+                return ScriptValue::Float(float);
+            }
+        }
+        match trimmed {
+            "true" | "True" => ScriptValue::Bool(true),
+            "false" | "False" => ScriptValue::Bool(false),
+            _ => ScriptValue::Str(raw.to_string()),
+        }
+    }
+
+    pub fn from_json(value: &Value) -> ScriptValue {
+        match value {
+            Value::Bool(b) => ScriptValue::Bool(*b),
+            Value::Number(n) => match n.as_i64() {
+                Some(int) => ScriptValue::Int(int),
+                None => ScriptValue::Float(n.as_f64().unwrap_or(0.0)),
+            },
+            Value::String(s) => ScriptValue::parse(s),
+            Value::Null => ScriptValue::Str(String::new()),
+            other => ScriptValue::Str(other.to_string()),
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            ScriptValue::Int(int) => Some(*int as f64),
+            ScriptValue::Float(float) => Some(*float),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            ScriptValue::Int(int) => Some(*int),
+            ScriptValue::Float(float) => Some(*float as i64),
+            ScriptValue::Bool(b) => Some(*b as i64),
+            ScriptValue::Str(s) => s.trim().parse().ok(),
+        }
+    }
+
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            ScriptValue::Bool(b) => *b,
+            ScriptValue::Int(int) => *int != 0,
+            ScriptValue::Float(float) => *float != 0.0,
+            ScriptValue::Str(s) => !s.is_empty(),
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            ScriptValue::Bool(_) => "bool",
+            ScriptValue::Int(_) => "int",
+            ScriptValue::Float(_) => "float",
+            ScriptValue::Str(_) => "string",
+        }
+    }
+
+    pub fn compare(&self, operator: &str, other: &ScriptValue) -> Result<bool, String> {
+        let lhs = self.to_string();
+        let rhs = other.to_string();
+
+        // Only compare numerically when both sides are actually numbers, "10" < "9" is a string thing
+        if let (Some(a), Some(b)) = (self.as_f64(), other.as_f64()) {
+            return match operator {
+                "==" => Ok(a == b),
+                "<>" | "!=" => Ok(a != b),
+                ">" => Ok(a > b),
+                "<" => Ok(a < b),
+                ">=" => Ok(a >= b),
+                "<=" => Ok(a <= b),
+                "contains" => Ok(lhs.contains(&rhs)),
+                "starts_with" => Ok(lhs.starts_with(&rhs)),
+                "ends_with" => Ok(lhs.ends_with(&rhs)),
+                _ => Err(format!("Invalid operator '{}'", operator)),
+            };
+        }
+
+        match operator {
+            "==" => Ok(lhs == rhs),
+            "<>" | "!=" => Ok(lhs != rhs),
+            ">" => Ok(lhs > rhs),
+            "<" => Ok(lhs < rhs),
+            ">=" => Ok(lhs >= rhs),
+            "<=" => Ok(lhs <= rhs),
+            "contains" => Ok(lhs.contains(&rhs)),
+            "starts_with" => Ok(lhs.starts_with(&rhs)),
+            "ends_with" => Ok(lhs.ends_with(&rhs)),
+            _ => Err(format!("Invalid operator '{}'", operator)),
+        }
+    }
+}
+
+impl Display for ScriptValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptValue::Bool(b) => write!(f, "{}", b),
+            ScriptValue::Int(int) => write!(f, "{}", int),
+            ScriptValue::Float(float) => write!(f, "{}", float),
+            ScriptValue::Str(s) => write!(f, "{}", s),
+        }
+    }
+}
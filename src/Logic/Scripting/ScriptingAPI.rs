@@ -33,13 +33,52 @@ impl ScriptingAPI {
         actions.insert(String::from("is_mouse_over_object"), Self::is_mouse_over_object as CodeBlockFunction);
         actions.insert(String::from("is_mouse_over_sprite"), Self::is_mouse_over_object as CodeBlockFunction); // Fix when i seperate sprites from objects
 
+        // Input
+        actions.insert(String::from("is_key_down"), Self::is_key_down as CodeBlockFunction);
+        actions.insert(String::from("is_action_down"), Self::is_action_down as CodeBlockFunction);
+        actions.insert(String::from("bind_action"), Self::bind_action as CodeBlockFunction);
+        actions.insert(String::from("unbind_action"), Self::unbind_action as CodeBlockFunction);
+
         // Shared
         actions.insert(String::from("goto_menu"), Self::goto_menu as CodeBlockFunction);
         actions.insert(String::from("compare_values"), Self::compare_values as CodeBlockFunction);
+        actions.insert(String::from("and"), Self::and as CodeBlockFunction);
+        actions.insert(String::from("or"), Self::or as CodeBlockFunction);
+        actions.insert(String::from("not"), Self::not as CodeBlockFunction);
+        actions.insert(String::from("call_function"), Self::call_function as CodeBlockFunction);
+        actions.insert(String::from("call_lua"), Self::call_lua as CodeBlockFunction);
+        actions.insert(String::from("cancel_timer"), Self::cancel_timer as CodeBlockFunction);
+        actions.insert(String::from("is_timer_running"), Self::is_timer_running as CodeBlockFunction);
+        actions.insert(String::from("stop_propagation"), Self::stop_propagation as CodeBlockFunction);
         actions.insert(String::from("play_sound"), Self::play_audio as CodeBlockFunction);
         actions.insert(String::from("stop_channel"), Self::stop_channel as CodeBlockFunction);
         actions.insert(String::from("set_var"), Self::set_var as CodeBlockFunction);
         actions.insert(String::from("set_data"), Self::set_datavalue as CodeBlockFunction);
+        actions.insert(String::from("save_data"), Self::save_data as CodeBlockFunction);
+        actions.insert(String::from("reset_data"), Self::reset_data as CodeBlockFunction);
+        actions.insert(String::from("delete_data"), Self::delete_data as CodeBlockFunction);
+        actions.insert(String::from("has_data"), Self::has_data as CodeBlockFunction);
+        actions.insert(String::from("list_slots"), Self::list_slots as CodeBlockFunction);
+        actions.insert(String::from("select_slot"), Self::select_slot as CodeBlockFunction);
+        actions.insert(String::from("copy_slot"), Self::copy_slot as CodeBlockFunction);
+        actions.insert(String::from("erase_slot"), Self::erase_slot as CodeBlockFunction);
+        actions.insert(String::from("slot_exists"), Self::slot_exists as CodeBlockFunction);
+        actions.insert(String::from("open_slot_picker"), Self::open_slot_picker as CodeBlockFunction);
+        actions.insert(String::from("open_mod_list"), Self::open_mod_list as CodeBlockFunction);
+        actions.insert(String::from("toggle_mod"), Self::toggle_mod as CodeBlockFunction);
+        actions.insert(String::from("pause_game"), Self::pause_game as CodeBlockFunction);
+        actions.insert(String::from("resume_game"), Self::resume_game as CodeBlockFunction);
+        actions.insert(String::from("goto_minigame"), Self::goto_minigame as CodeBlockFunction);
+        actions.insert(String::from("end_minigame"), Self::end_minigame as CodeBlockFunction);
+        actions.insert(String::from("show_minigame_sprite"), Self::show_minigame_sprite as CodeBlockFunction);
+        actions.insert(String::from("hide_minigame_sprite"), Self::hide_minigame_sprite as CodeBlockFunction);
+        actions.insert(String::from("save_state"), Self::save_state as CodeBlockFunction);
+        actions.insert(String::from("set_seed"), Self::set_seed as CodeBlockFunction);
+        actions.insert(String::from("load_state"), Self::load_state as CodeBlockFunction);
+        actions.insert(String::from("close_door"), Self::close_door as CodeBlockFunction);
+        actions.insert(String::from("open_door"), Self::open_door as CodeBlockFunction);
+        actions.insert(String::from("light_on"), Self::light_on as CodeBlockFunction);
+        actions.insert(String::from("light_off"), Self::light_off as CodeBlockFunction);
         actions.insert(String::from("quit"), Self::quit as CodeBlockFunction);
         actions.insert(String::from("line"), Self::line as CodeBlockFunction);
         actions.insert(String::from("comment"), Self::comment as CodeBlockFunction);
@@ -57,8 +96,10 @@ impl ScriptingAPI {
         true
     }
 
+    // The game loop does the actual quitting, once plugins and the like have been shut down
     fn quit(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
-        process::exit(0);
+        engine_data.quit_requested = true;
+        true
     }
 
     fn is_mouse_over_object(engine_data: &mut EngineData, event_manager: &mut EventManager, args: &[Value]) -> bool {
@@ -84,6 +125,58 @@ impl ScriptingAPI {
         }
     }
 
+    fn is_key_down(engine_data: &mut EngineData, event_manager: &mut EventManager, args: &[Value]) -> bool {
+        match args.get(0) {
+            Some(value) => {
+                let key = event_manager.get_expr(&Self::arg_to_string(value), engine_data);
+                engine_data.input.is_key_down(&key)
+            }
+            None => {
+                engine_data.logger.log_error("ScriptingAPI", "is_key_down needs a key name.");
+                false
+            }
+        }
+    }
+
+    fn is_action_down(engine_data: &mut EngineData, event_manager: &mut EventManager, args: &[Value]) -> bool {
+        match args.get(0) {
+            Some(value) => {
+                let action = event_manager.get_expr(&Self::arg_to_string(value), engine_data);
+                engine_data.input.is_action_down(&action)
+            }
+            None => {
+                engine_data.logger.log_error("ScriptingAPI", "is_action_down needs an action name.");
+                false
+            }
+        }
+    }
+
+    // args: [action, key names...], replaces whatever the action was bound to before
+    fn bind_action(engine_data: &mut EngineData, event_manager: &mut EventManager, args: &[Value]) -> bool {
+        if args.len() < 2 {
+            engine_data.logger.log_error("ScriptingAPI", "bind_action needs an action and at least one key.");
+            return false;
+        }
+        let action = event_manager.get_expr(&Self::arg_to_string(&args[0]), engine_data);
+        let keys: Vec<String> = args[1..].iter()
+            .map(|key| event_manager.get_expr(&Self::arg_to_string(key), engine_data))
+            .filter(|key| !key.is_empty())
+            .collect();
+        engine_data.input.bind(&action, keys);
+        true
+    }
+
+    fn unbind_action(engine_data: &mut EngineData, event_manager: &mut EventManager, args: &[Value]) -> bool {
+        match args.get(0) {
+            Some(value) => {
+                let action = event_manager.get_expr(&Self::arg_to_string(value), engine_data);
+                engine_data.input.unbind(&action);
+                true
+            }
+            None => false,
+        }
+    }
+
     fn hide_office_object(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
         let sprite_name = match args.get(0) {
             Some(value) => match value.as_str() {
@@ -172,10 +265,24 @@ impl ScriptingAPI {
         true
     }
 
+    // goto_menu <menu> <fade seconds>, without the fade it goes by the menus' fade properties
     fn goto_menu(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
         let binding = event_manager.get_expr(args[0].as_str().unwrap(), engine_data);
         let menu = binding.as_str();
-        MenuManager::goto_menu(menu, engine_data, event_manager);
+        let fade = match args.get(1).map(|value| event_manager.get_expr(&Self::arg_to_string(value), engine_data)) {
+            Some(seconds) => match seconds.trim().parse::<f32>() {
+                Ok(seconds) => Fade::new(seconds, seconds),
+                Err(_) => {
+                    engine_data.logger.log_error("ScriptingAPI", format!("Invalid fade '{}'.", seconds).as_str());
+                    return false;
+                }
+            },
+            None => {
+                MenuManager::goto_menu(menu, engine_data, event_manager);
+                return true;
+            }
+        };
+        SceneManager::transition(engine_data, event_manager, Rc::new(MenuScene::new(menu)), fade);
         true
     }
 
@@ -209,19 +316,400 @@ impl ScriptingAPI {
     }
 
     fn set_var(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
-        let name = event_manager.get_expr(args[0].as_str().unwrap(), engine_data);
-        let value = event_manager.get_expr(args[1].as_str().unwrap(), engine_data);
-        event_manager.set_variable_value(name, value);
+        if args.len() < 2 {
+            engine_data.logger.log_error("ScriptingAPI", "set_var needs a variable name and a value.");
+            return false;
+        }
+        let name = event_manager.get_expr(&Self::arg_to_string(&args[0]), engine_data);
+        let value = event_manager.get_expr(&Self::arg_to_string(&args[1]), engine_data);
+        event_manager.set_variable_value(name, ScriptValue::parse(&value));
         true
     }
 
     fn set_datavalue(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
-        let name = event_manager.get_expr(args[0].as_str().unwrap(), engine_data);
-        let value = event_manager.get_expr(args[1].as_str().unwrap(), engine_data);
-        event_manager.set_data_value(name, value);
+        if args.len() < 2 {
+            engine_data.logger.log_error("ScriptingAPI", "set_data needs a data value name and a value.");
+            return false;
+        }
+        let name = event_manager.get_expr(&Self::arg_to_string(&args[0]), engine_data);
+        let value = event_manager.get_expr(&Self::arg_to_string(&args[1]), engine_data);
+        event_manager.set_data_value(name, ScriptValue::parse(&value));
+        event_manager.flush_data_values(engine_data);
+        true
+    }
+
+    fn save_data(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        event_manager.flush_data_values(engine_data);
+        true
+    }
+
+    fn reset_data(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        event_manager.clear_data_values();
+        event_manager.flush_data_values(engine_data);
+        true
+    }
+
+    fn delete_data(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        let name = match args.get(0) {
+            Some(value) => event_manager.get_expr(&Self::arg_to_string(value), engine_data),
+            None => {
+                engine_data.logger.log_error("ScriptingAPI", "delete_data needs a data value name.");
+                return false;
+            }
+        };
+        event_manager.remove_data_value(&name);
+        event_manager.flush_data_values(engine_data);
+        true
+    }
+
+    fn has_data(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        match args.get(0) {
+            Some(value) => {
+                let name = event_manager.get_expr(&Self::arg_to_string(value), engine_data);
+                event_manager.get_data_value(&name).is_some()
+            }
+            None => false,
+        }
+    }
+
+    // Stores the slot names comma separated in a variable, e.g. list_slots slots
+    fn list_slots(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        let name = match args.get(0) {
+            Some(value) => event_manager.get_expr(&Self::arg_to_string(value), engine_data),
+            None => {
+                engine_data.logger.log_error("ScriptingAPI", "list_slots needs a variable to store the slots in.");
+                return false;
+            }
+        };
+        let slots = engine_data.save.slot_names().join(",");
+        event_manager.set_variable_value(name, ScriptValue::Str(slots));
+        true
+    }
+
+    fn select_slot(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        let slot = match args.get(0) {
+            Some(value) => event_manager.get_expr(&Self::arg_to_string(value), engine_data),
+            None => {
+                engine_data.logger.log_error("ScriptingAPI", "select_slot needs a slot name.");
+                return false;
+            }
+        };
+        event_manager.select_save_slot(&slot, engine_data)
+    }
+
+    fn copy_slot(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        if args.len() < 2 {
+            engine_data.logger.log_error("ScriptingAPI", "copy_slot needs a source and a destination slot.");
+            return false;
+        }
+        let from = event_manager.get_expr(&Self::arg_to_string(&args[0]), engine_data);
+        let to = event_manager.get_expr(&Self::arg_to_string(&args[1]), engine_data);
+        // The current slot's data only lives in memory until it's flushed
+        if from == engine_data.save.current_slot() {
+            event_manager.flush_data_values(engine_data);
+        }
+        match engine_data.save.copy_slot(&from, &to) {
+            Ok(true) => {}
+            Ok(false) => {
+                engine_data.logger.log_error("ScriptingAPI", format!("Save slot '{}' doesn't exist.", from).as_str());
+                return false;
+            }
+            Err(e) => {
+                engine_data.logger.log_error("ScriptingAPI", format!("Failed to copy save slot '{}': {}", from, e).as_str());
+                return false;
+            }
+        }
+        // Copying over the slot in use should show up right away
+        if to == engine_data.save.current_slot() {
+            event_manager.load_data_values(engine_data.save.slot(&to).map(|slot| slot.data_values.clone().into_iter().collect()).unwrap_or_default());
+        }
+        true
+    }
+
+    fn erase_slot(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        let slot = match args.get(0) {
+            Some(value) => event_manager.get_expr(&Self::arg_to_string(value), engine_data),
+            None => {
+                engine_data.logger.log_error("ScriptingAPI", "erase_slot needs a slot name.");
+                return false;
+            }
+        };
+        if let Err(e) = engine_data.save.erase_slot(&slot) {
+            engine_data.logger.log_error("ScriptingAPI", format!("Failed to erase save slot '{}': {}", slot, e).as_str());
+            return false;
+        }
+        if slot == engine_data.save.current_slot() {
+            event_manager.clear_data_values();
+        }
+        true
+    }
+
+    fn slot_exists(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        match args.get(0) {
+            Some(value) => {
+                let slot = event_manager.get_expr(&Self::arg_to_string(value), engine_data);
+                engine_data.save.slot(&slot).is_some()
+            }
+            None => false,
+        }
+    }
+
+    // open_slot_picker <menu to go back to>
+    fn open_slot_picker(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        let return_menu = match args.get(0) {
+            Some(value) => event_manager.get_expr(&Self::arg_to_string(value), engine_data),
+            None => engine_data.menumgr.curmenu.clone(),
+        };
+        if !engine_data.game.menus.contains_key(&return_menu) {
+            engine_data.logger.log_error("ScriptingAPI", format!("Menu '{}' doesn't exist.", return_menu).as_str());
+            return false;
+        }
+        MenuManager::open_slot_picker(&return_menu, engine_data, event_manager);
+        true
+    }
+
+    // open_mod_list <menu to go back to>
+    fn open_mod_list(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        let return_menu = match args.get(0) {
+            Some(value) => event_manager.get_expr(&Self::arg_to_string(value), engine_data),
+            None => engine_data.menumgr.curmenu.clone(),
+        };
+        if !engine_data.game.menus.contains_key(&return_menu) {
+            engine_data.logger.log_error("ScriptingAPI", format!("Menu '{}' doesn't exist.", return_menu).as_str());
+            return false;
+        }
+        MenuManager::open_mod_list(&return_menu, engine_data, event_manager);
         true
     }
 
+    // toggle_mod <mod id> <on/off>, flips it without the second arg. Applies on the next start.
+    fn toggle_mod(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        let id = match args.get(0) {
+            Some(value) => event_manager.get_expr(&Self::arg_to_string(value), engine_data),
+            None => {
+                engine_data.logger.log_error("ScriptingAPI", "toggle_mod needs a mod id.");
+                return false;
+            }
+        };
+        let enabled = match args.get(1).map(|value| event_manager.get_expr(&Self::arg_to_string(value), engine_data)) {
+            Some(state) => matches!(state.to_lowercase().as_str(), "on" | "true" | "1"),
+            None => !engine_data.mods.mods.iter().any(|info| info.manifest.id == id && info.enabled),
+        };
+        match engine_data.mods.set_enabled(&id, enabled) {
+            Ok(()) => true,
+            Err(e) => {
+                engine_data.logger.log_error("ScriptingAPI", e.as_str());
+                false
+            }
+        }
+    }
+
+    // pause_game <menu>, the game's pause menu without one
+    fn pause_game(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        let menu = match args.get(0) {
+            Some(value) => event_manager.get_expr(&Self::arg_to_string(value), engine_data),
+            None => match MenuManager::pause_menu(engine_data) {
+                Some(menu) => menu,
+                None => {
+                    engine_data.logger.log_error("ScriptingAPI", "pause_game needs a menu, the game doesn't have a pause menu.");
+                    return false;
+                }
+            },
+        };
+        if !engine_data.game.menus.contains_key(&menu) {
+            engine_data.logger.log_error("ScriptingAPI", format!("Menu '{}' doesn't exist.", menu).as_str());
+            return false;
+        }
+        MenuManager::pause_game(&menu, engine_data, event_manager)
+    }
+
+    fn resume_game(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        MenuManager::resume_game(engine_data, event_manager)
+    }
+
+    // goto_minigame <minigame>
+    fn goto_minigame(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        let minigame = match args.get(0) {
+            Some(value) => event_manager.get_expr(&Self::arg_to_string(value), engine_data),
+            None => {
+                engine_data.logger.log_error("ScriptingAPI", "goto_minigame needs a minigame.");
+                return false;
+            }
+        };
+        if !engine_data.game.minigames.contains_key(&minigame) {
+            engine_data.logger.log_error("ScriptingAPI", format!("Minigame '{}' doesn't exist.", minigame).as_str());
+            return false;
+        }
+        MinigameManager::goto_minigame(&minigame, engine_data, event_manager);
+        true
+    }
+
+    // end_minigame <menu>, goes back to the menu the minigame was started from without one
+    fn end_minigame(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        if engine_data.scenes.current() != SceneKind::Minigame {
+            engine_data.logger.log_error("ScriptingAPI", "end_minigame only works during a minigame.");
+            return false;
+        }
+        let menu = match args.get(0) {
+            Some(value) => event_manager.get_expr(&Self::arg_to_string(value), engine_data),
+            None => engine_data.minigamemgr.return_menu.clone(),
+        };
+        if !engine_data.game.menus.contains_key(&menu) {
+            engine_data.logger.log_error("ScriptingAPI", format!("Menu '{}' doesn't exist.", menu).as_str());
+            return false;
+        }
+        MinigameManager::end_minigame(&menu, engine_data, event_manager);
+        true
+    }
+
+    // show_minigame_sprite <sprite id>
+    fn show_minigame_sprite(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        let id = match args.get(0) {
+            Some(value) => event_manager.get_expr(&Self::arg_to_string(value), engine_data),
+            None => {
+                engine_data.logger.log_error("ScriptingAPI", "show_minigame_sprite needs a sprite id.");
+                return false;
+            }
+        };
+        engine_data.minigamemgr.hidden_sprites.remove(&id);
+        true
+    }
+
+    // hide_minigame_sprite <sprite id>, hidden sprites don't block the player either
+    fn hide_minigame_sprite(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        let id = match args.get(0) {
+            Some(value) => event_manager.get_expr(&Self::arg_to_string(value), engine_data),
+            None => {
+                engine_data.logger.log_error("ScriptingAPI", "hide_minigame_sprite needs a sprite id.");
+                return false;
+            }
+        };
+        engine_data.minigamemgr.hidden_sprites.insert(id);
+        true
+    }
+
+    // save_state <name>, snapshots the running night. The name defaults to "quicksave".
+    fn save_state(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        if engine_data.scenes.current() != SceneKind::Office {
+            engine_data.logger.log_error("ScriptingAPI", "save_state only works during a night.");
+            return false;
+        }
+        let name = match args.get(0) {
+            Some(value) => event_manager.get_expr(&Self::arg_to_string(value), engine_data),
+            None => "quicksave".to_string(),
+        };
+        let path = engine_data.save.state_path(&name);
+        if let Err(e) = NightSnapshot::capture(engine_data, event_manager).write(&path) {
+            engine_data.logger.log_error("ScriptingAPI", format!("Failed to write snapshot {:?}: {}", path, e).as_str());
+            return false;
+        }
+        engine_data.logger.log("ScriptingAPI", format!("Saved night snapshot '{}'.", name).as_str());
+        true
+    }
+
+    fn load_state(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        let name = match args.get(0) {
+            Some(value) => event_manager.get_expr(&Self::arg_to_string(value), engine_data),
+            None => "quicksave".to_string(),
+        };
+        let result = NightSnapshot::read(&engine_data.save.state_path(&name))
+            .and_then(|snapshot| snapshot.restore(engine_data, event_manager));
+        if let Err(e) = result {
+            engine_data.logger.log_error("ScriptingAPI", format!("Failed to load snapshot '{}': {}", name, e).as_str());
+            return false;
+        }
+        engine_data.logger.log("ScriptingAPI", format!("Loaded night snapshot '{}'.", name).as_str());
+        true
+    }
+
+    fn close_door(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        Self::set_door(engine_data, event_manager, args, true)
+    }
+
+    fn open_door(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        Self::set_door(engine_data, event_manager, args, false)
+    }
+
+    // Same as clicking the door button, except it can't be done once the power is out
+    fn set_door(engine_data: &mut EngineData, event_manager: &mut EventManager, args: &[Value], closed: bool) -> bool {
+        let id = match args.get(0) {
+            Some(value) => event_manager.get_expr(&Self::arg_to_string(value), engine_data),
+            None => {
+                engine_data.logger.log_error("ScriptingAPI", "Door blocks need a door id.");
+                return false;
+            }
+        };
+        let game_data = &mut engine_data.officemgr.game_data;
+        if game_data.power.enabled && game_data.power.level <= 0 {
+            return false;
+        }
+        match game_data.office.doors.get_mut(&id) {
+            Some(door) => {
+                door.is_closed = closed;
+                door.button.is_on = closed;
+                true
+            }
+            None => {
+                engine_data.logger.log_error("ScriptingAPI", format!("Door '{}' not found.", id).as_str());
+                false
+            }
+        }
+    }
+
+    fn light_on(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        Self::set_light(engine_data, event_manager, args, true)
+    }
+
+    fn light_off(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        Self::set_light(engine_data, event_manager, args, false)
+    }
+
+    fn set_light(engine_data: &mut EngineData, event_manager: &mut EventManager, args: &[Value], on: bool) -> bool {
+        let id = match args.get(0) {
+            Some(value) => event_manager.get_expr(&Self::arg_to_string(value), engine_data),
+            None => {
+                engine_data.logger.log_error("ScriptingAPI", "Light blocks need a light id.");
+                return false;
+            }
+        };
+        let game_data = &mut engine_data.officemgr.game_data;
+        if on && game_data.power.enabled && game_data.power.level <= 0 {
+            return false;
+        }
+        match game_data.office.lights.get_mut(&id) {
+            Some(light) => {
+                light.is_on = on;
+                true
+            }
+            None => {
+                engine_data.logger.log_error("ScriptingAPI", format!("Light '{}' not found.", id).as_str());
+                false
+            }
+        }
+    }
+
+    // Reseeds the engine RNG so everything random after this point plays out the same every time
+    fn set_seed(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        let seed = match args.get(0) {
+            Some(value) => event_manager.get_expr(&Self::arg_to_string(value), engine_data),
+            None => {
+                engine_data.logger.log_error("ScriptingAPI", "set_seed needs a seed.");
+                return false;
+            }
+        };
+        match seed.trim().parse::<u64>() {
+            Ok(seed) => {
+                engine_data.seed = seed;
+                engine_data.rng = StdRng::seed_from_u64(seed);
+                true
+            }
+            Err(_) => {
+                engine_data.logger.log_error("ScriptingAPI", format!("Invalid seed '{}'.", seed).as_str());
+                false
+            }
+        }
+    }
+
     fn setoff(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
         let office = event_manager.get_expr(args[0].as_str().unwrap(), engine_data);
         engine_data.officemgr.curoffice = office;
@@ -230,13 +718,26 @@ impl ScriptingAPI {
 
     fn office(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
         let office = event_manager.get_expr(args[0].as_str().unwrap(), engine_data);
-        OfficeManager::init_office(office, engine_data.officemgr.game_data.night.clone(), engine_data, event_manager);
+        OfficeManager::goto_office(office, engine_data.officemgr.game_data.night.clone(), engine_data, event_manager);
         true
     }
 
     fn start_night(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
-        let night = event_manager.get_expr(args[0].as_str().unwrap(), engine_data);
-        OfficeManager::init_office(engine_data.officemgr.curoffice.clone(), night.parse().unwrap(), engine_data, event_manager);
+        let Some(arg) = args.get(0) else {
+            engine_data.logger.log_error("ScriptingAPI", "start_night needs a night number.");
+            return false;
+        };
+        let night = event_manager.get_expr(&Self::arg_to_string(arg), engine_data);
+        let night: i32 = match night.trim().parse() {
+            Ok(night) => night,
+            Err(_) => {
+                engine_data.logger.log_error("ScriptingAPI", format!("start_night: '{}' is not a night number.", night).as_str());
+                return false;
+            }
+        };
+        engine_data.save.set_last_night(night);
+        event_manager.mark_data_dirty();
+        OfficeManager::goto_office(engine_data.officemgr.curoffice.clone(), night, engine_data, event_manager);
         true
     }
 
@@ -260,42 +761,172 @@ impl ScriptingAPI {
     }
 
     fn compare_values(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
-        let lhs = event_manager.get_expr(args[0].as_str().unwrap(), engine_data);
-        let rhs = event_manager.get_expr(args[2].as_str().unwrap(), engine_data);
+        if args.len() < 3 {
+            engine_data.logger.log_error("ScriptingAPI", format!("compare_values expects 3 arguments, got {}.", args.len()).as_str());
+            return false;
+        }
+        let lhs = ScriptValue::parse(&event_manager.get_expr(&Self::arg_to_string(&args[0]), engine_data));
+        let rhs = ScriptValue::parse(&event_manager.get_expr(&Self::arg_to_string(&args[2]), engine_data));
+        let operator = Self::arg_to_string(&args[1]);
 
-        match args[1].as_str().unwrap() {
-            "==" => lhs == rhs,
-            "<>" => lhs != rhs,
-            ">"  => lhs > rhs,
-            "<"  => lhs < rhs,
-            ">=" => lhs >= rhs,
-            "<=" => lhs <= rhs,
-            _    => panic!("Invalid operator"),
+        match lhs.compare(operator.trim(), &rhs) {
+            Ok(result) => result,
+            Err(e) => {
+                engine_data.logger.log_error("ScriptingAPI", format!("compare_values: {}", e).as_str());
+                false
+            }
         }
     }
 
-    fn TickEvents(engine_data: &mut EngineData, event_manager: &mut EventManager, ctx: &mut Context) -> Result<(), TetraError> {
-        event_manager.trigger_event("on_game_loop", &[String::new()], engine_data);
-        let tick = engine_data.clock.current_tick.lock().unwrap().clone();
-        event_manager.trigger_event(
-            "current_tick_equals",
-            &[tick.to_string()],
-            engine_data,
-        );
+    fn and(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        args.iter().all(|condition| event_manager.evaluate_condition(engine_data, condition))
+    }
+
+    fn or(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        args.iter().any(|condition| event_manager.evaluate_condition(engine_data, condition))
+    }
+
+    fn not(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        match args.get(0) {
+            Some(condition) => !event_manager.evaluate_condition(engine_data, condition),
+            None => {
+                engine_data.logger.log_error("ScriptingAPI", "not expects a condition block.");
+                false
+            }
+        }
+    }
+
+    // args: [function name, variable to store the return value in (can be empty), function args...]
+    fn call_function(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        let name = match args.get(0) {
+            Some(value) => event_manager.get_expr(&Self::arg_to_string(value), engine_data),
+            None => {
+                engine_data.logger.log_error("ScriptingAPI", "call_function needs a function name.");
+                return false;
+            }
+        };
+        let result_var = args.get(1).map(Self::arg_to_string).unwrap_or_default();
+        let call_args: Vec<ScriptValue> = args.iter().skip(2)
+            .map(|arg| ScriptValue::parse(&event_manager.get_expr(&Self::arg_to_string(arg), engine_data)))
+            .collect();
+
+        let result = match event_manager.call_function(engine_data, &name, call_args) {
+            Ok(result) => result,
+            Err(e) => {
+                engine_data.logger.log_error("ScriptingAPI", e.as_str());
+                return false;
+            }
+        };
+        if !result_var.is_empty() {
+            event_manager.set_variable_value(result_var, result.clone().unwrap_or(ScriptValue::Str(String::new())));
+        }
+        // Lets functions double as conditions, finishing without a return value counts as true
+        result.map_or(true, |value| value.is_truthy())
+    }
+
+    // call_lua [name, args...], the Lua function's result works as a condition
+    fn call_lua(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        let name = match args.get(0) {
+            Some(value) => event_manager.get_expr(&Self::arg_to_string(value), engine_data),
+            None => {
+                engine_data.logger.log_error("ScriptingAPI", "call_lua needs a function name.");
+                return false;
+            }
+        };
+        let call_args: Vec<String> = args.iter().skip(1)
+            .map(|arg| event_manager.get_expr(&Self::arg_to_string(arg), engine_data))
+            .collect();
+        event_manager.call_lua(&name, call_args, engine_data)
+    }
+
+    fn stop_propagation(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        event_manager.stop_propagation();
+        true
+    }
+
+    fn cancel_timer(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        match args.get(0) {
+            Some(value) => {
+                let name = event_manager.get_expr(&Self::arg_to_string(value), engine_data);
+                event_manager.cancel_timer(&name);
+                true
+            }
+            None => {
+                engine_data.logger.log_error("ScriptingAPI", "cancel_timer needs a timer name.");
+                false
+            }
+        }
+    }
+
+    fn is_timer_running(engine_data: &mut EngineData, event_manager: & mut EventManager, args: &[Value]) -> bool {
+        match args.get(0) {
+            Some(value) => {
+                let name = event_manager.get_expr(&Self::arg_to_string(value), engine_data);
+                event_manager.is_timer_running(&name)
+            }
+            None => false,
+        }
+    }
+
+    // Script args are usually strings but the editor sometimes writes raw numbers or bools
+    fn arg_to_string(value: &Value) -> String {
+        match value.as_str() {
+            Some(s) => s.to_string(),
+            None => value.to_string(),
+        }
+    }
+
+    fn TickEvents(engine_data: &mut EngineData, event_manager: &mut EventManager) -> Result<(), TetraError> {
+        engine_data.input.advance_tick();
+        if engine_data.input.finish_replay_if_done() {
+            engine_data.logger.log("ScriptingAPI", "Replay finished, input is back to the player.");
+            event_manager.trigger_event("on_replay_finished", &[], engine_data);
+        }
+        // While paused only global and menu scripts run, so the pause menu still works but the night waits
+        let paused = engine_data.clock.is_paused();
+        if paused {
+            event_manager.run_scheduled_in(engine_data, Self::runs_while_paused);
+        } else {
+            event_manager.run_scheduled(engine_data);
+            event_manager.trigger_event("on_game_loop", &[String::new()], engine_data);
+        }
+        // Nothing can be pressed or clicked while the screen is fading, letting go still counts
+        let fading = engine_data.scenes.is_fading();
+        Self::KeyEvents(engine_data, event_manager, fading);
+        if !paused {
+            let tick = engine_data.clock.get_current_tick();
+            event_manager.trigger_event(
+                "current_tick_equals",
+                &[tick.to_string()],
+                engine_data,
+            );
+        }
+        engine_data.audio.tick(TickManager::tick_length().as_secs_f32());
+        // Play time only lands in the save when it's written, so a crash can't lose more than a few minutes of it
+        if engine_data.save.play_time_due() {
+            event_manager.flush_data_values(engine_data);
+        }
+        SceneManager::tick(engine_data, event_manager);
+        if fading || engine_data.scenes.is_fading() {
+            return Ok(());
+        }
 
         let mut event_name_clicked: String = String::new();
         let mut event_args_clicked: Vec<String> = Vec::new();
+        let mut clicked_id: String = String::new();
         let mut selected_button_id: String = String::new();
         let mut is_image: bool = false;;
 
+        // Only the office scrolls, menus over it stay put
+        let x_offset = if engine_data.scenes.top() == SceneKind::Office { engine_data.officemgr.scroll_x } else { 0.0 };
         for (k, mut button) in &mut engine_data.buttons {
-            button.update(ctx, engine_data.officemgr.scroll_x);
+            button.update(&engine_data.input.current, x_offset);
             if button.is_hovered {
-                match (engine_data.scene) {
-                    0 => {
+                match (engine_data.scenes.top()) {
+                    SceneKind::Menu | SceneKind::Pause => {
                         engine_data.menumgr.selected_button_id = k.clone()
                     }
-                    1 => {
+                    SceneKind::Office => {
                         if (!engine_data.officemgr.hovered_id.is_empty()) {
                             engine_data.officemgr.game_data.office.objects.get_mut(engine_data.officemgr.hovered_id.as_str()).expect("Previous Object not found??").hovered = false;
                         }
@@ -307,6 +938,7 @@ impl ScriptingAPI {
                 if button.is_clicked {
                     event_name_clicked = button.event.clone();
                     event_args_clicked = button.event_args.clone();
+                    clicked_id = k.clone();
                 }
                 selected_button_id = k.clone();
                 is_image = button.is_image.clone();
@@ -316,14 +948,18 @@ impl ScriptingAPI {
         if (!selected_button_id.is_empty()) {
             if (!engine_data.hovered) {
                 event_manager
-                    .trigger_event(if is_image { "image_selected" } else { "button_selected" }, &[selected_button_id], engine_data);
+                    .trigger_event(if is_image { "image_selected" } else { "button_selected" }, &[selected_button_id.clone()], engine_data);
                 event_manager
-                    .trigger_event("any_button_selected", &[], engine_data);
+                    .trigger_event("any_button_selected", &[selected_button_id], engine_data);
                 engine_data.hovered = true;
             }
 
             if (!engine_data.disabled_clicks[3]) {
                 if (!event_name_clicked.is_empty()) {
+                    // Doors are part of the office itself, they toggle before the scripts hear about the click
+                    if engine_data.scenes.top() == SceneKind::Office {
+                        OfficeManager::toggle_door(&clicked_id, engine_data);
+                    }
                     event_manager
                         .trigger_event(event_name_clicked.as_str(), &*event_args_clicked, engine_data);
                     engine_data.disabled_clicks[3] = true;
@@ -344,23 +980,108 @@ impl ScriptingAPI {
         ];
 
         for (button, event_name) in &mouse_buttons {
-            if !engine_data.disabled_clicks[MouseButton_to_i32(*button)] && is_mouse_button_down(ctx, *button) {
-                event_manager.trigger_event(event_name, &[], engine_data);
-                engine_data.disabled_clicks[MouseButton_to_i32(*button)] = true;
+            let index = match MouseButton_to_i32(*button) {
+                Some(index) => index,
+                None => continue,
+            };
+            let is_down = InputManager::mouse_button_name(*button).map_or(false, |name| engine_data.input.is_mouse_down(name));
+            if !engine_data.disabled_clicks[index] && is_down {
+                Self::trigger_input(event_name, &[], engine_data, event_manager);
+                engine_data.disabled_clicks[index] = true;
             }
-            else if engine_data.disabled_clicks[MouseButton_to_i32(*button)] && is_mouse_button_up(ctx, *button) {
-                engine_data.disabled_clicks[MouseButton_to_i32(*button)] = false;
+            else if engine_data.disabled_clicks[index] && !is_down {
+                engine_data.disabled_clicks[index] = false;
             }
         }
         Ok(())
     }
+
+    // A fade only lets releases through, so whatever started a hold before it still hears the end of it
+    fn KeyEvents(engine_data: &mut EngineData, event_manager: &mut EventManager, fading: bool) {
+        for key in engine_data.input.released_keys() {
+            Self::trigger_input("key_released", &[key], engine_data, event_manager);
+        }
+        for action in engine_data.input.released_actions() {
+            Self::trigger_input("action_released", &[action], engine_data, event_manager);
+        }
+        if fading {
+            return;
+        }
+
+        for key in engine_data.input.pressed_keys() {
+            Self::trigger_input("key_pressed", &[key], engine_data, event_manager);
+        }
+        for key in engine_data.input.held_keys() {
+            Self::trigger_input("key_held", &[key], engine_data, event_manager);
+        }
+        for action in engine_data.input.pressed_actions() {
+            if action == "pause" {
+                Self::PauseAction(engine_data, event_manager);
+            } else if engine_data.scenes.top() == SceneKind::Office {
+                Self::OfficeAction(&action, engine_data, event_manager);
+            }
+            Self::trigger_input("action_pressed", &[action], engine_data, event_manager);
+        }
+    }
+
+    // While paused only global and menu scripts hear input, the night under the pause menu waits
+    fn trigger_input(event_name: &str, args: &[String], engine_data: &mut EngineData, event_manager: &mut EventManager) {
+        let paused = engine_data.clock.is_paused();
+        event_manager.trigger_event_in(event_name, args, engine_data, |scope| !paused || Self::runs_while_paused(scope));
+    }
+
+    fn runs_while_paused(scope: &ListenerScope) -> bool {
+        matches!(scope, ListenerScope::Global | ListenerScope::Menu(_))
+    }
+
+    // Toggles the game's pause menu, games without one can still use the action for their own thing
+    fn PauseAction(engine_data: &mut EngineData, event_manager: &mut EventManager) {
+        match engine_data.scenes.top() {
+            SceneKind::Office => {
+                if let Some(menu) = MenuManager::pause_menu(engine_data) {
+                    MenuManager::pause_game(&menu, engine_data, event_manager);
+                }
+            }
+            SceneKind::Pause => {
+                MenuManager::resume_game(engine_data, event_manager);
+            }
+            _ => {}
+        }
+    }
+
+    // The built-in office mechanics that are driven by named actions
+    fn OfficeAction(action: &str, engine_data: &mut EngineData, event_manager: &mut EventManager) {
+        match action {
+            "toggle_camera" => {
+                let player = &mut engine_data.officemgr.game_data.player;
+                if player.is_camera_up {
+                    player.putdown();
+                    event_manager.trigger_event("on_camera_down", &[], engine_data);
+                } else if !player.is_mask_on {
+                    player.pullup();
+                    event_manager.trigger_event("on_camera_up", &[], engine_data);
+                }
+            }
+            "mask" if engine_data.officemgr.game_data.settings.mask => {
+                let player = &mut engine_data.officemgr.game_data.player;
+                if player.is_mask_on {
+                    player.mask_off();
+                    event_manager.trigger_event("on_mask_off", &[], engine_data);
+                } else if !player.is_camera_up {
+                    player.mask_on();
+                    event_manager.trigger_event("on_mask_on", &[], engine_data);
+                }
+            }
+            _ => {}
+        }
+    }
 }
 
-fn MouseButton_to_i32(value: MouseButton) -> usize {
+fn MouseButton_to_i32(value: MouseButton) -> Option<usize> {
     match value {
-        MouseButton::Left => 0,
-        MouseButton::Middle => 1,
-        MouseButton::Right => 2,
-        _ => unimplemented!()
+        MouseButton::Left => Some(0),
+        MouseButton::Middle => Some(1),
+        MouseButton::Right => Some(2),
+        _ => None
     }
 }
\ No newline at end of file
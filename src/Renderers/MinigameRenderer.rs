@@ -0,0 +1,84 @@
+pub struct MinigameRenderer;
+
+impl MinigameRenderer {
+    pub fn render(ctx: &mut Context, engine: &mut EngineData, cache: &mut CacheData) -> tetra::Result {
+        let minigame = match engine.game.minigames.get(&engine.minigamemgr.curminigame) {
+            Some(minigame) => minigame.clone(),
+            None => return Ok(()),
+        };
+        // The screen is cleared once a frame before anything draws, the background goes over that
+        Mesh::rectangle(ctx, ShapeStyle::Fill, Rectangle::new(0.0, 0.0, 1280.0, 720.0))?
+            .draw(ctx, DrawParams::new().color(Self::background_color(&minigame.background_color)));
+
+        let tile_size = minigame.tile_size as f32;
+        let scale = minigame.scale;
+        let map_size = Vec2::new(
+            minigame.map.iter().map(|row| row.chars().count()).max().unwrap_or(0) as f32,
+            minigame.map.len() as f32,
+        ) * tile_size * scale;
+
+        // Follows the player, but never past the edge of the map. Maps smaller than the screen get centred.
+        let screen = Vec2::new(1280.0, 720.0);
+        let player = engine.minigamemgr.player * scale;
+        let player_size = Vec2::new(minigame.player.width, minigame.player.height) * scale;
+        let centre = player + player_size / 2.0 - screen / 2.0;
+        let camera = Vec2::new(
+            Self::camera_axis(centre.x, map_size.x, screen.x),
+            Self::camera_axis(centre.y, map_size.y, screen.y),
+        );
+
+        for (row, line) in minigame.map.iter().enumerate() {
+            for (column, tile) in line.chars().enumerate() {
+                let sprite = match minigame.tiles.get(&tile.to_string()) {
+                    Some(tile) if !tile.sprite.is_empty() => &tile.sprite,
+                    _ => continue,
+                };
+                let position = Vec2::new(column as f32, row as f32) * tile_size * scale - camera;
+                Self::draw_sprite(ctx, engine, cache, sprite, position, scale);
+            }
+        }
+
+        for sprite in &minigame.sprites {
+            if engine.minigamemgr.hidden_sprites.contains(&sprite.id) {
+                continue;
+            }
+            let position = Vec2::new(sprite.x, sprite.y) * scale - camera;
+            Self::draw_sprite(ctx, engine, cache, &sprite.sprite, position, scale);
+        }
+
+        Self::draw_sprite(ctx, engine, cache, &minigame.player.sprite, player - camera, scale);
+        Ok(())
+    }
+
+    fn draw_sprite(ctx: &mut Context, engine: &EngineData, cache: &mut CacheData, sprite: &str, position: Vec2<f32>, scale: f32) {
+        cache.textures
+            .entry(sprite.to_string())
+            .or_insert_with(|| {
+                let texture = Texture::new(ctx, engine.game.sprite_path(&engine.assets, sprite)
+                    .to_str()
+                    .unwrap()
+                    .to_string()).unwrap();
+                // Pixel art stays sharp when it's scaled up
+                texture.set_filter_mode(ctx, tetra::graphics::FilterMode::Nearest);
+                texture
+            })
+            .draw(ctx, DrawParams::new().position(position.round()).scale(Vec2::broadcast(scale)));
+    }
+
+    fn camera_axis(centre: f32, map: f32, screen: f32) -> f32 {
+        if map <= screen {
+            (map - screen) / 2.0
+        } else {
+            centre.clamp(0.0, map - screen)
+        }
+    }
+
+    // "r,g,b", black if it's missing or doesn't parse
+    fn background_color(color: &str) -> Color {
+        let parts: Vec<u8> = color.split(',').filter_map(|part| part.trim().parse().ok()).collect();
+        match parts[..] {
+            [r, g, b] => Color::rgb8(r, g, b),
+            _ => Color::rgb(0.0, 0.0, 0.0),
+        }
+    }
+}
@@ -1,7 +1,8 @@
 use std::alloc::System;
 use std::backtrace::Backtrace;
 use std::panic;
-use std::sync::mpsc;
+use std::sync::{mpsc, atomic::{AtomicBool, Ordering}};
+use std::thread;
 use libc;
 use chrono::Utc;
 use libc::c_int;
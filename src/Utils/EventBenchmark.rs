@@ -0,0 +1,77 @@
+// Dispatch timings for EventManager, run with `cargo test --release bench_event_dispatch -- --ignored --nocapture`
+pub struct EventBenchmark;
+
+const BENCH_EVENT_NAMES: usize = 100;
+const BENCH_TRIGGERS: usize = 100_000;
+
+impl EventBenchmark {
+    pub fn run(engine: &mut EngineData) {
+        for listener_count in [1_000, 5_000, 20_000] {
+            Self::run_with(engine, listener_count);
+        }
+    }
+
+    fn run_with(engine: &mut EngineData, listener_count: usize) {
+        let logger = engine.logger.clone();
+        let mut events = EventManager::new(logger.clone());
+        events.set_verbose(false);
+
+        let names: Vec<String> = (0..BENCH_EVENT_NAMES).map(|i| format!("bench_event_{}", i)).collect();
+        let args: Vec<Vec<String>> = (0..50).map(|i| vec![i.to_string()]).collect();
+
+        // Mostly exact listeners with a few wildcard and catch-all ones mixed in, like a real game
+        let start = Instant::now();
+        for i in 0..listener_count {
+            let listener_args = match i % 10 {
+                0 => vec!["*".to_string()],
+                1 => Vec::new(),
+                _ => args[i % args.len()].clone(),
+            };
+            events.register_listener(names[i % names.len()].clone(), listener_args, Vec::new(), (i % 3) as i32, ListenerScope::Global);
+        }
+        let register_time = start.elapsed();
+
+        let start = Instant::now();
+        for i in 0..BENCH_TRIGGERS {
+            events.trigger_event(&names[i % names.len()], &args[i % args.len()], engine);
+        }
+        let trigger_time = start.elapsed();
+
+        // Every register throws the cached handler lists away, this is the worst case for that
+        let start = Instant::now();
+        for i in 0..BENCH_TRIGGERS / 10 {
+            let id = events.register_listener(names[i % names.len()].clone(), Vec::new(), Vec::new(), 0, ListenerScope::Global);
+            events.trigger_event(&names[i % names.len()], &args[i % args.len()], engine);
+            events.kill_listener_by_id(id);
+        }
+        let churn_time = start.elapsed();
+
+        logger.log("Benchmark", format!(
+            "{} listeners: registered in {:.2?}, {} triggers in {:.2?} ({:.0} ns each), {} register/trigger/kill in {:.2?}",
+            events.listener_count(),
+            register_time,
+            BENCH_TRIGGERS,
+            trigger_time,
+            trigger_time.as_nanos() as f64 / BENCH_TRIGGERS as f64,
+            BENCH_TRIGGERS / 10,
+            churn_time,
+        ).as_str());
+    }
+}
+
+#[cfg(test)]
+mod event_benchmark {
+    use super::*;
+
+    #[test]
+    #[ignore]
+    fn bench_event_dispatch() {
+        let save_dir = env::temp_dir().join("FNaF-Engine-Rust-tests").join("bench_event_dispatch");
+        let assets = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join("test_game");
+        let mut game = HeadlessGame::new_with(assets, |engine| {
+            engine.save = SaveManager::at(save_dir.join("save.json"), &engine.game.game_info.id, engine.logger.clone());
+        });
+        EventBenchmark::run(&mut game.engine);
+        let _ = fs::remove_dir_all(&save_dir);
+    }
+}
@@ -0,0 +1,172 @@
+// Runs the game logic with no window, GPU or sound card. Ticks run back to back instead of
+// waiting on real time, input comes from a replay or whatever gets injected.
+pub struct HeadlessGame {
+    pub engine: EngineData,
+    pub eventmanager: EventManager,
+    platform: HeadlessPlatform,
+}
+
+impl HeadlessGame {
+    pub fn new(assets: PathBuf) -> HeadlessGame {
+        Self::new_with(assets, |_| {})
+    }
+
+    // Setup runs after the engine is loaded but before the save is read and the scripts start
+    pub fn new_with<F>(assets: PathBuf, setup: F) -> HeadlessGame
+    where
+        F: FnOnce(&mut EngineData),
+    {
+        let mods = ModLoader::load(&assets);
+        let audio = NullAudio::new(&mods.asset_dirs(&assets, "sounds"));
+        let mut engine = EngineData::load(assets, mods, Box::new(audio));
+        let logger = engine.logger.clone();
+        engine.extensions.skip_plugins(&logger);
+        let mut platform = HeadlessPlatform::new(&engine.assets, &engine.game);
+        setup(&mut engine);
+        let mut eventmanager = engine.start_scripts();
+        engine.recache_buttons(&mut platform, &mut eventmanager);
+        engine.clock.start();
+
+        HeadlessGame { engine, eventmanager, platform }
+    }
+
+    // Same order as GameState::update, minus the drawing
+    pub fn step(&mut self) {
+        if self.engine.needs_recache {
+            self.engine.needs_recache = false;
+            self.engine.recache_buttons(&mut self.platform, &mut self.eventmanager);
+        }
+        self.engine.clock.advance(TickManager::tick_length());
+        while self.engine.clock.next_tick() {
+            ScriptingAPI::TickEvents(&mut self.engine, &mut self.eventmanager).expect("FATAL: Failed to Tick Events.");
+        }
+    }
+
+    pub fn run_ticks(&mut self, ticks: u64) {
+        for _ in 0..ticks {
+            self.step();
+        }
+    }
+
+    // Steps until the condition holds, false if it never did within max_ticks
+    pub fn run_until<F>(&mut self, max_ticks: u64, mut condition: F) -> bool
+    where
+        F: FnMut(&HeadlessGame) -> bool,
+    {
+        for _ in 0..max_ticks {
+            if condition(self) {
+                return true;
+            }
+            self.step();
+        }
+        condition(self)
+    }
+
+    pub fn inject_input(&mut self, state: &InputState) {
+        self.engine.input.inject(state);
+    }
+
+    // --headless [--ticks N], runs without a window until the ticks run out or the replay ends
+    pub fn run_cli() {
+        let ticks = cli_arg("--ticks")
+            .and_then(|ticks| ticks.parse::<u64>().ok())
+            .unwrap_or(TICKS_PER_SECOND * 60 * 10);
+        let mut game = HeadlessGame::new(EngineData::find_assets());
+        let replaying = game.engine.input.is_replaying();
+
+        let mut ran = 0;
+        while ran < ticks {
+            game.step();
+            ran += 1;
+            if game.engine.quit_requested || (replaying && !game.engine.input.is_replaying()) {
+                break;
+            }
+        }
+
+        game.eventmanager.flush_dirty_data(&mut game.engine);
+        let game_data = &game.engine.officemgr.game_data;
+        game.engine.logger.log("Headless", format!(
+            "Ran {} ticks. Scene: {:?}, menu: {}, night: {}, hour: {}, power: {}%",
+            ran, game.engine.scenes.current(), game.engine.menumgr.curmenu, game_data.night, game_data.time, game_data.power.level
+        ).as_str());
+    }
+}
+
+#[cfg(test)]
+mod headless_game {
+    use super::*;
+
+    // The fixture game with a save of its own, so tests never share or touch a real one
+    fn fixture_game(test: &str) -> (HeadlessGame, PathBuf) {
+        let save_dir = env::temp_dir().join("FNaF-Engine-Rust-tests").join(test);
+        let _ = fs::remove_dir_all(&save_dir);
+        let assets = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join("test_game");
+        let save_path = save_dir.join("save.json");
+        let game = HeadlessGame::new_with(assets, |engine| {
+            engine.save = SaveManager::at(save_path, &engine.game.game_info.id, engine.logger.clone());
+            engine.input = InputManager::new(&engine.game.actions);
+        });
+        (game, save_dir)
+    }
+
+    fn start_night(game: &mut HeadlessGame, office: &str, night: i32) {
+        game.engine.officemgr.curoffice = office.to_string();
+        OfficeManager::goto_office(office.to_string(), night, &mut game.engine, &mut game.eventmanager);
+        assert!(game.run_until(TICKS_PER_SECOND * 10, |game| game.engine.scenes.current() == SceneKind::Office && !game.engine.scenes.is_fading()));
+        game.eventmanager.start_event_log();
+    }
+
+    fn fired(game: &HeadlessGame, name: &str) -> Vec<Vec<String>> {
+        game.eventmanager.event_log().iter().filter(|event| event.name == name).map(|event| event.args.clone()).collect()
+    }
+
+    #[test]
+    fn night_clock_runs_to_six_am() {
+        let (mut game, save_dir) = fixture_game("night_clock_runs_to_six_am");
+        start_night(&mut game, "Office", 1);
+
+        let night_ticks = (TICKS_PER_HOUR * LAST_HOUR) as u64;
+        assert!(game.run_until(night_ticks + 1, |game| game.engine.officemgr.game_data.paused));
+        // Paused once the night is over, nothing moves after that
+        game.run_ticks(TICKS_PER_HOUR as u64);
+
+        let game_data = &game.engine.officemgr.game_data;
+        assert_eq!(game_data.time, LAST_HOUR);
+        // Nothing closed or lit, so one bar of usage the whole night
+        assert_eq!(game_data.power.level, 100 - (night_ticks as i32 / DRAIN_PER_PERCENT));
+        let hours: Vec<Vec<String>> = (1..=LAST_HOUR).map(|hour| vec![hour.to_string()]).collect();
+        assert_eq!(fired(&game, "on_hour"), hours);
+        assert_eq!(fired(&game, "on_night_end"), vec![vec!["1".to_string()]]);
+        assert!(fired(&game, "on_power_out").is_empty());
+        let _ = fs::remove_dir_all(&save_dir);
+    }
+
+    #[test]
+    fn closed_door_drains_twice_as_fast() {
+        let (mut game, save_dir) = fixture_game("closed_door_drains_twice_as_fast");
+        start_night(&mut game, "Office", 1);
+
+        OfficeManager::toggle_door("LeftDoor", &mut game.engine);
+        assert!(game.engine.officemgr.game_data.office.doors["LeftDoor"].is_closed);
+        game.run_ticks(DRAIN_PER_PERCENT as u64);
+
+        let power = &game.engine.officemgr.game_data.power;
+        assert_eq!(power.usage, 2);
+        assert_eq!(power.level, 98);
+        let _ = fs::remove_dir_all(&save_dir);
+    }
+
+    #[test]
+    fn offices_without_night_clock_leave_time_and_power_to_scripts() {
+        let (mut game, save_dir) = fixture_game("offices_without_night_clock");
+        start_night(&mut game, "ScriptedOffice", 1);
+
+        game.run_ticks(TICKS_PER_HOUR as u64 * 2);
+
+        let game_data = &game.engine.officemgr.game_data;
+        assert_eq!(game_data.time, 0);
+        assert_eq!(game_data.power.level, 100);
+        assert!(fired(&game, "on_hour").is_empty());
+        let _ = fs::remove_dir_all(&save_dir);
+    }
+}
@@ -0,0 +1,416 @@
+// Mods sit in <game>/mods, as a folder or a .zip, with a mod.json at the top:
+//
+// {
+//   "id": "hard_mode",
+//   "name": "Hard Mode",
+//   "version": "1.2.0",
+//   "dependencies": { "more_cameras": "1.0" },
+//   "load_order": 10
+// }
+//
+// Any file a mod has under sprites/, sounds/, animations/ or scripts/ is used in place of the game's
+// file with the same path, or added if the game doesn't have one. game.patch.json is a JSON merge
+// patch (RFC 7396) applied to game.json, so a mod only has to give the parts it changes. Arrays are
+// replaced whole, adding a menu element means giving the menu's full element list.
+//
+// Mods load by load_order, then id, with dependencies always before the mods needing them. When two
+// mods touch the same file or game.json value the later one wins, and it gets reported.
+
+const MOD_MANIFEST: &str = "mod.json";
+const MOD_PATCH: &str = "game.patch.json";
+// Which mods the player turned off, everything else found is loaded
+const MOD_SETTINGS: &str = "mods.json";
+// Archives are unpacked in here the first time a version of them is seen, and loaded like folders
+const MOD_UNPACK_DIR: &str = ".unpacked";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModManifest {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+    // Mod id to the lowest version that works, "" or "*" for any
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    pub load_order: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct ModInfo {
+    pub manifest: ModManifest,
+    pub path: PathBuf,
+    // What the player wants, takes effect on the next start
+    pub enabled: bool,
+    enabled_at_start: bool,
+    // Whether it's actually in this run
+    pub loaded: bool,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct ModSettings {
+    #[serde(default)]
+    disabled: BTreeSet<String>,
+}
+
+pub struct ModLoader {
+    dir: PathBuf,
+    // In load order, disabled and broken ones included so the mod list can show them
+    pub mods: Vec<ModInfo>,
+    pub errors: Vec<String>,
+    pub conflicts: Vec<String>,
+}
+
+impl ModLoader {
+    pub fn load(assets: &PathBuf) -> ModLoader {
+        let dir = assets.join("mods");
+        let mut loader = ModLoader { dir, mods: Vec::new(), errors: Vec::new(), conflicts: Vec::new() };
+        if !loader.dir.is_dir() {
+            return loader;
+        }
+
+        let settings: ModSettings = fs::read_to_string(loader.dir.join(MOD_SETTINGS))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+
+        let mut found = Vec::new();
+        for path in loader.mod_folders() {
+            match Self::read_manifest(&path) {
+                Ok(manifest) if found.iter().any(|m: &ModInfo| m.manifest.id == manifest.id) => {
+                    loader.errors.push(format!("Two mods use the id '{}', skipping {:?}.", manifest.id, path));
+                }
+                Ok(manifest) => {
+                    let enabled = !settings.disabled.contains(&manifest.id);
+                    found.push(ModInfo { manifest, path, enabled, enabled_at_start: enabled, loaded: enabled });
+                }
+                Err(e) => loader.errors.push(e),
+            }
+        }
+        found.sort_by(|a, b| a.manifest.load_order.cmp(&b.manifest.load_order).then_with(|| a.manifest.id.cmp(&b.manifest.id)));
+
+        loader.mods = loader.resolve_dependencies(found);
+        loader.find_conflicts();
+        loader
+    }
+
+    // Folders as they are, archives unpacked first
+    fn mod_folders(&mut self) -> Vec<PathBuf> {
+        let mut entries: Vec<PathBuf> = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect(),
+            Err(_) => return Vec::new(),
+        };
+        entries.sort();
+
+        let mut folders = Vec::new();
+        for path in entries {
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            if path.is_dir() && !file_name.starts_with('.') {
+                folders.push(path);
+            } else if path.extension().map_or(false, |ext| ext == "zip") {
+                match self.unpack(&path) {
+                    Ok(folder) => folders.push(folder),
+                    Err(e) => self.errors.push(e),
+                }
+            }
+        }
+        self.prune_unpacked(&folders);
+        folders
+    }
+
+    // The folder is named after the archive with its full name, size and modified time hashed on,
+    // so a changed archive gets unpacked again and two names that sanitize the same don't share one
+    fn unpack(&self, archive: &Path) -> Result<PathBuf, String> {
+        let metadata = fs::metadata(archive).map_err(|e| format!("Failed to open mod archive {:?}: {}", archive, e))?;
+        let modified = metadata.modified().ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        let file_name = archive.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let key = format!("{}\0{}\0{}", file_name, metadata.len(), modified.as_nanos());
+        let hash = SaveManager::fnv_hash(key.bytes());
+        let stem = archive.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        let folder = self.dir.join(MOD_UNPACK_DIR).join(format!("{}-{:016x}", SaveManager::sanitize(&stem), hash));
+
+        // Only moved into place once everything is out, so a folder that's there is a finished unpack
+        if folder.is_dir() {
+            return Ok(folder);
+        }
+        let partial = folder.with_extension("partial");
+        let _ = fs::remove_dir_all(&partial);
+        let file = File::open(archive).map_err(|e| format!("Failed to open mod archive {:?}: {}", archive, e))?;
+        let mut zip = zip::ZipArchive::new(file).map_err(|e| format!("{:?} isn't a valid zip: {}", archive, e))?;
+        zip.extract(&partial).map_err(|e| format!("Failed to unpack mod archive {:?}: {}", archive, e))?;
+        fs::rename(&partial, &folder).map_err(|e| format!("Failed to unpack mod archive {:?}: {}", archive, e))?;
+        Ok(folder)
+    }
+
+    // Unpacks of archives that changed or aren't there anymore
+    fn prune_unpacked(&self, in_use: &[PathBuf]) {
+        let entries = match fs::read_dir(self.dir.join(MOD_UNPACK_DIR)) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+            if !in_use.contains(&path) {
+                let _ = fs::remove_dir_all(&path);
+            }
+        }
+    }
+
+    fn read_manifest(path: &Path) -> Result<ModManifest, String> {
+        let content = fs::read_to_string(path.join(MOD_MANIFEST))
+            .map_err(|e| format!("{:?} has no readable {}: {}", path, MOD_MANIFEST, e))?;
+        let mut manifest: ModManifest = serde_json::from_str(&content)
+            .map_err(|e| format!("{:?} has an invalid {}: {}", path, MOD_MANIFEST, e))?;
+        if manifest.id.trim().is_empty() {
+            return Err(format!("{:?} has no id in its {}.", path, MOD_MANIFEST));
+        }
+        if manifest.name.is_empty() {
+            manifest.name = manifest.id.clone();
+        }
+        Ok(manifest)
+    }
+
+    // Keeps the load order where it can, but moves mods after what they depend on. One with a missing,
+    // outdated or disabled dependency (or a cycle) doesn't load.
+    fn resolve_dependencies(&mut self, mut pending: Vec<ModInfo>) -> Vec<ModInfo> {
+        let mut ordered: Vec<ModInfo> = Vec::new();
+        loop {
+            let ready = pending.iter().position(|m| {
+                m.manifest.dependencies.keys().all(|id| ordered.iter().any(|placed| placed.manifest.id == *id)
+                    || !pending.iter().any(|other| other.manifest.id == *id))
+            });
+            let index = match ready {
+                Some(index) => index,
+                None => break,
+            };
+            let mut info = pending.remove(index);
+            if info.loaded {
+                for (id, version) in &info.manifest.dependencies {
+                    let problem = match ordered.iter().find(|placed| placed.manifest.id == *id) {
+                        None => Some(format!("needs '{}', which isn't installed", id)),
+                        Some(dependency) if !dependency.loaded => Some(format!("needs '{}', which isn't loaded", id)),
+                        Some(dependency) if !Self::version_satisfies(&dependency.manifest.version, version) => {
+                            Some(format!("needs '{}' {} or newer, {} is installed", id, version, dependency.manifest.version))
+                        }
+                        _ => None,
+                    };
+                    if let Some(problem) = problem {
+                        self.errors.push(format!("Mod '{}' {}.", info.manifest.id, problem));
+                        info.loaded = false;
+                        break;
+                    }
+                }
+            }
+            ordered.push(info);
+        }
+
+        // Whatever's left depends on itself somewhere down the line
+        for mut info in pending {
+            self.errors.push(format!("Mod '{}' has circular dependencies.", info.manifest.id));
+            info.loaded = false;
+            ordered.push(info);
+        }
+        ordered
+    }
+
+    // Dotted numbers compared piece by piece, "1.10" is newer than "1.9"
+    fn version_satisfies(version: &str, minimum: &str) -> bool {
+        let minimum = minimum.trim();
+        if minimum.is_empty() || minimum == "*" {
+            return true;
+        }
+        let parts = |text: &str| -> Vec<u64> {
+            text.trim().split('.').map(|part| part.parse::<u64>().unwrap_or(0)).collect()
+        };
+        let (version, minimum) = (parts(version), parts(minimum));
+        for i in 0..version.len().max(minimum.len()) {
+            let (have, need) = (version.get(i).copied().unwrap_or(0), minimum.get(i).copied().unwrap_or(0));
+            if have != need {
+                return have > need;
+            }
+        }
+        true
+    }
+
+    fn find_conflicts(&mut self) {
+        let mut files: HashMap<String, String> = HashMap::new();
+        let mut values: Vec<(String, String)> = Vec::new();
+        let mut conflicts = Vec::new();
+
+        for info in self.loaded() {
+            let mut found = Vec::new();
+            Self::mod_files(&info.path, &info.path, &mut found);
+            for file in found {
+                if let Some(previous) = files.insert(file.clone(), info.manifest.id.clone()) {
+                    conflicts.push(format!("'{}' and '{}' both replace {}, '{}' wins.", previous, info.manifest.id, file, info.manifest.id));
+                }
+            }
+
+            if let Some(patch) = Self::read_patch(info) {
+                let mut paths = Vec::new();
+                Self::patch_paths(&patch, String::new(), &mut paths);
+                for path in paths {
+                    // A patch inside a value another mod patched counts too
+                    for (previous_path, previous) in &values {
+                        if *previous != info.manifest.id && Self::paths_overlap(previous_path, &path) {
+                            conflicts.push(format!("'{}' and '{}' both patch game.json {}, '{}' wins.", previous, info.manifest.id, path, info.manifest.id));
+                        }
+                    }
+                    values.push((path, info.manifest.id.clone()));
+                }
+            }
+        }
+        conflicts.dedup();
+        self.conflicts = conflicts;
+    }
+
+    // Paths relative to the mod folder, with / so they match the game's own
+    fn mod_files(root: &Path, dir: &Path, files: &mut Vec<String>) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                Self::mod_files(root, &path, files);
+            } else if let Ok(relative) = path.strip_prefix(root) {
+                let relative = relative.to_string_lossy().replace("\\", "/");
+                if relative != MOD_MANIFEST && relative != MOD_PATCH {
+                    files.push(relative);
+                }
+            }
+        }
+    }
+
+    // The JSON pointer of every value the patch sets or removes
+    fn patch_paths(patch: &Value, path: String, paths: &mut Vec<String>) {
+        match patch {
+            Value::Object(fields) if !fields.is_empty() => {
+                for (key, value) in fields {
+                    Self::patch_paths(value, format!("{}/{}", path, key), paths);
+                }
+            }
+            _ => paths.push(path),
+        }
+    }
+
+    fn paths_overlap(a: &str, b: &str) -> bool {
+        a == b || b.starts_with(&format!("{}/", a)) || a.starts_with(&format!("{}/", b))
+    }
+
+    fn read_patch(info: &ModInfo) -> Option<Value> {
+        let content = fs::read_to_string(info.path.join(MOD_PATCH)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    // RFC 7396, objects merge key by key, null removes a key and anything else replaces
+    fn merge_patch(target: &mut Value, patch: &Value) {
+        match patch {
+            Value::Object(fields) => {
+                if !target.is_object() {
+                    *target = Value::Object(serde_json::Map::new());
+                }
+                let target = target.as_object_mut().unwrap();
+                for (key, value) in fields {
+                    if value.is_null() {
+                        target.remove(key);
+                    } else {
+                        Self::merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+                    }
+                }
+            }
+            _ => *target = patch.clone(),
+        }
+    }
+
+    pub fn loaded(&self) -> impl Iterator<Item = &ModInfo> {
+        self.mods.iter().filter(|info| info.loaded)
+    }
+
+    // The game's folder first, then every loaded mod's, later ones win
+    pub fn asset_dirs(&self, assets: &PathBuf, folder: &str) -> Vec<PathBuf> {
+        let mut dirs = vec![assets.join(folder)];
+        dirs.extend(self.loaded().map(|info| info.path.join(folder)));
+        dirs
+    }
+
+    // game.json with every mod's patch on top, plus their scripts, animations and sprites. A mod
+    // whose patch, scripts or animations can't be read is skipped whole, the rest still load.
+    pub fn load_game(&mut self, assets: &PathBuf) -> Game {
+        let mut json = GameLoader::read_json(&assets.join("game.json")).expect("Failed to load game.json");
+        let mut contents = Vec::new();
+        let mut errors = Vec::new();
+        for info in self.mods.iter_mut().filter(|info| info.loaded) {
+            match Self::read_mod(info, &json) {
+                Ok((patched, content)) => {
+                    json = patched;
+                    contents.push((info.path.clone(), content));
+                }
+                Err(e) => {
+                    errors.push(format!("Mod '{}' was skipped: {}", info.manifest.id, e));
+                    info.loaded = false;
+                }
+            }
+        }
+        self.errors.extend(errors);
+
+        let mut game = GameLoader::from_json(json).expect("Failed to load game.json");
+        GameLoader::load_content(&mut game, assets).expect("Failed to load the game's scripts and animations");
+        for (path, content) in contents {
+            GameLoader::apply_content(&mut game, content);
+            let mut files = Vec::new();
+            Self::mod_files(&path.join("sprites"), &path.join("sprites"), &mut files);
+            for file in files {
+                let sprite = path.join("sprites").join(&file);
+                game.sprite_overrides.insert(file, sprite);
+            }
+        }
+        game
+    }
+
+    // The game.json the mod's patch makes and its content, checked before any of it is used
+    fn read_mod(info: &ModInfo, json: &Value) -> Result<(Value, GameLoader::Content), String> {
+        let mut patched = json.clone();
+        let path = info.path.join(MOD_PATCH);
+        if path.exists() {
+            let patch = GameLoader::read_json(&path)?;
+            Self::merge_patch(&mut patched, &patch);
+            GameLoader::from_json(patched.clone()).map_err(|e| format!("its {} breaks game.json: {}", MOD_PATCH, e))?;
+        }
+        Ok((patched, GameLoader::read_content(&info.path)?))
+    }
+
+    pub fn report(&self, logger: &Logger) {
+        for info in self.loaded() {
+            logger.log("ModLoader", format!("Loaded mod {} {} ({})", info.manifest.name, info.manifest.version, info.manifest.id).as_str());
+        }
+        for error in &self.errors {
+            logger.log_error("ModLoader", error.as_str());
+        }
+        for conflict in &self.conflicts {
+            logger.log_warn("ModLoader", conflict.as_str());
+        }
+    }
+
+    // Saved straight away, the mod itself only comes or goes on the next start
+    pub fn set_enabled(&mut self, id: &str, enabled: bool) -> Result<(), String> {
+        let info = self.mods.iter_mut().find(|info| info.manifest.id == id)
+            .ok_or_else(|| format!("There's no mod '{}'.", id))?;
+        info.enabled = enabled;
+
+        let settings = ModSettings {
+            disabled: self.mods.iter().filter(|info| !info.enabled).map(|info| info.manifest.id.clone()).collect(),
+        };
+        let json = serde_json::to_string_pretty(&settings).map_err(|e| e.to_string())?;
+        fs::write(self.dir.join(MOD_SETTINGS), json)
+            .map_err(|e| format!("Failed to write {:?}: {}", self.dir.join(MOD_SETTINGS), e))
+    }
+
+    // Whether the player changed anything since the game started
+    pub fn needs_restart(&self) -> bool {
+        self.mods.iter().any(|info| info.enabled != info.enabled_at_start)
+    }
+}
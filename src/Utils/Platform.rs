@@ -0,0 +1,77 @@
+// What the game logic needs to know about the window and the assets it draws. The tetra one asks the
+// real window and asset cache, the headless one works it out from the files so logic runs without a GPU.
+pub trait Platform {
+    fn window_size(&self) -> (i32, i32);
+    // Size of a text button's label, used for its click bounds
+    fn text_size(&mut self, text: &str, fontsize: i32) -> Vec2<f32>;
+    fn texture_size(&mut self, sprite: &str) -> Vec2<f32>;
+}
+
+pub struct TetraPlatform<'a> {
+    pub ctx: &'a mut Context,
+    pub cache: &'a mut CacheData,
+}
+
+impl Platform for TetraPlatform<'_> {
+    fn window_size(&self) -> (i32, i32) {
+        (window::get_width(self.ctx), window::get_height(self.ctx))
+    }
+
+    fn text_size(&mut self, text: &str, fontsize: i32) -> Vec2<f32> {
+        let uid = format!("{}-{}", text, fontsize);
+        let cache = &mut *self.cache;
+        let text = cache.texts
+            .entry(uid)
+            .or_insert_with(|| Text::new(text, cache.fonts[&fontsize].clone()));
+        match text.get_bounds(self.ctx) {
+            Some(bounds) => Vec2::new(bounds.width, bounds.height),
+            None => Vec2::zero(),
+        }
+    }
+
+    fn texture_size(&mut self, sprite: &str) -> Vec2<f32> {
+        let texture = &self.cache.textures[sprite];
+        Vec2::new(texture.width() as f32, texture.height() as f32)
+    }
+}
+
+pub struct HeadlessPlatform {
+    assets: PathBuf,
+    sprite_overrides: HashMap<String, PathBuf>,
+    texture_sizes: HashMap<String, Vec2<f32>>,
+}
+
+impl HeadlessPlatform {
+    pub fn new(assets: &PathBuf, game: &Game) -> HeadlessPlatform {
+        HeadlessPlatform {
+            assets: assets.clone(),
+            sprite_overrides: game.sprite_overrides.clone(),
+            texture_sizes: HashMap::new(),
+        }
+    }
+}
+
+impl Platform for HeadlessPlatform {
+    fn window_size(&self) -> (i32, i32) {
+        (1280, 720)
+    }
+
+    // Close enough to Arial for click bounds, nothing gets drawn
+    fn text_size(&mut self, text: &str, fontsize: i32) -> Vec2<f32> {
+        Vec2::new(text.chars().count() as f32 * fontsize as f32 * 0.5, fontsize as f32 * 1.2)
+    }
+
+    // Only reads the image header, the pixels are never loaded
+    fn texture_size(&mut self, sprite: &str) -> Vec2<f32> {
+        let assets = &self.assets;
+        let overrides = &self.sprite_overrides;
+        *self.texture_sizes.entry(sprite.to_string()).or_insert_with(|| {
+            let path = overrides.get(&sprite.replace("\\", "/")).cloned()
+                .unwrap_or_else(|| assets.join("sprites").join(sprite.replace("\\", "/")));
+            match image::image_dimensions(&path) {
+                Ok((width, height)) => Vec2::new(width as f32, height as f32),
+                Err(_) => Vec2::zero(),
+            }
+        })
+    }
+}
@@ -0,0 +1,280 @@
+const SAVE_FORMAT_VERSION: u32 = 2;
+const DEFAULT_SLOT: &str = "1";
+// How long play time can go unwritten before the save gets written just for it
+const PLAY_TIME_FLUSH_INTERVAL: Duration = Duration::from_secs(120);
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct SaveSlot {
+    pub data_values: BTreeMap<String, ScriptValue>,
+    pub last_night: i32,
+    // Seconds
+    pub play_time: u64,
+    pub timestamp: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveFile {
+    version: u32,
+    game_id: String,
+    #[serde(default)]
+    current_slot: String,
+    #[serde(default)]
+    slots: BTreeMap<String, SaveSlot>,
+    checksum: String,
+}
+
+pub struct SaveManager {
+    path: PathBuf,
+    game_id: String,
+    current_slot: String,
+    slots: BTreeMap<String, SaveSlot>,
+    // Play time is added to the current slot every time it gets written
+    last_flush: Instant,
+    logger: Logger,
+}
+
+impl SaveManager {
+    pub fn new(game_id: &str, logger: Logger) -> SaveManager {
+        // Game ids come from game.json, keep them from escaping the save folder
+        let path = Self::data_dir().join("FNaF-Engine-Rust").join(Self::sanitize(game_id)).join("save.json");
+        Self::at(path, game_id, logger)
+    }
+
+    // A save somewhere other than the player's data folder, scenario runs use this to keep away from real saves
+    pub fn at(path: PathBuf, game_id: &str, logger: Logger) -> SaveManager {
+        SaveManager {
+            path,
+            game_id: game_id.to_string(),
+            current_slot: DEFAULT_SLOT.to_string(),
+            slots: BTreeMap::new(),
+            last_flush: Instant::now(),
+            logger,
+        }
+    }
+
+    // The per-user data folder for whatever OS we're on
+    pub fn data_dir() -> PathBuf {
+        if cfg!(target_os = "windows") {
+            if let Some(appdata) = env::var_os("APPDATA") {
+                return PathBuf::from(appdata);
+            }
+        } else if cfg!(target_os = "macos") {
+            if let Some(home) = env::var_os("HOME") {
+                return PathBuf::from(home).join("Library").join("Application Support");
+            }
+        } else {
+            if let Some(data_home) = env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()) {
+                return PathBuf::from(data_home);
+            }
+            if let Some(home) = env::var_os("HOME") {
+                return PathBuf::from(home).join(".local").join("share");
+            }
+        }
+        PathBuf::from(".")
+    }
+
+    fn sanitize(name: &str) -> String {
+        name.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Night snapshots sit next to the save file, one per name
+    pub fn state_path(&self, name: &str) -> PathBuf {
+        self.path.with_file_name("states").join(format!("{}.json", Self::sanitize(name)))
+    }
+
+    pub fn play_time_due(&self) -> bool {
+        self.last_flush.elapsed() >= PLAY_TIME_FLUSH_INTERVAL
+    }
+
+    pub fn game_id(&self) -> &str {
+        &self.game_id
+    }
+
+    // Reads the save file and hands back the data values of the slot that was in use last
+    pub fn load(&mut self) -> HashMap<String, ScriptValue> {
+        self.last_flush = Instant::now();
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(_) => {
+                self.logger.log("SaveManager", "No save file found, starting fresh.");
+                return HashMap::new();
+            }
+        };
+
+        let save: SaveFile = match serde_json::from_str(&content) {
+            Ok(save) => save,
+            Err(e) => {
+                self.logger.log_error("SaveManager", format!("Save file is corrupted, ignoring it: {}", e).as_str());
+                self.backup_rejected();
+                return HashMap::new();
+            }
+        };
+
+        if save.game_id != self.game_id {
+            self.logger.log_error("SaveManager", format!("Save file belongs to '{}', not '{}', ignoring it.", save.game_id, self.game_id).as_str());
+            self.backup_rejected();
+            return HashMap::new();
+        }
+
+        if save.checksum != Self::checksum(&self.game_id, &(&save.current_slot, &save.slots)) {
+            self.logger.log_error("SaveManager", "Save file failed its integrity check, it was edited outside the game. Ignoring it.");
+            self.backup_rejected();
+            return HashMap::new();
+        }
+
+        self.slots = save.slots;
+        if !save.current_slot.is_empty() {
+            self.current_slot = save.current_slot;
+        }
+
+        let data_values = self.slot_data(&self.current_slot.clone());
+        self.logger.log("SaveManager", format!("Loaded slot '{}' with {} data values.", self.current_slot, data_values.len()).as_str());
+        data_values
+    }
+
+    // Copies a save that load refused next to it, the next write would replace it otherwise
+    fn backup_rejected(&self) {
+        let backup = self.path.with_extension(format!("bad-{}.json", Utc::now().format("%Y%m%d-%H%M%S")));
+        match fs::copy(&self.path, &backup) {
+            Ok(_) => self.logger.log_warn("SaveManager", format!("Kept the rejected save at {:?}", backup).as_str()),
+            Err(e) => self.logger.log_error("SaveManager", format!("Failed to back up the rejected save to {:?}: {}", backup, e).as_str()),
+        }
+    }
+
+    // Stores the data values in the current slot and writes the whole file. It goes to a temp file first
+    // and gets renamed over the old save, so a crash mid write can't eat the save.
+    pub fn save(&mut self, data_values: &HashMap<String, ScriptValue>) -> std::io::Result<()> {
+        let elapsed = self.last_flush.elapsed().as_secs();
+        // Only move the clock forward by whole seconds so the leftover isn't lost between writes
+        self.last_flush += Duration::from_secs(elapsed);
+
+        let slot = self.slots.entry(self.current_slot.clone()).or_default();
+        slot.data_values = data_values.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        slot.play_time += elapsed;
+        slot.timestamp = Utc::now().to_rfc3339();
+
+        self.write()
+    }
+
+    fn write(&self) -> std::io::Result<()> {
+        let save = SaveFile {
+            version: SAVE_FORMAT_VERSION,
+            game_id: self.game_id.clone(),
+            current_slot: self.current_slot.clone(),
+            slots: self.slots.clone(),
+            checksum: Self::checksum(&self.game_id, &(&self.current_slot, &self.slots)),
+        };
+        let json = serde_json::to_string_pretty(&save)?;
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temp_path = self.path.with_extension("json.tmp");
+        {
+            let mut file = File::create(&temp_path)?;
+            file.write_all(json.as_bytes())?;
+            file.sync_all()?;
+        }
+        fs::rename(&temp_path, &self.path)
+    }
+
+    pub fn delete(&self) -> std::io::Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn current_slot(&self) -> &str {
+        &self.current_slot
+    }
+
+    pub fn slot(&self, name: &str) -> Option<&SaveSlot> {
+        self.slots.get(name)
+    }
+
+    pub fn slot_names(&self) -> Vec<String> {
+        self.slots.keys().cloned().collect()
+    }
+
+    fn slot_data(&self, name: &str) -> HashMap<String, ScriptValue> {
+        match self.slots.get(name) {
+            Some(slot) => slot.data_values.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+            None => HashMap::new(),
+        }
+    }
+
+    // Saves the slot being left, then returns the data values of the one being switched to
+    pub fn select_slot(&mut self, name: &str, data_values: &HashMap<String, ScriptValue>) -> std::io::Result<HashMap<String, ScriptValue>> {
+        self.save(data_values)?;
+        self.current_slot = name.to_string();
+        self.slots.entry(name.to_string()).or_default();
+        self.write()?;
+        Ok(self.slot_data(name))
+    }
+
+    pub fn copy_slot(&mut self, from: &str, to: &str) -> std::io::Result<bool> {
+        let slot = match self.slots.get(from) {
+            Some(slot) => slot.clone(),
+            None => return Ok(false),
+        };
+        self.slots.insert(to.to_string(), slot);
+        self.write()?;
+        Ok(true)
+    }
+
+    pub fn erase_slot(&mut self, name: &str) -> std::io::Result<bool> {
+        if self.slots.remove(name).is_none() {
+            return Ok(false);
+        }
+        self.write()?;
+        Ok(true)
+    }
+
+    // %slot(current), %slot(count), or %slot(name:field) with last_night, play_time, timestamp or exists
+    pub fn slot_expression(&self, content: &str) -> Result<String, String> {
+        let content = content.trim();
+        match content {
+            "current" => return Ok(self.current_slot.clone()),
+            "count" => return Ok(self.slots.len().to_string()),
+            _ => {}
+        }
+        let (name, field) = content.rsplit_once(':').ok_or_else(|| format!("Unknown slot field '{}'.", content))?;
+        if field == "exists" {
+            return Ok(self.slots.contains_key(name).to_string());
+        }
+        let slot = self.slots.get(name).ok_or_else(|| format!("Save slot '{}' not found.", name))?;
+        match field {
+            "last_night" => Ok(slot.last_night.to_string()),
+            "play_time" => Ok(slot.play_time.to_string()),
+            "timestamp" => Ok(slot.timestamp.clone()),
+            _ => Err(format!("Unknown slot field '{}'.", field)),
+        }
+    }
+
+    pub fn set_last_night(&mut self, night: i32) {
+        self.slots.entry(self.current_slot.clone()).or_default().last_night = night;
+    }
+
+    // FNV-1a over the game id and the data. Stops players from casually editing their save, not a determined cheater.
+    fn checksum<T: Serialize>(game_id: &str, payload: &T) -> String {
+        let payload = serde_json::to_string(payload).unwrap_or_default();
+        format!("{:016x}", Self::fnv_hash(game_id.bytes().chain(std::iter::once(0)).chain(payload.bytes())))
+    }
+
+    // 64 bit FNV-1a, fast and stable across runs. Mod archives use it to name their unpacked folders.
+    pub fn fnv_hash<I: IntoIterator<Item = u8>>(bytes: I) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in bytes {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        hash
+    }
+}
@@ -0,0 +1,302 @@
+// Regression tests for game packages. Every <game>/scenarios/*.json is a scenario that gets played
+// headlessly with a fixed seed and a throwaway save, then checked against what it expects.
+//
+// {
+//   "name": "Night 3 with the left door shut",
+//   "seed": 42,
+//   "steps": [
+//     { "run": [{ "block": "setoff", "args": ["Office"] }, { "block": "start_night", "args": ["3"] }] },
+//     { "at": 200, "run": [{ "block": "close_door", "args": ["LeftDoor"] }] },
+//     { "until": { "value": "%game(time)", "op": ">=", "expected": 6 } }
+//   ],
+//   "expect": [
+//     { "value": "%game(power)", "op": ">", "expected": 0 },
+//     { "event": "on_night_end", "args": ["3"] },
+//     { "event": "on_power_out", "count": 0 }
+//   ]
+// }
+
+// Long enough to play a whole night out
+const SCENARIO_MAX_TICKS: u64 = TICKS_PER_HOUR as u64 * (LAST_HOUR as u64 + 1);
+
+#[derive(Deserialize)]
+pub struct Scenario {
+    #[serde(default)]
+    pub name: String,
+    // Scenarios always use a fixed seed, 0 unless they pick one
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default)]
+    pub steps: Vec<ScenarioStep>,
+    #[serde(default)]
+    pub expect: Vec<ScenarioCheck>,
+}
+
+// Steps go in order. `at` waits until that many ticks into the scenario, then the input is held
+// and the blocks run. `until` keeps ticking until the check passes, failing after max_ticks.
+#[derive(Deserialize)]
+pub struct ScenarioStep {
+    #[serde(default)]
+    pub at: Option<u64>,
+    #[serde(default)]
+    pub input: Option<InputState>,
+    #[serde(default)]
+    pub run: Vec<Code>,
+    #[serde(default)]
+    pub until: Option<ScenarioCheck>,
+    #[serde(default = "scenario_max_ticks")]
+    pub max_ticks: u64,
+}
+
+fn scenario_max_ticks() -> u64 {
+    SCENARIO_MAX_TICKS
+}
+
+// Either an expression compared with compare_values' operators, or an event that has to have fired.
+// Event args only need to match as far as they're given. Without a count the event has to fire at least once.
+#[derive(Deserialize)]
+pub struct ScenarioCheck {
+    #[serde(default)]
+    pub value: String,
+    #[serde(default = "scenario_default_op")]
+    pub op: String,
+    #[serde(default)]
+    pub expected: Value,
+    #[serde(default)]
+    pub event: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub count: Option<usize>,
+}
+
+fn scenario_default_op() -> String {
+    String::from("==")
+}
+
+pub struct ScenarioResult {
+    pub name: String,
+    pub failures: Vec<String>,
+    pub ticks: u64,
+}
+
+impl ScenarioResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+pub struct ScenarioRunner;
+
+impl ScenarioRunner {
+    pub fn run(assets: &PathBuf, scenario: &Scenario) -> ScenarioResult {
+        // Scenarios get their own save so they start clean and never touch the player's
+        let save_dir = env::temp_dir().join("FNaF-Engine-Rust-scenarios").join(SaveManager::sanitize(&scenario.name));
+        let _ = fs::remove_dir_all(&save_dir);
+
+        let seed = scenario.seed;
+        let mut game = HeadlessGame::new_with(assets.clone(), |engine| {
+            engine.save = SaveManager::at(save_dir.join("save.json"), &engine.game.game_info.id, engine.logger.clone());
+            engine.seed = seed;
+            engine.rng = StdRng::seed_from_u64(seed);
+            // --replay and --record belong to the run the scenarios were started from
+            engine.input = InputManager::new(&engine.game.actions);
+        });
+        game.eventmanager.start_event_log();
+
+        let mut result = ScenarioResult { name: scenario.name.clone(), failures: Vec::new(), ticks: 0 };
+        for (number, step) in scenario.steps.iter().enumerate() {
+            if let Some(at) = step.at {
+                while result.ticks < at {
+                    game.step();
+                    result.ticks += 1;
+                }
+            }
+            if let Some(input) = &step.input {
+                game.inject_input(input);
+            }
+            if !step.run.is_empty() {
+                if let BlockFlow::Wait(ticks, rest) = game.eventmanager.run_blocks(&mut game.engine, &step.run) {
+                    game.eventmanager.schedule(String::new(), ticks, None, rest);
+                }
+            }
+            if let Some(check) = &step.until {
+                let mut waited = 0;
+                let mut outcome = Self::check(&mut game, check);
+                while outcome.is_err() && waited < step.max_ticks {
+                    game.step();
+                    result.ticks += 1;
+                    waited += 1;
+                    outcome = Self::check(&mut game, check);
+                }
+                if let Err(e) = outcome {
+                    result.failures.push(format!("step {}: still not there after {} ticks, {}", number + 1, step.max_ticks, e));
+                    break;
+                }
+            }
+        }
+
+        // Checking the end state of a scenario that never got there would only pile on noise
+        if result.passed() {
+            for check in &scenario.expect {
+                if let Err(e) = Self::check(&mut game, check) {
+                    result.failures.push(e);
+                }
+            }
+        }
+
+        let _ = fs::remove_dir_all(&save_dir);
+        result
+    }
+
+    fn check(game: &mut HeadlessGame, check: &ScenarioCheck) -> Result<(), String> {
+        if !check.event.is_empty() {
+            let fired = game.eventmanager.event_log().iter()
+                .filter(|event| event.name == check.event
+                    && event.args.len() >= check.args.len()
+                    && check.args.iter().zip(&event.args).all(|(expected, actual)| expected == actual))
+                .count();
+            let label = if check.args.is_empty() { check.event.clone() } else { format!("{} {:?}", check.event, check.args) };
+            return match check.count {
+                Some(count) if fired != count => Err(format!("{} fired {} times, expected {}", label, fired, count)),
+                None if fired == 0 => Err(format!("{} never fired", label)),
+                _ => Ok(()),
+            };
+        }
+        if check.value.is_empty() {
+            return Err("check needs a value or an event".to_string());
+        }
+
+        let expected = match &check.expected {
+            Value::String(s) => s.clone(),
+            Value::Null => String::new(),
+            other => other.to_string(),
+        };
+        let actual = game.eventmanager.get_expr(&check.value, &mut game.engine);
+        let expected = game.eventmanager.get_expr(&expected, &mut game.engine);
+        match ScriptValue::parse(&actual).compare(check.op.trim(), &ScriptValue::parse(&expected)) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(format!("{} is {}, expected {} {}", check.value, actual, check.op, expected)),
+            Err(e) => Err(format!("{}: {}", check.value, e)),
+        }
+    }
+
+    // --test-scenarios [--scenario <name>], runs the game's scenarios and reports. False if any failed.
+    pub fn run_cli() -> bool {
+        let filter = cli_arg("--scenario");
+        Self::run_all(&EngineData::find_assets(), filter.as_deref(), &Logger::new())
+    }
+
+    // A filter that matches no scenario fails too, otherwise a typo in --scenario would pass
+    pub fn run_all(assets: &PathBuf, filter: Option<&str>, logger: &Logger) -> bool {
+        let mut paths: Vec<PathBuf> = match fs::read_dir(assets.join("scenarios")) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().map_or(false, |ext| ext == "json"))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        paths.sort();
+        if paths.is_empty() {
+            logger.log_warn("Scenarios", format!("No scenarios found in {:?}.", assets.join("scenarios")).as_str());
+            return filter.is_none();
+        }
+
+        let mut results = Vec::new();
+        for path in paths {
+            let file_name = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+            let scenario = fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|content| serde_json::from_str::<Scenario>(&content).map_err(|e| e.to_string()));
+            let result = match scenario {
+                Ok(mut scenario) => {
+                    if scenario.name.is_empty() {
+                        scenario.name = file_name;
+                    }
+                    if filter.map_or(false, |filter| filter != scenario.name) {
+                        continue;
+                    }
+                    Self::run(assets, &scenario)
+                }
+                Err(e) => ScenarioResult { name: file_name, failures: vec![format!("can't read {:?}: {}", path, e)], ticks: 0 },
+            };
+
+            if result.passed() {
+                logger.log("Scenarios", format!("PASS {} ({} ticks)", result.name, result.ticks).as_str());
+            } else {
+                logger.log_error("Scenarios", format!("FAIL {} ({} ticks)", result.name, result.ticks).as_str());
+                for failure in &result.failures {
+                    logger.log_error("Scenarios", format!("    {}", failure).as_str());
+                }
+            }
+            results.push(result);
+        }
+
+        if let (Some(filter), true) = (filter, results.is_empty()) {
+            logger.log_error("Scenarios", format!("No scenario named '{}'.", filter).as_str());
+            return false;
+        }
+
+        let failed = results.iter().filter(|result| !result.passed()).count();
+        logger.log("Scenarios", format!("{} passed, {} failed.", results.len() - failed, failed).as_str());
+        failed == 0
+    }
+}
+
+#[cfg(test)]
+mod scenario_runner {
+    use super::*;
+
+    fn fixture_assets() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join("test_game")
+    }
+
+    fn fixture_scenario(name: &str) -> Scenario {
+        let path = fixture_assets().join("scenarios").join(format!("{}.json", name));
+        serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn a_whole_night_passes() {
+        let result = ScenarioRunner::run(&fixture_assets(), &fixture_scenario("first_night"));
+        assert_eq!(result.failures, Vec::<String>::new());
+        assert!(result.ticks >= (TICKS_PER_HOUR * LAST_HOUR) as u64);
+    }
+
+    #[test]
+    fn wrong_event_counts_fail() {
+        let result = ScenarioRunner::run(&fixture_assets(), &fixture_scenario("wrong_expectations"));
+        assert_eq!(result.failures, vec![
+            "on_hour fired 1 times, expected 2".to_string(),
+            "on_night_end [\"2\"] never fired".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn until_gives_up_after_max_ticks() {
+        let scenario: Scenario = serde_json::from_str(r#"{
+            "name": "until_gives_up_after_max_ticks",
+            "steps": [
+                { "at": 5 },
+                { "until": { "value": "%game(time)", "op": ">=", "expected": 1 }, "max_ticks": 20 },
+                { "at": 1000 }
+            ],
+            "expect": [{ "event": "on_hour", "count": 0 }]
+        }"#).unwrap();
+        let result = ScenarioRunner::run(&fixture_assets(), &scenario);
+        // Stops at the step that failed and skips the end checks
+        assert_eq!(result.ticks, 25);
+        assert_eq!(result.failures.len(), 1);
+        assert!(result.failures[0].starts_with("step 2: still not there after 20 ticks"));
+    }
+
+    #[test]
+    fn a_filter_matching_nothing_fails() {
+        let logger = Logger::new();
+        assert!(!ScenarioRunner::run_all(&fixture_assets(), Some("no_such_scenario"), &logger));
+        // A game without scenarios is fine, asking it for one isn't
+        let no_scenarios = fixture_assets().join("no_such_game");
+        assert!(ScenarioRunner::run_all(&no_scenarios, None, &logger));
+        assert!(!ScenarioRunner::run_all(&no_scenarios, Some("first_night"), &logger));
+    }
+}
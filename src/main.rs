@@ -13,6 +13,8 @@ use std::hash::Hash;
 use std::path::{Path, PathBuf};
 use std::string::String;
 use std::time::Instant;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use tetra::input::Key::P;
 use tetra::time::Timestep;
 use tetra::{audio, Context, ContextBuilder, Event, State, TetraError};
@@ -28,18 +30,35 @@ include!("Loaders/GameLoader.rs");
 include!("Utils/SmartFPS.rs");
 include!("Loaders/AssetLoader.rs");
 include!("Loaders/PluginManager.rs");
+include!("Loaders/ExtensionRegistry.rs");
 include!("Renderers/MenuRenderer.rs");
 //include!("Loaders/FontLoader.rs");
 include!("Logic/Game/MenuManager.rs");
 include!("Logic/Scripting/EventManager.rs");
 include!("Logic/Scripting/LuaBindings.rs");
 include!("Logic/Game/OfficeManager.rs");
+include!("Logic/Game/MinigameManager.rs");
+include!("Logic/Game/Animatronics.rs");
+include!("Logic/Game/NightManager.rs");
 include!("Renderers/OfficeRenderer.rs");
+include!("Renderers/MinigameRenderer.rs");
 include!("Logic/Game/Types/OfficeData.rs");
+include!("Logic/Game/NightSnapshot.rs");
 include!("Logic/Game/Types/Button.rs");
 include!("Logic/Game/TickManager.rs");
+include!("Logic/Game/SceneManager.rs");
+include!("Logic/Game/InputManager.rs");
+include!("Logic/Game/InputRecorder.rs");
 include!("Logic/Game/AudioManager.rs");
 include!("Utils/CrashHandler.rs");
+// Only the benchmark test uses it
+#[cfg(test)]
+include!("Utils/EventBenchmark.rs");
+include!("Utils/SaveManager.rs");
+include!("Utils/ModLoader.rs");
+include!("Utils/Platform.rs");
+include!("Utils/HeadlessRunner.rs");
+include!("Utils/ScenarioRunner.rs");
 include!("Logic/Game/Types/RevAnimation.rs");
 pub struct CacheData {
     textures: HashMap<String, Texture>,
@@ -63,12 +82,20 @@ pub struct EngineData {
     pub stopwatch: Instant,
     pub game: Game,
     pub assets: PathBuf,
-    pub scene: i32,
+    pub scenes: SceneManager,
     pub menumgr: MenuManager,
     pub officemgr: OfficeManager,
+    pub minigamemgr: MinigameManager,
     pub logger: Logger,
     pub buttons: HashMap<String, Button2D>,
-    pub clock: Arc<TickManager>,
+    pub clock: TickManager,
+    // Every random decision goes through this so a seed replays the same game
+    pub rng: StdRng,
+    pub seed: u64,
+    pub input: InputManager,
+    pub save: SaveManager,
+    pub mods: ModLoader,
+    pub extensions: ExtensionRegistry,
     pub audio: AudioManager,
     pub fullscreen: bool,
     pub show_mouse: bool,
@@ -76,16 +103,13 @@ pub struct EngineData {
     pub hovered: bool,
     pub disabled_clicks: Vec<bool>,
     pub needs_recache: bool,
+    // Set by the quit block, the window closes at the start of the next update
+    pub quit_requested: bool,
     pub camera: tetra::graphics::Camera,
 }
 
 impl CacheData {
     fn new(ctx: &mut Context, mut engine_data: &mut EngineData) -> tetra::Result<CacheData> {
-        if (engine_data.game.menus.contains_key("Warning")) {
-            engine_data.menumgr.curmenu = "Warning".parse().unwrap();
-        } else {
-            engine_data.menumgr.curmenu = "Main".parse().unwrap()
-        }
         let mut menus_cache = AssetLoader::load_menus(
             ctx,
             &engine_data.game,
@@ -122,37 +146,94 @@ impl CacheData {
 
 impl EngineData {
     pub fn new(ctx: &mut Context) -> tetra::Result<EngineData> {
-        let menumgr = MenuManager::new();
+        let assets = Self::find_assets();
+        let mods = ModLoader::load(&assets);
+        let mut audio = TetraAudio::new();
+        audio.load_audio_assets(ctx, &mods.asset_dirs(&assets, "sounds"));
+        Ok(Self::load(assets, mods, Box::new(audio)))
+    }
+
+    // --game <dir> points at a game folder, otherwise it's the assets folder next to the build
+    pub fn find_assets() -> PathBuf {
+        match cli_arg("--game") {
+            Some(dir) => PathBuf::from(dir),
+            None => find_folder::Search::ParentsThenKids(3, 3)
+                .for_folder("target/debug/assets")
+                .unwrap(),
+        }
+    }
+
+    // Everything but the window, shared by the real game and headless runs
+    // Mods are found first since the audio backend needs their sounds before this runs
+    pub fn load(assets: PathBuf, mut mods: ModLoader, audio_backend: Box<dyn AudioBackend>) -> EngineData {
+        let mut menumgr = MenuManager::new();
         let officemgr = OfficeManager::new();
-        let clock = Arc::new(TickManager::new());
-        let mut audio = AudioManager::new();
+        let clock = TickManager::new();
+        let audio = AudioManager::new(audio_backend);
 
         let logger = Logger::new();
         logger.draw_splash();
 
-        let assets = find_folder::Search::ParentsThenKids(3, 3)
-            .for_folder("target/debug/assets")
-            .unwrap();
-        let game = GameLoader::Load(&assets.join("game.json").to_str().unwrap());
+        let game = mods.load_game(&assets);
+        mods.report(&logger);
+        let extensions = ExtensionRegistry::load(&game.loaded_extensions, &logger);
+        if (game.menus.contains_key("Warning")) {
+            menumgr.curmenu = "Warning".parse().unwrap();
+        } else {
+            menumgr.curmenu = "Main".parse().unwrap()
+        }
+        let mut input = InputManager::new(&game.actions);
+        let save = SaveManager::new(&game.game_info.id, logger.clone());
+
+        // --seed <number> replays a run, otherwise pick one and log it so the run can be replayed later
+        let mut seed = cli_arg("--seed")
+            .and_then(|seed| seed.parse::<u64>().ok())
+            .unwrap_or_else(rand::random::<u64>);
+
+        // --replay <file> plays back a recording with the seed it was made with
+        if let Some(path) = cli_arg("--replay") {
+            match InputReplay::load(Path::new(&path)) {
+                Ok(replay) if replay.header.game_id != game.game_info.id => {
+                    logger.log_error("Engine", format!("Recording {} was made with '{}', not '{}'.", path, replay.header.game_id, game.game_info.id).as_str());
+                }
+                Ok(replay) => {
+                    logger.log("Engine", format!("Replaying {}", path).as_str());
+                    seed = replay.header.seed;
+                    input.start_replay(replay);
+                }
+                Err(e) => logger.log_error("Engine", e.as_str()),
+            }
+        }
+        logger.log("Engine", format!("RNG seed: {}", seed).as_str());
+
+        // --record <file> writes every tick's input so the run can be replayed, it starts in start_scripts
+        if let Some(path) = cli_arg("--record") {
+            input.record_to(PathBuf::from(path));
+        }
 
         let fps = SmartFPS::new(5);
         let stopwatch = Instant::now();
 
         let disabled_clicks = vec![false, false, false, false];
 
-        audio.load_audio_assets(ctx, &assets);
-
-        Ok(EngineData {
+        EngineData {
             fps,
             stopwatch,
             game,
             assets,
-            scene: 0,
+            scenes: SceneManager::new(Rc::new(MenuScene::new(&menumgr.curmenu))),
             menumgr,
             officemgr,
+            minigamemgr: MinigameManager::new(),
             logger,
             buttons: HashMap::new(),
             clock,
+            rng: StdRng::seed_from_u64(seed),
+            seed,
+            input,
+            save,
+            mods,
+            extensions,
             audio,
             fullscreen: false,
             show_mouse: true,
@@ -160,9 +241,75 @@ impl EngineData {
             hovered: false,
             disabled_clicks,
             needs_recache: false,
+            quit_requested: false,
             camera: graphics::Camera::new(1280.0, 720.0),
 
-        })
+        }
+    }
+
+    // Loads the save and starts the global and first menu scripts
+    pub fn start_scripts(&mut self) -> EventManager {
+        let mut eventmanager = EventManager::new(self.logger.clone());
+        let data_values = self.load_save();
+        // The header keeps the save the recording started from
+        if let Some(path) = self.input.take_record_path() {
+            let header = RecordingHeader {
+                version: RECORDING_FORMAT_VERSION,
+                game_id: self.game.game_info.id.clone(),
+                seed: self.seed,
+                save: fs::read_to_string(self.save.path()).ok(),
+                data_values: data_values.clone(),
+            };
+            match InputRecorder::create(&path, &header) {
+                Ok(recorder) => {
+                    self.logger.log("Engine", format!("Recording input to {:?}", path).as_str());
+                    self.input.start_recording(recorder);
+                }
+                Err(e) => self.logger.log_error("Engine", format!("Failed to create recording {:?}: {}", path, e).as_str()),
+            }
+        }
+        eventmanager.load_data_values(data_values);
+        self.extensions.install_code_blocks(&mut eventmanager);
+
+        for (path, code) in &self.game.global_scripts {
+            self.logger.log("Event Manager", format!("Starting Global Script {}", path).as_str());
+            eventmanager.run_script(code, ListenerScope::Global);
+        }
+        eventmanager.run_script(&self.game.menus[&self.menumgr.curmenu].code, ListenerScope::Menu(self.menumgr.curmenu.clone()));
+
+        let global_lua = self.game.lua_scripts.global.clone();
+        eventmanager.run_lua_scripts(&global_lua, ListenerScope::Global, self);
+        let menu_lua = self.game.lua_scripts.menus.get(&self.menumgr.curmenu).cloned().unwrap_or_default();
+        eventmanager.run_lua_scripts(&menu_lua, ListenerScope::Menu(self.menumgr.curmenu.clone()), self);
+        eventmanager
+    }
+
+    pub fn recache_buttons(&mut self, platform: &mut dyn Platform, event_manager: &mut EventManager) {
+        SceneManager::recache_buttons(platform, self, event_manager);
+    }
+
+    // A replay starts from the save in its recording. It gets its own copy of the file so playing it back
+    // can't write over the player's save.
+    fn load_save(&mut self) -> HashMap<String, ScriptValue> {
+        // Version 1 recordings didn't keep the save, they play back on whatever save is there
+        let header = match self.input.replay_header() {
+            Some(header) if header.version >= 2 => header.clone(),
+            _ => return self.save.load(),
+        };
+        let path = self.save.path().with_file_name("replay_save.json");
+        let copied = match &header.save {
+            Some(content) => fs::create_dir_all(path.parent().unwrap_or(Path::new("."))).and_then(|_| fs::write(&path, content)),
+            None => match fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            },
+        };
+        if let Err(e) = copied {
+            self.logger.log_error("Engine", format!("Failed to set up the replay's save at {:?}: {}", path, e).as_str());
+        }
+        self.save = SaveManager::at(path, &self.game.game_info.id, self.logger.clone());
+        self.save.load();
+        header.data_values
     }
 }
 
@@ -171,12 +318,20 @@ impl GameState {
         let scaler = ScreenScaler::with_window_size(ctx, 1280, 720, ScalingMode::ShowAllPixelPerfect)?;
         let mut engine = EngineData::new(ctx)?;
         let mut cache = CacheData::new(ctx, &mut engine)?;
-        let mut eventmanager = EventManager::new(engine.logger.clone());
+        let mut eventmanager = engine.start_scripts();
 
-        eventmanager.run_script(&engine.game.menus[&engine.menumgr.curmenu].code);
-        MenuManager::recache_buttons(ctx, &mut engine, &mut eventmanager, &mut cache);
+        // Native plugins sit in the game's plugins folder, built for this platform
+        let mut plugin_manager = PluginManager::new(engine.logger.clone());
+        unsafe {
+            plugin_manager.load_plugins(&engine.assets.join("plugins"));
+        }
+        plugin_manager.initialize_plugins(&mut engine, &mut eventmanager);
+        let logger = engine.logger.clone();
+        engine.extensions.check_plugins(&plugin_manager.plugin_names(), &logger);
 
-        engine.clock.clone().start();
+        // After the plugins, they can add the element types the first menu uses
+        engine.recache_buttons(&mut TetraPlatform { ctx: &mut *ctx, cache: &mut cache }, &mut eventmanager);
+        engine.clock.start();
 
         Ok(GameState {
             screen_scaler: scaler,
@@ -184,7 +339,7 @@ impl GameState {
             engine,
             eventmanager,
             firstrun: true,
-            plugin_manager: PluginManager::new()
+            plugin_manager
         })
     }
 }
@@ -192,17 +347,12 @@ impl GameState {
 impl State for GameState {
     fn draw(&mut self, ctx: &mut Context) -> tetra::Result {
         graphics::set_canvas(ctx, self.screen_scaler.canvas());
-        match self.engine.scene {
-            0 => {
-                if !self.engine.needs_recache {
-                    MenuRenderer::render(ctx, &mut self.engine, &mut self.cache)?;
-                }
-            }
-            1 => {
-                OfficeRenderer::render(ctx, &mut self.engine, &mut self.cache, tetra::time::get_delta_time(ctx))?;
-            }
-            _ => {}
-        }
+        // Once here, the renderers draw over what the plugins put below them
+        graphics::clear(ctx, Color::BLACK);
+        self.plugin_manager.draw_plugins(ctx, &mut self.engine, &mut self.eventmanager, &mut self.cache, PLUGIN_LAYER_BELOW)?;
+        SceneManager::draw(ctx, &mut self.engine, &mut self.cache)?;
+        self.plugin_manager.draw_plugins(ctx, &mut self.engine, &mut self.eventmanager, &mut self.cache, PLUGIN_LAYER_ABOVE)?;
+        SceneManager::draw_fade(ctx, &self.engine)?;
 
         graphics::reset_canvas(ctx);
         graphics::clear(ctx, Color::BLACK);
@@ -219,33 +369,22 @@ impl State for GameState {
         //    plugin_manager.initialize_plugins();
         //    self.firstrun = false
     //    }
-        self.plugin_manager.update_plugins(ctx);
+        if self.engine.quit_requested {
+            self.eventmanager.flush_dirty_data(&mut self.engine);
+            self.plugin_manager.shutdown_plugins();
+            window::quit(ctx);
+            return Ok(());
+        }
+        self.plugin_manager.update_plugins(&mut self.engine, &mut self.eventmanager, tetra::time::get_delta_time(ctx).as_secs_f64());
         if self.engine.needs_recache {
             self.engine.needs_recache = false;
-            match self.engine.scene {
-                0 => {
-                    MenuManager::recache_buttons(
-                        ctx,
-                        &mut self.engine,
-                        &mut self.eventmanager,
-                        &mut self.cache,
-                    );
-                }
-                1 => {
-                    OfficeManager::recache_buttons(
-                        ctx,
-                        &mut self.engine,
-                        &mut self.eventmanager,
-                        &mut self.cache,
-                    );
-                }
-                _ => {}
-            }
+            self.engine.recache_buttons(&mut TetraPlatform { ctx: &mut *ctx, cache: &mut self.cache }, &mut self.eventmanager);
         }
 
-        if *self.engine.clock.ticked.lock().unwrap() {
-            ScriptingAPI::TickEvents(&mut self.engine, &mut self.eventmanager, ctx).expect("FATAL: Failed to Tick Events.");
-            *self.engine.clock.ticked.lock().unwrap() = false;
+        self.engine.input.poll(ctx);
+        self.engine.clock.advance(tetra::time::get_delta_time(ctx));
+        while self.engine.clock.next_tick() {
+            ScriptingAPI::TickEvents(&mut self.engine, &mut self.eventmanager).expect("FATAL: Failed to Tick Events.");
         }
 
         if self.engine.update_context {
@@ -254,15 +393,7 @@ impl State for GameState {
             self.engine.update_context = false;
         }
 
-        match self.engine.scene {
-            0 => {
-                MenuManager::update(ctx, self)?;
-            }
-            1 => {
-                OfficeManager::update(ctx, self)?;
-            }
-            _ => {}
-        }
+        SceneManager::update(ctx, self)?;
 
         //plugin_manager.update_plugins(ctx, self);
 
@@ -279,14 +410,30 @@ impl State for GameState {
     }
 }
 
+// Closing the window skips the quit block, anything unsaved still gets written
+impl Drop for GameState {
+    fn drop(&mut self) {
+        self.eventmanager.flush_dirty_data(&mut self.engine);
+    }
+}
+
+// The value after a command line flag, e.g. cli_arg("--seed") for "--seed 42"
+fn cli_arg(name: &str) -> Option<String> {
+    let args: Vec<String> = env::args().collect();
+    args.iter().position(|arg| arg == name).and_then(|i| args.get(i + 1)).cloned()
+}
+
 #[tokio::main]
 async fn main() -> tetra::Result {
     //let crashhandler = CrashHandler::new();
-    let mut plugin_manager = PluginManager::new();
-    unsafe {
-        plugin_manager.load_plugin("G:\\actually repos\\FNaF-Engine-Rust\\src\\PluginAPI\\Builtin-Plugins\\FNaF-World-Plugin\\target\\debug\\FNaF_World_Plugin.dll");
+    if env::args().any(|arg| arg == "--headless") {
+        HeadlessGame::run_cli();
+        return Ok(());
+    }
+    if env::args().any(|arg| arg == "--test-scenarios") {
+        let passed = ScenarioRunner::run_cli();
+        process::exit(if passed { 0 } else { 1 });
     }
-    plugin_manager.initialize_plugins();
     ContextBuilder::new("FNaF Engine: Rust", 1280, 720)
         .fps_limit(false)
         .show_mouse(true)
@@ -294,10 +441,5 @@ async fn main() -> tetra::Result {
         .timestep(Timestep::Variable)
         .high_dpi(true)
         .build()?
-        .run(|ctx| {
-            let mut game_state = GameState::new(ctx)?;
-
-            game_state.plugin_manager = plugin_manager;
-            Ok(game_state)
-        })
+        .run(GameState::new)
 }